@@ -0,0 +1,71 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::path::PathBuf;
+
+use abi::{FaultInfo, FaultSource, SchedState, TaskState};
+use kern::arch::Finish;
+use kern::task::Task;
+
+/// How long the smoke test runs, in ticks (milliseconds): long enough for
+/// `pong` to have toggled LEDs a few times, and halfway between its timers,
+/// so that it's sure to be waiting.
+const SMOKE_TICKS: u64 = 2_250;
+
+// Task indices, in app.toml order.
+const PONG: usize = 0;
+const PING: usize = 1;
+const USER_LEDS: usize = 2;
+
+fn main() {
+    let mut args = std::env::args().skip(1).collect::<Vec<_>>();
+    let smoke = args.first().map(String::as_str) == Some("--smoke");
+    if smoke {
+        args.remove(0);
+    }
+    let dir = match args.as_slice() {
+        [dir] => PathBuf::from(dir),
+        _ => {
+            eprintln!("usage: demo-hosted [--smoke] DIST-DIR");
+            std::process::exit(2);
+        }
+    };
+
+    let finish = if smoke {
+        Some(Finish {
+            ticks: SMOKE_TICKS,
+            check: smoke_check,
+        })
+    } else {
+        None
+    };
+    kern::arch::boot(&dir, finish)
+}
+
+/// Checks that the application got where it should have by the end of the
+/// smoke test.
+fn smoke_check(tasks: &[Task]) -> bool {
+    // ping faults on purpose, by reading address zero, but only once pong has
+    // replied to it, so this says that IPC works -- and that faults are caught.
+    let ping_ok = matches!(
+        tasks[PING].state(),
+        TaskState::Faulted {
+            fault: FaultInfo::MemoryAccess {
+                address: Some(0),
+                source: FaultSource::User,
+            },
+            ..
+        }
+    );
+    // pong and user_leds, meanwhile, should be waiting for their next
+    // message, having come to no harm in sending or receiving them.
+    let waiting = |i: usize| {
+        matches!(
+            tasks[i].state(),
+            TaskState::Healthy(SchedState::InRecv(None))
+        )
+    };
+
+    ping_ok && waiting(PONG) && waiting(USER_LEDS)
+}
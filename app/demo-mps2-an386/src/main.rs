@@ -0,0 +1,40 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![no_std]
+#![no_main]
+
+// QEMU has no ITM, so semihosting is the only game in town for kernel logs
+// and panics.
+#[cfg(not(feature = "semihosting"))]
+compile_error!("Must have feature semihosting enabled");
+
+#[cfg(feature = "semihosting")]
+extern crate panic_semihosting; // requires QEMU's -semihosting
+
+use cortex_m_rt::entry;
+use kern::app::App;
+
+extern "C" {
+    static hubris_app_table: App;
+    static mut __sheap: u8;
+    static __eheap: u8;
+}
+
+#[entry]
+fn main() -> ! {
+    // QEMU models the MPS2 FPGA images as running at a fixed 25MHz.
+    const CYCLES_PER_MS: u32 = 25_000;
+
+    unsafe {
+        let heap_size =
+            (&__eheap as *const _ as usize) - (&__sheap as *const _ as usize);
+        kern::startup::start_kernel(
+            &hubris_app_table,
+            (&mut __sheap) as *mut _,
+            heap_size,
+            CYCLES_PER_MS,
+        )
+    }
+}
@@ -101,5 +101,8 @@ SECTIONS
     *(.ARM.exidx);
     *(.ARM.exidx.*);
     *(.ARM.extab.*);
+    /* The same, for hosted tasks */
+    *(.eh_frame);
+    *(.eh_frame_hdr);
   }
 }
@@ -50,3 +50,19 @@ pub fn config<T: DeserializeOwned>() -> Result<T> {
     println!("cargo:rerun-if-env-changed=HUBRIS_APP_CONFIG");
     Ok(rval)
 }
+
+///
+/// Pulls the task-specific configuration (that is, the `config` table of the
+/// task being built) for purposes of a build task.  Unlike the app-wide
+/// configuration, this is entirely optional:  if the task has no `config`
+/// table, this returns `Ok(None)`.  As with [`config`], `T` need only
+/// describe the parts of the configuration that the build task cares about.
+///
+pub fn task_config<T: DeserializeOwned>() -> Result<Option<T>> {
+    println!("cargo:rerun-if-env-changed=HUBRIS_TASK_CONFIG");
+    match env::var("HUBRIS_TASK_CONFIG") {
+        Ok(config) => Ok(Some(toml::from_slice(config.as_bytes())?)),
+        Err(env::VarError::NotPresent) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
@@ -0,0 +1,363 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Checks an image configuration for mistakes, without building anything.
+//!
+//! Most mistakes in an `app.toml` are otherwise only found part-way through
+//! `dist`, one at a time, or not until the image is running. This looks for all
+//! of them at once, and says where in the file each one is. `dist` runs the
+//! same checks before it builds anything.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+
+use crate::graph;
+use crate::Config;
+
+pub fn run(cfg: &Path) -> Result<()> {
+    let text = std::fs::read_to_string(cfg)?;
+    let toml: Config = toml::from_str(&text)
+        .with_context(|| format!("can't parse {}", cfg.display()))?;
+
+    report(cfg, &check(&toml, &text))?;
+    println!("{}: no problems found", cfg.display());
+    Ok(())
+}
+
+/// Prints `problems` with configuration `cfg`, if there are any, and returns
+/// an error if so.
+pub(crate) fn report(cfg: &Path, problems: &[Problem]) -> Result<()> {
+    for problem in problems {
+        match problem.line {
+            Some(line) => {
+                eprintln!("{}:{}: {}", cfg.display(), line, problem.message)
+            }
+            None => eprintln!("{}: {}", cfg.display(), problem.message),
+        }
+    }
+    if !problems.is_empty() {
+        bail!("{}: found {} problem(s)", cfg.display(), problems.len());
+    }
+    Ok(())
+}
+
+/// Something wrong with the configuration.
+#[derive(Clone, Debug)]
+pub(crate) struct Problem {
+    /// Line of the file it's on, counting from 1, if we can tell.
+    pub(crate) line: Option<usize>,
+    pub(crate) message: String,
+}
+
+/// Looks for problems in configuration `toml`, read from `text`.
+pub(crate) fn check(toml: &Config, text: &str) -> Vec<Problem> {
+    let mut c = Checker {
+        lines: text.lines().collect(),
+        problems: vec![],
+    };
+    check_memories(&mut c, toml);
+    check_tasks(&mut c, toml);
+    check_interrupts(&mut c, toml);
+    check_locks(&mut c, toml);
+
+    // These checks are shared with `dist`, and stop at the first problem.
+    if let Err(e) = crate::dist::check_start_order(&toml.tasks) {
+        c.report(None, None, e.to_string());
+    }
+    if let Err(e) = crate::dist::allocate(toml) {
+        c.report(None, None, e.to_string());
+    }
+
+    c.problems
+}
+
+struct Checker<'a> {
+    lines: Vec<&'a str>,
+    problems: Vec<Problem>,
+}
+
+impl Checker<'_> {
+    /// Records a problem with `key` in table `table`.
+    fn report(&mut self, table: Option<&str>, key: Option<&str>, msg: String) {
+        let line = table.and_then(|t| self.line_of(t, key));
+        self.problems.push(Problem { line, message: msg });
+    }
+
+    /// Finds the line that sets `key` in table `table` (either as `key = ...`
+    /// or as a table of its own), or failing that, the line that starts the
+    /// table. This only understands tables written out in the usual way, with
+    /// `[table]` headers, but that's how every `app.toml` is written.
+    fn line_of(&self, table: &str, key: Option<&str>) -> Option<usize> {
+        let mut current = String::new();
+        let mut header = None;
+        for (i, line) in self.lines.iter().enumerate() {
+            let line = line.trim();
+            if line.starts_with('[') {
+                current =
+                    line.trim_matches(|c| c == '[' || c == ']').to_string();
+                if current == table {
+                    header = Some(i + 1);
+                }
+                if let Some(key) = key {
+                    if current == format!("{}.{}", table, key) {
+                        return Some(i + 1);
+                    }
+                }
+                continue;
+            }
+            if let Some(key) = key {
+                let is_key = line
+                    .strip_prefix(key)
+                    .map_or(false, |rest| rest.trim_start().starts_with('='));
+                if current == table && is_key {
+                    return Some(i + 1);
+                }
+            }
+        }
+        header
+    }
+}
+
+/// Checks that everything that asks for memory names memory that exists.
+fn check_memories(c: &mut Checker, toml: &Config) {
+    for mem in toml.kernel.requires.keys() {
+        if !toml.outputs.contains_key(mem) {
+            c.report(
+                Some("kernel"),
+                Some("requires"),
+                format!("kernel: requires unknown memory {}", mem),
+            );
+        }
+    }
+    for (name, task) in &toml.tasks {
+        let table = format!("tasks.{}", name);
+        for mem in task.requires.keys() {
+            if !toml.outputs.contains_key(mem) {
+                c.report(
+                    Some(&table),
+                    Some("requires"),
+                    format!("task {}: requires unknown memory {}", name, mem),
+                );
+            }
+        }
+        for mem in ["flash", "ram"] {
+            if !task.requires.contains_key(mem) {
+                c.report(
+                    Some(&table),
+                    Some("requires"),
+                    format!("task {}: doesn't require any {}", name, mem),
+                );
+            }
+        }
+        if task.stacksize.or(toml.stacksize).is_none() {
+            c.report(
+                Some(&table),
+                None,
+                format!(
+                    "task {}: no stack size specified and there is no default",
+                    name
+                ),
+            );
+        }
+    }
+}
+
+/// Checks what each task refers to: the tasks in its slots and `sends-to`,
+/// and the peripherals it uses.
+fn check_tasks(c: &mut Checker, toml: &Config) {
+    // The kernel keeps some tables of tasks as bitmaps of this size.
+    let max_tasks = 32 * abi::IPC_ALLOW_WORDS;
+    if toml.tasks.len() > max_tasks {
+        c.report(
+            None,
+            None,
+            format!(
+                "image has {} tasks, but the kernel supports at most {}",
+                toml.tasks.len(),
+                max_tasks
+            ),
+        );
+    }
+
+    for (name, task) in &toml.tasks {
+        let table = format!("tasks.{}", name);
+
+        for (slot, target) in &task.task_slots {
+            let server = match toml.tasks.get(target) {
+                Some(server) => server,
+                None => {
+                    c.report(
+                        Some(&table),
+                        Some("task-slots"),
+                        format!(
+                            "task {}: task slot {} names unknown task {}",
+                            name, slot, target
+                        ),
+                    );
+                    continue;
+                }
+            };
+
+            // Sending to a server of equal priority is only warned about, by
+            // `cargo xtask graph`.
+            if graph::Problem::of_call(task.priority, server.priority)
+                == Some(graph::Problem::LowerPriority)
+            {
+                c.report(
+                    Some(&table),
+                    Some("task-slots"),
+                    format!(
+                        "task {} (priority {}): task slot {} names {}, which \
+                         has lower priority {}; tasks should only send to \
+                         more important servers",
+                        name, task.priority, slot, target, server.priority
+                    ),
+                );
+            }
+
+            if let Some(sends_to) = &task.sends_to {
+                if !sends_to.contains(target) {
+                    c.report(
+                        Some(&table),
+                        Some("sends-to"),
+                        format!(
+                            "task {}: task slot {} names {}, which isn't in \
+                             sends-to",
+                            name, slot, target
+                        ),
+                    );
+                }
+            }
+        }
+
+        for peer in task.sends_to.iter().flatten() {
+            if !toml.tasks.contains_key(peer) {
+                c.report(
+                    Some(&table),
+                    Some("sends-to"),
+                    format!(
+                        "task {}: sends-to names unknown task {}",
+                        name, peer
+                    ),
+                );
+            }
+        }
+
+        for peripheral in &task.uses {
+            if !toml.peripherals.contains_key(peripheral)
+                && !toml.extratext.contains_key(peripheral)
+            {
+                c.report(
+                    Some(&table),
+                    Some("uses"),
+                    format!(
+                        "task {}: uses unknown peripheral {}",
+                        name, peripheral
+                    ),
+                );
+            }
+        }
+    }
+}
+
+/// Checks that each interrupt goes to one task, on one notification bit, and
+/// that the supervisor's interrupts don't collide with its fault
+/// notification.
+fn check_interrupts(c: &mut Checker, toml: &Config) {
+    let fault_mask = toml.supervisor.as_ref().map(|s| s.notification);
+    if let Some(mask) = fault_mask {
+        if mask.count_ones() != 1 {
+            c.report(
+                Some("supervisor"),
+                Some("notification"),
+                format!(
+                    "supervisor: notification mask (0b{:b}) has {} bits set \
+                     (expected exactly one)",
+                    mask,
+                    mask.count_ones()
+                ),
+            );
+        }
+    }
+
+    let mut routed: BTreeMap<u32, &str> = BTreeMap::new();
+    for (i, (name, task)) in toml.tasks.iter().enumerate() {
+        let table = format!("tasks.{}", name);
+        for (irq_str, &notification) in &task.interrupts {
+            let irq = match irq_str.parse::<u32>() {
+                Ok(irq) => irq,
+                Err(_) => {
+                    c.report(
+                        Some(&table),
+                        Some("interrupts"),
+                        format!(
+                            "task {}: {} isn't an interrupt number",
+                            name, irq_str
+                        ),
+                    );
+                    continue;
+                }
+            };
+
+            if let Some(other) = routed.insert(irq, name) {
+                c.report(
+                    Some(&table),
+                    Some("interrupts"),
+                    format!(
+                        "task {}: IRQ {} is already routed to task {}",
+                        name, irq, other
+                    ),
+                );
+            }
+
+            if notification.count_ones() != 1 {
+                c.report(
+                    Some(&table),
+                    Some("interrupts"),
+                    format!(
+                        "task {}: IRQ {}: notification mask (0b{:b}) has {} \
+                         bits set (expected exactly one)",
+                        name,
+                        irq,
+                        notification,
+                        notification.count_ones()
+                    ),
+                );
+            }
+
+            if let Some(mask) = fault_mask {
+                if i == 0 && notification & mask != 0 {
+                    c.report(
+                        Some(&table),
+                        Some("interrupts"),
+                        format!(
+                            "task {}: IRQ {}: notification mask (0b{:b}) \
+                             collides with the supervisor's fault \
+                             notification (0b{:b})",
+                            name, irq, notification, mask
+                        ),
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Checks that locks are only used by tasks that exist.
+fn check_locks(c: &mut Checker, toml: &Config) {
+    for (name, lock) in &toml.locks {
+        let table = format!("locks.{}", name);
+        for user in &lock.users {
+            if !toml.tasks.contains_key(user) {
+                c.report(
+                    Some(&table),
+                    Some("users"),
+                    format!("lock {}: users names unknown task {}", name, user),
+                );
+            }
+        }
+    }
+}
@@ -2,10 +2,10 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use std::collections::hash_map::DefaultHasher;
-use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
 use std::fs::{self, File};
-use std::hash::Hasher;
 use std::io::{Read, Write};
 use std::ops::Range;
 use std::path::{Path, PathBuf};
@@ -14,11 +14,11 @@ use std::process::Command;
 use anyhow::{anyhow, bail, Context, Result};
 
 use indexmap::IndexMap;
-use path_slash::PathBufExt;
+use path_slash::{PathBufExt, PathExt};
 
 use crate::{
-    elf, task_slot, Config, LoadSegment, Output, Peripheral, Signing,
-    Supervisor, Task,
+    elf, task_slot, Config, LoadSegment, Lock, Output, Peripheral,
+    SharedRegion, Signing, Supervisor, Task,
 };
 
 use lpc55_sign::{crc_image, sign_ecc, signed_image};
@@ -28,6 +28,10 @@ use lpc55_sign::{crc_image, sign_ecc, signed_image};
 /// padded that a bit.
 const DEFAULT_KERNEL_STACK: u32 = 1024;
 
+/// The target for the kernel's hosted (simulation) backend, which runs the
+/// kernel and its tasks in a Linux process instead of on hardware.
+pub(crate) const HOSTED_TARGET: &str = "i686-unknown-linux-gnu";
+
 pub fn package(
     verbose: bool,
     edges: bool,
@@ -41,9 +45,17 @@ pub fn package(
     let cfg_contents = std::fs::read(&cfg)?;
     let toml: Config = toml::from_slice(&cfg_contents)?;
 
-    let mut hasher = DefaultHasher::new();
-    hasher.write(&cfg_contents);
-    let buildhash = hasher.finish();
+    // Catch what mistakes we can before spending any time building.
+    crate::check::report(
+        cfg,
+        &crate::check::check(&toml, &String::from_utf8_lossy(&cfg_contents)),
+    )?;
+
+    // Everything gets rebuilt from scratch if the configuration or the
+    // toolchain changes.
+    let toolchain = toolchain_version()?;
+    let buildhash =
+        sha256_hex(&[cfg_contents.as_slice(), toolchain.as_bytes()].concat());
     drop(cfg_contents);
 
     let mut out = PathBuf::from("target");
@@ -54,20 +66,8 @@ pub fn package(
 
     std::fs::create_dir_all(&out)?;
 
-    let rebuild = match std::fs::read(&buildstamp_file) {
-        Ok(contents) => {
-            if let Ok(contents) = std::str::from_utf8(&contents) {
-                if let Ok(cmp) = u64::from_str_radix(contents, 16) {
-                    buildhash != cmp
-                } else {
-                    println!("buildstamp file contents unknown; re-building.");
-                    true
-                }
-            } else {
-                println!("buildstamp file contents corrupt; re-building.");
-                true
-            }
-        }
+    let rebuild = match std::fs::read_to_string(&buildstamp_file) {
+        Ok(contents) => contents.trim() != buildhash,
         Err(_) => {
             println!("no buildstamp file found; re-building.");
             true
@@ -77,46 +77,40 @@ pub fn package(
     let mut src_dir = cfg.to_path_buf();
     src_dir.pop();
 
-    let mut memories = IndexMap::new();
-    for (name, out) in &toml.outputs {
-        if let Some(end) = out.address.checked_add(out.size) {
-            memories.insert(name.clone(), out.address..end);
-        } else {
-            eprintln!(
-                "output {}: address {:08x} size {:x} would overflow",
-                name, out.address, out.size
-            );
-            std::process::exit(1);
-        }
-    }
+    let memories = output_ranges(&toml)?;
     for (name, range) in &memories {
         println!("{} = {:x?}", name, range);
     }
-    let starting_memories = memories.clone();
+
+    let hosted = toml.target == HOSTED_TARGET;
 
     // Allocate memories.
-    let allocs = allocate_all(&toml.kernel, &toml.tasks, &mut memories)?;
+    let mpu = Mpu::for_target(&toml.target)?;
+    let allocs =
+        allocate_all(mpu, &toml.kernel, &toml.tasks, &toml.shared, &memories)?;
 
-    println!("Used:");
-    for (name, new_range) in &memories {
-        let orig_range = &starting_memories[name];
-        println!("{}: 0x{:x}", name, new_range.start - orig_range.start);
-    }
+    println!("Memory map:");
+    allocs.write_map(&mut std::io::stdout(), &memories)?;
 
     let mut infofile = File::create(out.join("allocations.txt"))?;
     writeln!(infofile, "kernel: {:#x?}", allocs.kernel)?;
     writeln!(infofile, "tasks: {:#x?}", allocs.tasks)?;
+    writeln!(infofile, "shared: {:#x?}", allocs.shared)?;
+    allocs.write_map(&mut infofile, &memories)?;
     drop(infofile);
 
     // Build each task.
     let task_names = toml.tasks.keys().cloned().collect::<Vec<_>>();
     let task_names = task_names.join(",");
+    let lock_names = toml.locks.keys().cloned().collect::<Vec<_>>();
+    let lock_names = lock_names.join(",");
+    let start_after = start_after_env(&toml.tasks);
     let mut all_output_sections = BTreeMap::default();
     let mut entry_points = HashMap::<_, _>::default();
 
     // if we need to rebuild, we should clean everything before we start building
     if rebuild {
-        println!("app.toml has changed; rebuilding all tasks");
+        println!("app.toml or toolchain has changed; rebuilding all tasks");
 
         cargo_clean(&toml.kernel.name, &toml.target)?;
 
@@ -138,7 +132,7 @@ pub fn package(
 
     // now that we're clean, update our buildstamp file; any failure to build
     // from here on need not trigger a clean
-    std::fs::write(&buildstamp_file, format!("{:x}", buildhash))?;
+    std::fs::write(&buildstamp_file, &buildhash)?;
     let mut shared_syms: Option<&[String]> = None;
 
     // If there is a bootloader, build it first as there may be dependencies
@@ -207,10 +201,13 @@ pub fn package(
             verbose,
             edges,
             &task_names,
+            &lock_names,
+            &start_after,
             &None,
             &shared_syms,
             &None,
             &toml.config,
+            false,
         )?;
 
         // Need a bootloader binary for signing
@@ -266,9 +263,19 @@ pub fn package(
         }
         let task_toml = &toml.tasks[name];
 
+        let shared = toml
+            .shared
+            .iter()
+            .filter(|(_, region)| region.access(name).is_some())
+            .map(|(region_name, _)| {
+                (region_name.as_str(), allocs.shared[region_name].clone())
+            })
+            .collect::<Vec<_>>();
+
         generate_task_linker_script(
             "memory.x",
             &allocs.tasks[name],
+            &shared,
             Some(&task_toml.sections),
             task_toml.stacksize.or(toml.stacksize).ok_or_else(|| {
                 anyhow!(
@@ -291,10 +298,13 @@ pub fn package(
             verbose,
             edges,
             &task_names,
+            &lock_names,
+            &start_after,
             &toml.secure,
             &shared_syms,
             &task_toml.config,
             &toml.config,
+            false,
         )
         .context(format!("failed to build {}", name))?;
 
@@ -321,28 +331,42 @@ pub fn package(
     }
 
     // Format the descriptors for the kernel build.
-    let mut descriptor_text = vec![];
-    for word in make_descriptors(
+    let descriptors = make_descriptors(
         &toml.target,
         &toml.tasks,
         &toml.peripherals,
         toml.supervisor.as_ref(),
         &allocs.tasks,
+        &toml.shared,
+        &allocs.shared,
         toml.stacksize,
         &toml.outputs,
         &entry_points,
         &toml.extratext,
-    )? {
-        descriptor_text.push(format!("LONG(0x{:08x});", word));
-    }
-    let descriptor_text = descriptor_text.join("\n");
-
-    generate_kernel_linker_script(
-        "memory.x",
-        &allocs.kernel,
-        toml.kernel.stacksize.unwrap_or(DEFAULT_KERNEL_STACK),
-        &descriptor_text,
+        &toml.locks,
     )?;
+    if hosted {
+        // The hosted kernel reads the descriptors at startup instead of having
+        // them linked in.
+        let bytes = descriptors
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .collect::<Vec<_>>();
+        std::fs::write(out.join("app.bin"), bytes)?;
+    } else {
+        let descriptor_text = descriptors
+            .iter()
+            .map(|word| format!("LONG(0x{:08x});", word))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        generate_kernel_linker_script(
+            "memory.x",
+            &allocs.kernel,
+            toml.kernel.stacksize.unwrap_or(DEFAULT_KERNEL_STACK),
+            &descriptor_text,
+        )?;
+    }
 
     // this one was for the tasks, but we don't want to use it for the kernel
     fs::remove_file("target/link.x")?;
@@ -358,12 +382,21 @@ pub fn package(
         verbose,
         edges,
         "",
+        "",
+        "",
         &toml.secure,
         &None,
         &None,
         &toml.config,
+        hosted,
     )?;
-    let (kentry, _) = load_elf(&out.join("kernel"), &mut all_output_sections)?;
+    // The hosted kernel is an ordinary program, which the OS loads for us, so
+    // it isn't part of the image.
+    let kentry = if hosted {
+        0
+    } else {
+        load_elf(&out.join("kernel"), &mut all_output_sections)?.0
+    };
 
     // Write a map file, because that seems nice.
     let mut mapfile = File::create(&out.join("map.txt"))?;
@@ -385,6 +418,22 @@ pub fn package(
     // Generate combined SREC, which is our source of truth for combined images.
     write_srec(&all_output_sections, kentry, &out.join("combined.srec"))?;
 
+    // The hosted kernel loads the image itself, from the SREC and the
+    // descriptors, so there's no use for other formats or an archive; tasks'
+    // symbols are all a debugger needs on top of the kernel's own.
+    if hosted {
+        let mut gdb_script = File::create(out.join("script.gdb"))?;
+        write_substitute_paths(&mut gdb_script)?;
+        for name in toml.tasks.keys() {
+            writeln!(
+                gdb_script,
+                "add-symbol-file {}",
+                out.join(name).to_slash().unwrap()
+            )?;
+        }
+        return Ok(());
+    }
+
     // Convert SREC to other formats for convenience.
     objcopy_translate_format(
         "srec",
@@ -484,6 +533,7 @@ pub fn package(
     }
 
     let mut gdb_script = File::create(out.join("script.gdb"))?;
+    write_substitute_paths(&mut gdb_script)?;
     writeln!(
         gdb_script,
         "add-symbol-file {}",
@@ -505,9 +555,14 @@ pub fn package(
     }
     drop(gdb_script);
 
-    // Bundle everything up into an archive.
-    let mut archive =
-        Archive::new(out.join(format!("build-{}.zip", toml.name)))?;
+    // Bundle everything up into an archive. Everything in it is stamped with
+    // the same time, so that building the same source with the same toolchain
+    // gives the same archive, byte for byte.
+    let epoch = source_date_epoch()?;
+    let mut archive = Archive::new(
+        out.join(format!("build-{}.zip", toml.name)),
+        zip_time(epoch)?,
+    )?;
 
     archive.text(
         "README.TXT",
@@ -519,7 +574,10 @@ pub fn package(
         - elf/ contains ELF images for all firmware components.\n\
         - elf/tasks/ contains each task by name.\n\
         - elf/kernel is the kernel.\n\
-        - img/ contains the final firmware images.\n",
+        - img/ contains the final firmware images.\n\
+        - manifest.json lists the SHA-256 of every other file, along with what\n  \
+          is needed to reproduce the build; `cargo xtask verify-archive`\n  \
+          checks it.\n",
     )?;
 
     let (git_rev, git_dirty) = get_git_status()?;
@@ -557,6 +615,9 @@ pub fn package(
         archive.copy(out.join(&name), img_dir.join(&name))?;
     }
 
+    let manifest =
+        archive.manifest(&toml.name, &git_rev, git_dirty, &toolchain, epoch)?;
+    println!("build id: {}", sha256_hex(manifest.as_bytes()));
     archive.finish()?;
 
     Ok(())
@@ -739,6 +800,7 @@ fn generate_bootloader_linker_script(
 fn generate_task_linker_script(
     name: &str,
     map: &BTreeMap<String, Range<u32>>,
+    shared: &[(&str, Range<u32>)],
     sections: Option<&IndexMap<String, String>>,
     stacksize: u32,
 ) -> Result<()> {
@@ -779,10 +841,25 @@ fn generate_task_linker_script(
 
         emit(&mut linkscr, &name, start, end - start)?;
     }
+    // Shared regions get a memory each, named so as not to collide with the
+    // task's own.
+    for (region, range) in shared {
+        emit(
+            &mut linkscr,
+            &shared_memory_name(region),
+            range.start,
+            range.end - range.start,
+        )?;
+    }
     writeln!(linkscr, "}}")?;
 
-    // The task may have defined additional section-to-memory mappings.
-    if let Some(map) = sections {
+    // The task may have defined additional section-to-memory mappings, and
+    // each shared region has its own section.
+    let mut map = sections.cloned().unwrap_or_default();
+    for (region, _) in shared {
+        map.insert(format!("shared.{}", region), shared_memory_name(region));
+    }
+    if !map.is_empty() {
         writeln!(linkscr, "SECTIONS {{")?;
         for (section, memory) in map {
             writeln!(linkscr, "  .{} (NOLOAD) : ALIGN(4) {{", section)?;
@@ -795,6 +872,133 @@ fn generate_task_linker_script(
     Ok(())
 }
 
+/// Names the linker script memory for shared region `region`.
+fn shared_memory_name(region: &str) -> String {
+    format!("SHARED_{}", region.to_ascii_uppercase().replace('-', "_"))
+}
+
+/// Checks that the application's shared regions make sense, before we try to
+/// allocate them.
+fn check_shared_regions(toml: &Config) -> Result<()> {
+    for (name, region) in &toml.shared {
+        let out = toml.outputs.get(region.memory()).ok_or_else(|| {
+            anyhow!(
+                "shared region {}: no output named {}",
+                name,
+                region.memory()
+            )
+        })?;
+        if !out.write && !region.writers.is_empty() {
+            bail!(
+                "shared region {}: has writers, but output {} isn't writable",
+                name,
+                region.memory()
+            );
+        }
+        for task in region.writers.iter().chain(&region.readers) {
+            if !toml.tasks.contains_key(task) {
+                bail!("shared region {}: names unknown task {}", name, task);
+            }
+        }
+        if let Some(task) =
+            region.readers.iter().find(|t| region.writers.contains(t))
+        {
+            bail!(
+                "shared region {}: task {} is both a reader and a writer",
+                name,
+                task
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Checks that the tasks' `start-after` lists make sense: that every task
+/// involved actually starts, that the supervisor doesn't have to wait, and that
+/// no tasks are waiting for each other.
+pub(crate) fn check_start_order(tasks: &IndexMap<String, Task>) -> Result<()> {
+    for (i, (name, task)) in tasks.iter().enumerate() {
+        if task.start_after.is_empty() {
+            continue;
+        }
+        if i == 0 {
+            bail!("task {}: the supervisor can't have start-after", name);
+        }
+        if !task.start {
+            bail!("task {}: has start-after, but doesn't start", name);
+        }
+        for dep in &task.start_after {
+            match tasks.get(dep) {
+                None => {
+                    bail!("task {}: start-after: unknown task {}", name, dep)
+                }
+                Some(t) if !t.start => bail!(
+                    "task {}: start-after: {} never starts, so neither would \
+                     this",
+                    name,
+                    dep
+                ),
+                Some(_) => (),
+            }
+        }
+    }
+
+    // Look for cycles with a depth-first search from every task, keeping
+    // track of the path we took to get where we are.
+    #[derive(Copy, Clone, PartialEq)]
+    enum Mark {
+        Unvisited,
+        OnPath,
+        Done,
+    }
+
+    fn visit<'a>(
+        tasks: &'a IndexMap<String, Task>,
+        name: &'a str,
+        marks: &mut HashMap<&'a str, Mark>,
+        path: &mut Vec<&'a str>,
+    ) -> Result<()> {
+        match marks.get(name).copied().unwrap_or(Mark::Unvisited) {
+            Mark::Done => return Ok(()),
+            Mark::OnPath => {
+                let start = path.iter().position(|&n| n == name).unwrap();
+                let mut cycle = path[start..].to_vec();
+                cycle.push(name);
+                bail!(
+                    "tasks wait for each other to start: {}",
+                    cycle.join(" -> ")
+                );
+            }
+            Mark::Unvisited => (),
+        }
+        marks.insert(name, Mark::OnPath);
+        path.push(name);
+        for dep in &tasks[name].start_after {
+            visit(tasks, dep, marks, path)?;
+        }
+        path.pop();
+        marks.insert(name, Mark::Done);
+        Ok(())
+    }
+
+    let mut marks = HashMap::new();
+    for name in tasks.keys() {
+        visit(tasks, name, &mut marks, &mut vec![])?;
+    }
+    Ok(())
+}
+
+/// Encodes the tasks' `start-after` lists for the supervisor's build, as
+/// `task=dep,dep;task=dep`, leaving out tasks that don't wait for anything.
+fn start_after_env(tasks: &IndexMap<String, Task>) -> String {
+    tasks
+        .iter()
+        .filter(|(_, task)| !task.start_after.is_empty())
+        .map(|(name, task)| format!("{}={}", name, task.start_after.join(",")))
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
 fn generate_kernel_linker_script(
     name: &str,
     map: &BTreeMap<String, Range<u32>>,
@@ -871,10 +1075,13 @@ fn build(
     verbose: bool,
     edges: bool,
     task_names: &str,
+    lock_names: &str,
+    start_after: &str,
     secure: &Option<bool>,
     shared_syms: &Option<&[String]>,
     config: &Option<toml::Value>,
     app_config: &Option<toml::Value>,
+    host_program: bool,
 ) -> Result<()> {
     println!("building path {}", path.display());
 
@@ -913,21 +1120,47 @@ fn build(
     // rebuilds. by canonicalizing it, you get foo/target for every one.
     let canonical_cargo_out_dir = fs::canonicalize(&cargo_out)?;
 
-    cmd.current_dir(path);
-    cmd.env(
-        "RUSTFLAGS",
-        &format!(
+    // The hosted kernel is linked however the host usually links programs;
+    // everything else gets our linker script.
+    let mut rustflags = if host_program {
+        String::new()
+    } else {
+        format!(
             "-C link-arg=-Tlink.x \
              -L {} \
              -C link-arg=-z -C link-arg=common-page-size=0x20 \
              -C link-arg=-z -C link-arg=max-page-size=0x20 \
-             -C llvm-args=--enable-machine-outliner=never \
-             -C overflow-checks=y",
-            canonical_cargo_out_dir.display()
-        ),
-    );
+             -C llvm-args=--enable-machine-outliner=never ",
+            canonical_cargo_out_dir.display(),
+        )
+    };
+    if target == HOSTED_TARGET && !host_program {
+        // Hosted tasks are still bare-metal programs, at fixed addresses, that
+        // happen to run in a Linux process: keep the host's C runtime, and its
+        // ideas about position independence, out of them.
+        rustflags.push_str(
+            "-C relocation-model=static \
+             -C panic=abort \
+             -C link-arg=-nostartfiles \
+             -C link-arg=-nostdlib \
+             -C link-arg=-static ",
+        );
+    }
+    rustflags.push_str("-C overflow-checks=y");
+    for (local, remapped) in remapped_paths()? {
+        rustflags.push_str(&format!(
+            " --remap-path-prefix={}={}",
+            local.display(),
+            remapped
+        ));
+    }
+
+    cmd.current_dir(path);
+    cmd.env("RUSTFLAGS", &rustflags);
 
     cmd.env("HUBRIS_TASKS", task_names);
+    cmd.env("HUBRIS_LOCKS", lock_names);
+    cmd.env("HUBRIS_START_AFTER", start_after);
     cmd.env("HUBRIS_BOARD", board_name);
 
     if let Some(s) = shared_syms {
@@ -999,22 +1232,223 @@ fn build(
 }
 
 #[derive(Debug, Clone, Default)]
-struct Allocations {
+pub struct Allocations {
     /// Map from memory-name to address-range
-    kernel: BTreeMap<String, Range<u32>>,
+    pub kernel: BTreeMap<String, Range<u32>>,
     /// Map from task-name to memory-name to address-range
-    tasks: BTreeMap<String, BTreeMap<String, Range<u32>>>,
+    pub tasks: BTreeMap<String, BTreeMap<String, Range<u32>>>,
+    /// Map from shared-region-name to address-range
+    pub shared: BTreeMap<String, Range<u32>>,
+}
+
+/// Something that wants address space.
+#[derive(Copy, Clone, Debug)]
+enum Requester<'a> {
+    /// The kernel.
+    Kernel,
+    /// A task, by name.
+    Task(&'a str),
+    /// A shared region, by name.
+    Shared(&'a str),
+}
+
+impl fmt::Display for Requester<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Requester::Kernel => write!(f, "kernel"),
+            Requester::Task(name) => write!(f, "task {}", name),
+            Requester::Shared(name) => write!(f, "shared region {}", name),
+        }
+    }
+}
+
+impl Allocations {
+    /// Records that `requester` has been given `range` from memory `region`.
+    fn insert(
+        &mut self,
+        requester: Requester,
+        region: &str,
+        range: Range<u32>,
+    ) {
+        match requester {
+            Requester::Kernel => {
+                self.kernel.insert(region.to_string(), range);
+            }
+            Requester::Task(task) => {
+                self.tasks
+                    .entry(task.to_string())
+                    .or_default()
+                    .insert(region.to_string(), range);
+            }
+            Requester::Shared(name) => {
+                self.shared.insert(name.to_string(), range);
+            }
+        }
+    }
+
+    /// Writes out where everything is in each of `memories`, including the
+    /// holes between things.
+    fn write_map(
+        &self,
+        out: &mut impl Write,
+        memories: &IndexMap<String, Range<u32>>,
+    ) -> Result<()> {
+        for (mem, avail) in memories {
+            let mut used = self
+                .kernel
+                .get(mem)
+                .map(|r| (r.clone(), Requester::Kernel))
+                .into_iter()
+                .chain(self.tasks.iter().filter_map(|(name, allocs)| {
+                    allocs.get(mem).map(|r| (r.clone(), Requester::Task(name)))
+                }))
+                .chain(self.shared.iter().filter_map(|(name, r)| {
+                    if r.start >= avail.start && r.end <= avail.end {
+                        Some((r.clone(), Requester::Shared(name)))
+                    } else {
+                        None
+                    }
+                }))
+                .collect::<Vec<_>>();
+            if used.is_empty() {
+                continue;
+            }
+            used.sort_by_key(|(r, _)| r.start);
+
+            writeln!(out, "{}:", mem)?;
+            let mut pos = avail.start;
+            let mut holes = 0;
+            for (range, what) in &used {
+                if range.start > pos {
+                    writeln!(
+                        out,
+                        "    {:#010x}..{:#010x} {:>8x}  (hole)",
+                        pos,
+                        range.start,
+                        range.start - pos
+                    )?;
+                    holes += range.start - pos;
+                }
+                writeln!(
+                    out,
+                    "    {:#010x}..{:#010x} {:>8x}  {}",
+                    range.start,
+                    range.end,
+                    range.end - range.start,
+                    what
+                )?;
+                pos = range.end;
+            }
+            writeln!(
+                out,
+                "    {:#x} used, {:#x} in holes, {:#x} free at end",
+                pos - avail.start - holes,
+                holes,
+                avail.end - pos
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// What the target's MPU needs of the memory regions we give tasks.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Mpu {
+    /// ARMv6-M and ARMv7-M: a region is a power of two in size, naturally
+    /// aligned, and split into eight equal subregions that can each be left
+    /// out.
+    PowerOfTwo,
+    /// ARMv8-M: a region starts and ends on any 32-byte boundary.
+    BaseLimit,
+}
+
+impl Mpu {
+    pub(crate) fn for_target(target: &str) -> Result<Self> {
+        match target {
+            "thumbv8m.main-none-eabihf" => Ok(Mpu::BaseLimit),
+            "thumbv7em-none-eabihf" => Ok(Mpu::PowerOfTwo),
+            // The hosted kernel checks task accesses in software, and does so
+            // at the same granularity as ARMv8-M.
+            HOSTED_TARGET => Ok(Mpu::BaseLimit),
+            t => bail!("unknown MPU requirements for target '{}'", t),
+        }
+    }
+
+    /// Returns the size of the smallest region that can hold `used` bytes.
+    pub(crate) fn smallest_region(self, used: u32) -> u32 {
+        match self {
+            Mpu::PowerOfTwo => {
+                let region = used.max(32).next_power_of_two();
+                if region < 256 {
+                    region
+                } else {
+                    let sub = region / 8;
+                    (used + sub - 1) / sub * sub
+                }
+            }
+            Mpu::BaseLimit => (used.max(32) + 31) & !31,
+        }
+    }
+
+    /// Returns the alignment of a region of `size` bytes of memory `mem` for
+    /// `requester`, or an error if the MPU can't make a region that size.
+    fn alignment(
+        self,
+        requester: Requester,
+        mem: &str,
+        size: u32,
+    ) -> Result<u32> {
+        match self {
+            Mpu::PowerOfTwo => {
+                if size.is_power_of_two() {
+                    return Ok(size);
+                }
+                // Otherwise, it has to be the bottom few subregions of the
+                // next power of two up. Regions under 256 bytes don't have
+                // subregions.
+                match size.checked_next_power_of_two() {
+                    Some(region)
+                        if region >= 256 && size % (region / 8) == 0 =>
+                    {
+                        Ok(region)
+                    }
+                    _ => bail!(
+                        "{}, memory region {}: requirement {} is not a power \
+                         of two, or a whole number of eighths of one of at \
+                         least 256 bytes.",
+                        requester,
+                        mem,
+                        size
+                    ),
+                }
+            }
+            Mpu::BaseLimit => {
+                if size == 0 || size % 32 != 0 {
+                    bail!(
+                        "{}, memory region {}: requirement {} is not a \
+                         multiple of 32 bytes.",
+                        requester,
+                        mem,
+                        size
+                    );
+                }
+                Ok(32)
+            }
+        }
+    }
 }
 
 /// Allocates address space from all regions for the kernel and all tasks.
 ///
 /// The allocation strategy is slightly involved, because of the limitations of
-/// the ARMv7-M MPU. (Currently we use the same strategy on ARMv8-M even though
-/// it's more flexible.)
+/// the MPU.
 ///
-/// Address space regions are required to be power-of-two in size and naturally
-/// aligned. In other words, all the addresses in a single region must have some
-/// number of top bits the same, and any combination of bottom bits.
+/// On ARMv7-M, address space regions are required to be power-of-two in size
+/// and naturally aligned. In other words, all the addresses in a single region
+/// must have some number of top bits the same, and any combination of bottom
+/// bits. A region is split into eight subregions, and we can leave out the top
+/// few, so a request for (say) 48 KiB gets 48 KiB, but aligned like 64 KiB. On
+/// ARMv8-M, regions need only be multiples of 32 bytes, aligned to match.
 ///
 /// To complicate things,
 ///
@@ -1026,167 +1460,128 @@ struct Allocations {
 ///   ROM, so, the kernel must be laid down first. (This is not true of RAM, but
 ///   putting the kernel first in RAM has some useful benefits.)
 ///
-/// The method we're using here is essentially the "deallocate" side of a
-/// power-of-two buddy allocator, only simplified because we're using it to
-/// allocate a series of known sizes.
-///
-/// To allocate space for a single request, we
-///
-/// - Check the alignment of the current position pointer.
-/// - Find the largest pending request of that alignment _or less._
-/// - If we found one, bump the pointer forward and repeat.
-/// - If not, find the smallest pending request that requires greater alignment,
-///   and skip address space until it can be satisfied, and then repeat.
-///
-/// This means that the algorithm needs to keep track of a queue of pending
-/// requests per alignment size.
+/// So, after the kernel, we place requests in descending order of alignment
+/// (and then of size), since each one then leaves the free space at least as
+/// aligned as the next needs. That can't always work -- after the kernel, or
+/// after a request that uses only part of its subregions -- so we keep track of
+/// the holes left behind, and put each request in whichever spot leaves the
+/// smallest gap below it. Later, smaller requests can then fill in the gaps.
 fn allocate_all(
+    mpu: Mpu,
     kernel: &crate::Kernel,
     tasks: &IndexMap<String, crate::Task>,
-    free: &mut IndexMap<String, Range<u32>>,
+    shared: &IndexMap<String, SharedRegion>,
+    memories: &IndexMap<String, Range<u32>>,
 ) -> Result<Allocations> {
-    // Collect all allocation requests into queues, one per memory type, indexed
-    // by allocation size. This is equivalent to required alignment because of
-    // the naturally-aligned-power-of-two requirement.
-    //
-    // We keep kernel and task requests separate so we can always service the
-    // kernel first.
-    //
-    // The task map is: memory name -> allocation size -> queue of task name.
-    // The kernel map is: memory name -> allocation size
-    let kernel_requests = &kernel.requires;
-    for (name, &amt) in kernel_requests {
-        if !amt.is_power_of_two() {
-            bail!("kernel, memory region {}: requirement {} is not a power of two.",
-                name, amt);
-        }
-    }
-
-    let mut task_requests: BTreeMap<&str, BTreeMap<u32, VecDeque<&str>>> =
+    // Collect all allocation requests into lists, one per memory type, of
+    // (requester, size, alignment).
+    let mut requests: BTreeMap<&str, Vec<(Requester, u32, u32)>> =
         BTreeMap::new();
+    let mut request = |requester, mem, size| -> Result<()> {
+        let align = mpu.alignment(requester, mem, size)?;
+        requests
+            .entry(mem)
+            .or_default()
+            .push((requester, size, align));
+        Ok(())
+    };
 
+    for (mem, &size) in &kernel.requires {
+        request(Requester::Kernel, mem.as_str(), size)?;
+    }
     for (name, task) in tasks {
-        for (mem, &amt) in &task.requires {
-            if !amt.is_power_of_two() {
-                bail!("task {}, memory region {}: requirement {} is not a power of two.",
-                    task.name, name, amt);
-            }
-            task_requests
-                .entry(mem.as_str())
-                .or_default()
-                .entry(amt)
-                .or_default()
-                .push_back(name.as_str());
+        for (mem, &size) in &task.requires {
+            request(Requester::Task(name.as_str()), mem.as_str(), size)?;
         }
     }
+    for (name, region) in shared {
+        request(
+            Requester::Shared(name.as_str()),
+            region.memory(),
+            region.size,
+        )?;
+    }
 
     // Okay! Do memory types one by one, fitting kernel first.
     let mut allocs = Allocations::default();
-    for (region, avail) in free {
-        let mut k_req = kernel_requests.get(region.as_str());
-        let mut t_reqs = task_requests.get_mut(region.as_str());
-
-        fn reqs_map_not_empty(
-            om: &Option<&mut BTreeMap<u32, VecDeque<&str>>>,
-        ) -> bool {
-            om.iter()
-                .flat_map(|map| map.values())
-                .any(|q| !q.is_empty())
-        }
-
-        'fitloop: while k_req.is_some() || reqs_map_not_empty(&t_reqs) {
-            let align = if avail.start == 0 {
-                // Lie to keep the masks in range. This could be avoided by
-                // tracking log2 of masks rather than masks.
-                1 << 31
-            } else {
-                1 << avail.start.trailing_zeros()
-            };
-
-            // Search order is:
-            // - Kernel.
-            // - Task requests equal to or smaller than this alignment, in
-            //   descending order of size.
-            // - Task requests larger than this alignment, in ascending order of
-            //   size.
-
-            if let Some(&sz) = k_req.take() {
-                // The kernel wants in on this.
-                allocs.kernel.insert(
-                    region.to_string(),
-                    allocate_one(region, sz, avail)?,
-                );
-                continue 'fitloop;
-            }
-
-            if let Some(t_reqs) = t_reqs.as_mut() {
-                for (&sz, q) in t_reqs.range_mut(..=align).rev() {
-                    if let Some(task) = q.pop_front() {
-                        // We can pack an equal or smaller one in.
-                        allocs
-                            .tasks
-                            .entry(task.to_string())
-                            .or_default()
-                            .insert(
-                                region.to_string(),
-                                allocate_one(region, sz, avail)?,
-                            );
-                        continue 'fitloop;
-                    }
-                }
-
-                for (&sz, q) in t_reqs.range_mut(align + 1..) {
-                    if let Some(task) = q.pop_front() {
-                        // We've gotta use a larger one.
-                        allocs
-                            .tasks
-                            .entry(task.to_string())
-                            .or_default()
-                            .insert(
-                                region.to_string(),
-                                allocate_one(region, sz, avail)?,
-                            );
-                        continue 'fitloop;
-                    }
-                }
-            }
+    for (region, avail) in memories {
+        let reqs = match requests.get_mut(region.as_str()) {
+            Some(reqs) => reqs,
+            None => continue,
+        };
+        // This sort is stable, so requests that are otherwise alike stay in
+        // app.toml order.
+        reqs.sort_by_key(|&(requester, size, align)| {
+            (
+                !matches!(requester, Requester::Kernel),
+                Reverse(align),
+                Reverse(size),
+            )
+        });
 
-            // If we reach this point, it means our loop condition is wrong,
-            // because one of the above things should really have happened.
-            // Panic here because otherwise it's a hang.
-            panic!("loop iteration without progess made!");
+        let mut holes = vec![avail.clone()];
+        for &(requester, size, align) in reqs.iter() {
+            let range = allocate_one(region, size, align, &mut holes)?;
+            allocs.insert(requester, region, range);
         }
     }
 
     Ok(allocs)
 }
 
+/// Takes `size` bytes, aligned to `align`, from the free address space in
+/// `holes`, which is kept in address order.
 fn allocate_one(
     region: &str,
     size: u32,
-    avail: &mut Range<u32>,
+    align: u32,
+    holes: &mut Vec<Range<u32>>,
 ) -> Result<Range<u32>> {
     // This condition is ensured by allocate_all.
-    assert!(size.is_power_of_two());
-
-    let size_mask = size - 1;
-
-    // Our base address will be larger than avail.start if it doesn't meet our
-    // minimum requirements. Round up.
-    let base = (avail.start + size_mask) & !size_mask;
+    assert!(align.is_power_of_two());
+
+    let align_mask = align - 1;
+
+    // Find the spot that wastes the least space below it, or the lowest of
+    // those that tie.
+    let mut best: Option<(usize, u32)> = None;
+    for (i, hole) in holes.iter().enumerate() {
+        // Our base address will be larger than hole.start if it doesn't meet
+        // our minimum requirements. Round up.
+        let base = match hole.start.checked_add(align_mask) {
+            Some(x) => x & !align_mask,
+            None => continue,
+        };
+        if base >= hole.end || size > hole.end - base {
+            continue;
+        }
+        let better = match best {
+            Some((j, b)) => base - hole.start < b - holes[j].start,
+            None => true,
+        };
+        if better {
+            best = Some((i, base));
+        }
+    }
 
-    if base >= avail.end || size > avail.end - base {
-        bail!(
-            "out of {}: can't allocate {} more after base {:x}",
+    let (i, base) = best.ok_or_else(|| {
+        anyhow!(
+            "out of {}: can't allocate {} more aligned to {:#x}",
             region,
             size,
-            base
+            align
         )
-    }
+    })?;
 
+    // Update the free space to exclude what we've taken.
     let end = base + size;
-    // Update the available range to exclude what we've taken.
-    avail.start = end;
+    let hole = holes.remove(i);
+    if end < hole.end {
+        holes.insert(i, end..hole.end);
+    }
+    if hole.start < base {
+        holes.insert(i, hole.start..base);
+    }
 
     Ok(base..end)
 }
@@ -1219,19 +1614,25 @@ fn cargo_output_dir(target: &str, path: &Path) -> Result<PathBuf> {
 /// The layout of the table is a series of structs from the `abi` crate:
 ///
 /// - One `App` header.
-/// - Some number of `RegionDesc` records describing memory regions.
+/// - Some number of `RegionDesc` records describing memory regions. Shared
+///   regions get one record for their writers and one for their readers, each
+///   used by all tasks in that group.
 /// - Some number of `TaskDesc` records describing tasks.
 /// - Some number of `Interrupt` records routing interrupts to tasks.
+/// - Some number of `LockDesc` records describing locks.
 fn make_descriptors(
     target: &str,
     tasks: &IndexMap<String, Task>,
     peripherals: &IndexMap<String, Peripheral>,
     supervisor: Option<&Supervisor>,
     task_allocations: &BTreeMap<String, BTreeMap<String, Range<u32>>>,
+    shared: &IndexMap<String, SharedRegion>,
+    shared_allocations: &BTreeMap<String, Range<u32>>,
     stacksize: Option<u32>,
     outputs: &IndexMap<String, Output>,
     entry_points: &HashMap<String, u32>,
     extra_text: &IndexMap<String, Peripheral>,
+    locks: &IndexMap<String, Lock>,
 ) -> Result<Vec<u32>> {
     // Generate the three record sections concurrently, using three separate
     // vecs that we'll later concatenate.
@@ -1254,12 +1655,9 @@ fn make_descriptors(
     let mut peripheral_index = IndexMap::new();
 
     // ARMv6-M and ARMv7-M require that memory regions be a power of two.
-    // ARMv8-M does not.
-    let power_of_two_required = match target {
-        "thumbv8m.main-none-eabihf" => false,
-        "thumbv7em-none-eabihf" => true,
-        t => panic!("Unknown mpu requirements for target '{}'", t),
-    };
+    // ARMv8-M does not. (Task memory can use fewer than all of a region's
+    // subregions, but we don't bother with that for peripherals.)
+    let power_of_two_required = Mpu::for_target(target)? == Mpu::PowerOfTwo;
 
     for (name, p) in peripherals.iter() {
         if power_of_two_required && !p.size.is_power_of_two() {
@@ -1300,27 +1698,31 @@ fn make_descriptors(
         });
     }
 
+    // Shared regions are created as their users are encountered, and indexed
+    // by (region name, writable) so that tasks with the same access share a
+    // record.
+    let mut shared_index: HashMap<(&str, bool), usize> = HashMap::new();
+
     // The remaining regions are allocated to tasks on a first-come first-serve
     // basis.
     for (i, (name, task)) in tasks.iter().enumerate() {
-        if power_of_two_required && !task.requires["flash"].is_power_of_two() {
-            panic!("Flash for task '{}' is required to be a power of two, but has size {}", task.name, task.requires["flash"]);
-        }
-
-        if power_of_two_required && !task.requires["ram"].is_power_of_two() {
-            panic!("Ram for task '{}' is required to be a power of two, but has size {}", task.name, task.requires["flash"]);
-        }
-
         // Regions are referenced by index into the table we just generated.
         // Each task has up to 8, chosen from its 'requires' and 'uses' keys.
         let mut task_regions = [0; 8];
 
-        if task.uses.len() + task.requires.len() > 8 {
+        let task_shared = shared
+            .iter()
+            .filter_map(|(n, r)| r.access(name).map(|w| (n.as_str(), w)))
+            .collect::<Vec<_>>();
+
+        if task.uses.len() + task.requires.len() + task_shared.len() > 8 {
             panic!(
-                "task {} uses {} peripherals and {} memories (too many)",
+                "task {} uses {} peripherals, {} memories, and {} shared \
+                 regions (too many)",
                 name,
                 task.uses.len(),
-                task.requires.len()
+                task.requires.len(),
+                task_shared.len()
             );
         }
 
@@ -1370,11 +1772,46 @@ fn make_descriptors(
             }
         }
 
+        // Shared regions go after the peripherals, and are only created the
+        // first time a task with a particular kind of access to them shows up.
+        let first_shared = allocs.len() + task.uses.len();
+        for (j, &(shared_name, writable)) in task_shared.iter().enumerate() {
+            let index = *shared_index
+                .entry((shared_name, writable))
+                .or_insert_with(|| {
+                    let range = &shared_allocations[shared_name];
+                    let mut attributes = abi::RegionAttributes::READ;
+                    if writable {
+                        attributes |= abi::RegionAttributes::WRITE;
+                    }
+                    regions.push(abi::RegionDesc {
+                        base: range.start,
+                        size: range.end - range.start,
+                        attributes,
+                        reserved_zero: 0,
+                    });
+                    regions.len() - 1
+                });
+            task_regions[first_shared + j] = index as u8;
+        }
+
         let mut flags = abi::TaskFlags::empty();
-        if task.start {
+        // Tasks that have to wait for others are started later, by the
+        // supervisor.
+        if task.start && task.start_after.is_empty() {
             flags |= abi::TaskFlags::START_AT_BOOT;
         }
 
+        let mut ipc_allow = [0; abi::IPC_ALLOW_WORDS];
+        if let Some(sends_to) = &task.sends_to {
+            flags |= abi::TaskFlags::RESTRICT_IPC;
+            ipc_allow = task_bitmap(
+                tasks,
+                sends_to,
+                &format!("task {}: sends-to", name),
+            )?;
+        }
+
         task_descs.push(abi::TaskDesc {
             regions: task_regions,
             entry_point: entry_points[name],
@@ -1382,6 +1819,7 @@ fn make_descriptors(
                 + task.stacksize.unwrap_or(stacksize.unwrap()),
             priority: task.priority,
             flags,
+            ipc_allow,
         });
 
         // Interrupts.
@@ -1412,6 +1850,18 @@ fn make_descriptors(
         }
     }
 
+    // Locks.
+    let mut lock_descs = vec![];
+    for (name, lock) in locks {
+        lock_descs.push(abi::LockDesc {
+            users: task_bitmap(
+                tasks,
+                &lock.users,
+                &format!("lock {}: users", name),
+            )?,
+        });
+    }
+
     // Assemble everything into the final image.
     let mut words = vec![];
 
@@ -1420,9 +1870,8 @@ fn make_descriptors(
     words.push(task_descs.len() as u32);
     words.push(regions.len() as u32);
     words.push(irqs.len() as u32);
-    if let Some(supervisor) = supervisor {
-        words.push(supervisor.notification);
-    }
+    words.push(supervisor.map(|s| s.notification).unwrap_or(0));
+    words.push(lock_descs.len() as u32);
     // pad out to 32 bytes
     words.resize(32 / 4, 0);
 
@@ -1454,6 +1903,7 @@ fn make_descriptors(
         words.push(tdesc.initial_stack);
         words.push(tdesc.priority);
         words.push(tdesc.flags.bits());
+        words.extend_from_slice(&tdesc.ipc_allow);
     }
 
     // Flatten interrupt response records.
@@ -1463,9 +1913,41 @@ fn make_descriptors(
         words.push(idesc.notification);
     }
 
+    // Flatten lock descriptors.
+    for ldesc in lock_descs {
+        words.extend_from_slice(&ldesc.users);
+    }
+
     Ok(words)
 }
 
+/// Converts a list of task names into a bitmap of tasks by index, as used for
+/// `TaskDesc::ipc_allow` and `LockDesc::users`. `what` describes the list, for
+/// error messages.
+fn task_bitmap(
+    tasks: &IndexMap<String, Task>,
+    names: &[String],
+    what: &str,
+) -> Result<[u32; abi::IPC_ALLOW_WORDS]> {
+    let mut bitmap = [0; abi::IPC_ALLOW_WORDS];
+    for peer in names {
+        let index = tasks
+            .get_index_of(peer)
+            .ok_or_else(|| anyhow!("{} names unknown task {}", what, peer))?;
+        if index >= 32 * abi::IPC_ALLOW_WORDS {
+            bail!(
+                "{} can only name the first {} tasks, and {} is task {}",
+                what,
+                32 * abi::IPC_ALLOW_WORDS,
+                peer,
+                index
+            );
+        }
+        bitmap[index / 32] |= 1 << (index % 32);
+    }
+    Ok(bitmap)
+}
+
 /// Loads an SREC file into the same representation we use for ELF. This is
 /// currently unused, but I'm keeping it compiling as proof that it's possible,
 /// because we may need it later.
@@ -1519,8 +2001,11 @@ fn load_elf(
     if elf.header.container()? != Container::Little {
         bail!("where did you get a big-endian image?");
     }
-    if elf.header.e_machine != goblin::elf::header::EM_ARM {
-        bail!("this is not an ARM file");
+    match elf.header.e_machine {
+        goblin::elf::header::EM_ARM => (),
+        // Hosted tasks.
+        goblin::elf::header::EM_386 => (),
+        _ => bail!("this is not an ARM (or hosted x86) file"),
     }
 
     let mut flash = 0;
@@ -1565,36 +2050,63 @@ fn load_elf(
     Ok((elf.header.e_entry as u32, flash))
 }
 
+/// Returns the address range of each of the image's outputs, by name.
+fn output_ranges(toml: &Config) -> Result<IndexMap<String, Range<u32>>> {
+    let mut memories = IndexMap::new();
+    for (name, out) in &toml.outputs {
+        if let Some(end) = out.address.checked_add(out.size) {
+            memories.insert(name.clone(), out.address..end);
+        } else {
+            bail!(
+                "output {}: address {:08x} size {:x} would overflow",
+                name,
+                out.address,
+                out.size
+            );
+        }
+    }
+    Ok(memories)
+}
+
+/// Works out where everything in the image goes, just as `package` does, for
+/// the benefit of commands that look at its output afterwards.
+pub(crate) fn allocate(toml: &Config) -> Result<Allocations> {
+    check_shared_regions(toml)?;
+    let memories = output_ranges(toml)?;
+    allocate_all(
+        Mpu::for_target(&toml.target)?,
+        &toml.kernel,
+        &toml.tasks,
+        &toml.shared,
+        &memories,
+    )
+}
+
 /// Keeps track of a build archive being constructed.
+///
+/// Files are collected in memory and written out in order of name when the
+/// archive is finished, all with the same timestamp and permissions, so that
+/// the archive depends only on their contents.
 struct Archive {
     /// Place where we'll put the final zip file.
     final_path: PathBuf,
-    /// Name of temporary file used during construction.
-    tmp_path: PathBuf,
-    /// ZIP output to the temporary file.
-    inner: zip::ZipWriter<File>,
+    /// Files to go in the archive, by path within it.
+    files: BTreeMap<String, Vec<u8>>,
     /// Options used for every file.
     opts: zip::write::FileOptions,
 }
 
 impl Archive {
     /// Creates a new build archive that will, when finished, be placed at
-    /// `dest`.
-    fn new(dest: impl AsRef<Path>) -> Result<Self> {
-        let final_path = PathBuf::from(dest.as_ref());
-
-        let mut tmp_path = final_path.clone();
-        tmp_path.set_extension("zip.partial");
-
-        let archive = File::create(&tmp_path)?;
-        let mut inner = zip::ZipWriter::new(archive);
-        inner.set_comment("hubris build archive v1.0.0");
+    /// `dest`, with every file in it stamped with time `mtime`.
+    fn new(dest: impl AsRef<Path>, mtime: zip::DateTime) -> Result<Self> {
         Ok(Self {
-            final_path,
-            tmp_path,
-            inner,
+            final_path: PathBuf::from(dest.as_ref()),
+            files: BTreeMap::new(),
             opts: zip::write::FileOptions::default()
-                .compression_method(zip::CompressionMethod::Bzip2),
+                .compression_method(zip::CompressionMethod::Bzip2)
+                .last_modified_time(mtime)
+                .unix_permissions(0o644),
         })
     }
 
@@ -1604,10 +2116,8 @@ impl Archive {
         src_path: impl AsRef<Path>,
         zip_path: impl AsRef<Path>,
     ) -> Result<()> {
-        let mut input = File::open(src_path)?;
-        self.inner
-            .start_file_from_path(zip_path.as_ref(), self.opts)?;
-        std::io::copy(&mut input, &mut self.inner)?;
+        let contents = std::fs::read(src_path)?;
+        self.files.insert(archive_name(zip_path.as_ref()), contents);
         Ok(())
     }
 
@@ -1617,36 +2127,184 @@ impl Archive {
         zip_path: impl AsRef<Path>,
         contents: impl AsRef<str>,
     ) -> Result<()> {
-        self.inner
-            .start_file_from_path(zip_path.as_ref(), self.opts)?;
-        self.inner.write_all(contents.as_ref().as_bytes())?;
+        self.files.insert(
+            archive_name(zip_path.as_ref()),
+            contents.as_ref().as_bytes().to_vec(),
+        );
         Ok(())
     }
 
-    /// Completes the archive and moves it to its intended location.
+    /// Adds `manifest.json`, which records the SHA-256 of every file added so
+    /// far, and what else went into the build, and returns its contents.
+    fn manifest(
+        &mut self,
+        name: &str,
+        git_rev: &str,
+        git_dirty: bool,
+        toolchain: &str,
+        epoch: u64,
+    ) -> Result<String> {
+        let files = self
+            .files
+            .iter()
+            .map(|(path, contents)| (path.clone(), sha256_hex(contents)))
+            .collect::<BTreeMap<_, _>>();
+        let manifest = serde_json::json!({
+            "version": 1,
+            "name": name,
+            "git-rev": git_rev,
+            "git-dirty": git_dirty,
+            "toolchain": toolchain,
+            "source-date-epoch": epoch,
+            "app-toml": files["app.toml"],
+            "files": files,
+        });
+        let manifest = serde_json::to_string_pretty(&manifest)? + "\n";
+        self.text("manifest.json", &manifest)?;
+        Ok(manifest)
+    }
+
+    /// Writes out the archive and moves it to its intended location.
     ///
-    /// If you drop an `Archive` without calling this, it will leave a temporary
-    /// file rather than creating the final archive.
+    /// If you drop an `Archive` without calling this, no archive is created.
     fn finish(self) -> Result<()> {
-        let Self {
-            tmp_path,
-            final_path,
-            mut inner,
-            ..
-        } = self;
+        let mut tmp_path = self.final_path.clone();
+        tmp_path.set_extension("zip.partial");
+
+        let mut inner = zip::ZipWriter::new(File::create(&tmp_path)?);
+        inner.set_comment("hubris build archive v1.1.0");
+        for (path, contents) in &self.files {
+            inner.start_file(path.as_str(), self.opts)?;
+            inner.write_all(contents)?;
+        }
         inner.finish()?;
         drop(inner);
-        std::fs::rename(tmp_path, final_path)?;
+        std::fs::rename(tmp_path, &self.final_path)?;
         Ok(())
     }
 }
 
+/// Names a file in an archive, with forward slashes whatever the host.
+fn archive_name(path: &Path) -> String {
+    path.to_slash_lossy()
+}
+
+/// Returns the SHA-256 of `data`, in hex.
+pub(crate) fn sha256_hex(data: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Returns the version of the Rust toolchain we're building with.
+pub(crate) fn toolchain_version() -> Result<String> {
+    let mut cmd = Command::new("rustc");
+    cmd.arg("--version");
+    let out = cmd
+        .output()
+        .context(format!("failed to run rustc ({:?})", cmd))?;
+    if !out.status.success() {
+        bail!("rustc --version failed");
+    }
+    Ok(std::str::from_utf8(&out.stdout)?.trim().to_string())
+}
+
+/// Returns the prefixes of the paths that we keep out of the binaries (in panic
+/// messages and debug info), so that building the same source in different
+/// places gives the same result, each with what it's replaced with: the
+/// checkout, and the cargo home that dependencies' sources -- from crates.io
+/// and git alike -- are unpacked into.
+///
+/// Later prefixes take precedence, so the checkout wins if one is inside the
+/// other.
+fn remapped_paths() -> Result<Vec<(PathBuf, &'static str)>> {
+    let mut paths = vec![];
+    let cargo_home = match std::env::var_os("CARGO_HOME") {
+        Some(home) => Some(PathBuf::from(home)),
+        None => std::env::var_os("HOME").map(|h| Path::new(&h).join(".cargo")),
+    };
+    // If there's no cargo home, there's nothing in it to remap.
+    if let Some(Ok(cargo_home)) = cargo_home.map(fs::canonicalize) {
+        paths.push((cargo_home, "/cargo"));
+    }
+    paths.push((fs::canonicalize(std::env::current_dir()?)?, "/hubris"));
+    Ok(paths)
+}
+
+/// Tells GDB where to find the sources that `remapped_paths` hid from it.
+fn write_substitute_paths(gdb_script: &mut File) -> Result<()> {
+    for (local, remapped) in remapped_paths()? {
+        writeln!(
+            gdb_script,
+            "set substitute-path {} {}",
+            remapped,
+            local.to_slash().unwrap()
+        )?;
+    }
+    Ok(())
+}
+
+/// Returns the time to stamp build archives with, in seconds since the Unix
+/// epoch: `SOURCE_DATE_EPOCH` if it's set (see
+/// https://reproducible-builds.org/specs/source-date-epoch/), or the time of
+/// the commit we're building from.
+fn source_date_epoch() -> Result<u64> {
+    if let Ok(epoch) = std::env::var("SOURCE_DATE_EPOCH") {
+        return epoch
+            .trim()
+            .parse()
+            .with_context(|| format!("bad SOURCE_DATE_EPOCH {:?}", epoch));
+    }
+
+    let mut cmd = Command::new("git");
+    cmd.arg("log").arg("-1").arg("--format=%ct").arg("HEAD");
+    let out = cmd.output()?;
+    if !out.status.success() {
+        bail!("git log failed");
+    }
+    Ok(std::str::from_utf8(&out.stdout)?.trim().parse()?)
+}
+
+/// Converts `epoch`, in seconds since the Unix epoch, to a zip timestamp. Zip
+/// timestamps start in 1980, so anything earlier becomes the start of 1980.
+fn zip_time(epoch: u64) -> Result<zip::DateTime> {
+    // This is the days-to-civil algorithm from
+    // http://howardhinnant.github.io/date_algorithms.html, simplified because
+    // we never see dates before 1970.
+    let days = epoch / 86400;
+    let secs = epoch % 86400;
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+
+    if year < 1980 {
+        return Ok(zip::DateTime::default());
+    }
+    zip::DateTime::from_date_and_time(
+        year as u16,
+        month as u8,
+        day as u8,
+        (secs / 3600) as u8,
+        (secs / 60 % 60) as u8,
+        (secs % 60) as u8,
+    )
+    .map_err(|_| anyhow!("can't represent {} in a zip file", epoch))
+}
+
 /// Gets the status of a git repository containing the current working
 /// directory. Returns two values:
 ///
 /// - A `String` containing the git commit hash.
 /// - A `bool` indicating whether the repository has uncommitted changes.
-fn get_git_status() -> Result<(String, bool)> {
+pub(crate) fn get_git_status() -> Result<(String, bool)> {
     let mut cmd = Command::new("git");
     cmd.arg("rev-parse").arg("HEAD");
     let out = cmd.output()?;
@@ -0,0 +1,168 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Loading raw dumps of the tables the kernel and Jefe keep for debuggers.
+//!
+//! `trace`, `faults`, `stacks` and `top` each decode a dump of one symbol --
+//! e.g. from GDB, with `dump binary value` -- in light of the image
+//! configuration. The symbols come in two shapes: per-task tables, which are
+//! arrays of fixed-size records indexed by task, and logs, which are rings
+//! (see `Ring`). All of them are little-endian.
+
+use std::convert::TryInto;
+use std::path::Path;
+
+use anyhow::{bail, Result};
+
+use crate::Config;
+
+/// Reads the image configuration at `cfg`.
+pub(crate) fn load_config(cfg: &Path) -> Result<Config> {
+    let cfg_contents = std::fs::read(&cfg)?;
+    Ok(toml::from_slice(&cfg_contents)?)
+}
+
+/// Returns the names of the tasks in `toml`, in index order.
+pub(crate) fn task_names(toml: &Config) -> Vec<&str> {
+    toml.tasks.keys().map(|k| k.as_str()).collect()
+}
+
+/// Reads `dump`, which should be a dump of `symbol`, a per-task table with a
+/// `record_size`-byte record for each of `tasks` tasks, and returns the
+/// records in task order.
+pub fn read_task_table(
+    dump: &Path,
+    symbol: &str,
+    tasks: usize,
+    record_size: usize,
+) -> Result<Vec<Vec<u8>>> {
+    let bytes = std::fs::read(dump)?;
+    if bytes.len() != tasks * record_size {
+        bail!(
+            "{} is {} bytes, but should be {} for {} tasks; is this a dump \
+             of {} from this image?",
+            dump.display(),
+            bytes.len(),
+            tasks * record_size,
+            tasks,
+            symbol
+        );
+    }
+    Ok(bytes
+        .chunks_exact(record_size)
+        .map(|r| r.to_vec())
+        .collect())
+}
+
+/// Reads the little-endian `u16` at `offset` in `bytes`.
+pub fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap())
+}
+
+/// Reads the little-endian `u32` at `offset` in `bytes`.
+pub fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+/// Reads the little-endian `u64` at `offset` in `bytes`.
+pub fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+/// What to expect of a particular ring.
+pub struct RingFormat {
+    /// What the ring holds, for error messages.
+    pub what: &'static str,
+    pub magic: u32,
+    /// Size of the header, including any words of the ring's own.
+    pub header_size: usize,
+    /// Smallest record that holds everything we decode.
+    pub min_record_size: usize,
+    /// Suggestion of what went wrong, when the magic number is wrong.
+    pub hint: &'static str,
+}
+
+/// A ring of records, as the kernel's trace buffer and Jefe's fault log keep
+/// them: a header of words -- a magic number, the size of a record, how many
+/// records fit, and how many have ever been written, and then any words of the
+/// ring's own -- followed by the records.
+pub struct Ring<'a> {
+    bytes: &'a [u8],
+    header_size: usize,
+    record_size: usize,
+    capacity: usize,
+    count: usize,
+}
+
+impl<'a> Ring<'a> {
+    /// Checks that `bytes` holds a ring in the given `format`.
+    pub fn parse(bytes: &'a [u8], format: &RingFormat) -> Result<Self> {
+        if bytes.len() < format.header_size {
+            bail!("{} dump is too short to contain a header", format.what);
+        }
+
+        let magic = u32_at(bytes, 0);
+        if magic != format.magic {
+            bail!(
+                "bad {} magic {:#x} (expected {:#x}); {}",
+                format.what,
+                magic,
+                format.magic,
+                format.hint
+            );
+        }
+
+        let record_size = u32_at(bytes, 4) as usize;
+        let capacity = u32_at(bytes, 8) as usize;
+        let count = u32_at(bytes, 12) as usize;
+
+        if record_size < format.min_record_size {
+            bail!(
+                "{} records are too small ({} bytes)",
+                format.what,
+                record_size
+            );
+        }
+        if bytes.len() < format.header_size + record_size * capacity {
+            bail!(
+                "{} dump is truncated: {} records of {} bytes don't fit",
+                format.what,
+                capacity,
+                record_size
+            );
+        }
+
+        Ok(Self {
+            bytes,
+            header_size: format.header_size,
+            record_size,
+            capacity,
+            count,
+        })
+    }
+
+    /// Reads the header word at `offset`, for words of the ring's own.
+    pub fn header_word(&self, offset: usize) -> u32 {
+        u32_at(self.bytes, offset)
+    }
+
+    /// Returns the records, oldest first.
+    pub fn records(&self) -> Vec<&'a [u8]> {
+        // Until the ring wraps, it starts at slot 0; after that, the oldest
+        // record is the one about to be overwritten.
+        let (first, len) = if self.count <= self.capacity {
+            (0, self.count)
+        } else {
+            (self.count % self.capacity, self.capacity)
+        };
+
+        (0..len)
+            .map(|i| {
+                let base = self.header_size
+                    + (first + i) % self.capacity * self.record_size;
+                &self.bytes[base..base + self.record_size]
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,116 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Decoder for the supervisor's fault log.
+//!
+//! Jefe records each task fault in a ring at the symbol `FAULT_LOG` (see
+//! `task/jefe/src/faultlog.rs`), which survives reset. Given a raw dump of
+//! that symbol -- e.g. from GDB, with `dump binary value faults.bin
+//! FAULT_LOG` -- this prints the faults oldest-first.
+
+use std::convert::TryInto;
+use std::path::Path;
+
+use abi::{FaultInfo, FaultRecord, FaultSource, FAULT_RECORD_INFO_SIZE};
+use anyhow::Result;
+
+use crate::dump::{self, Ring, RingFormat};
+
+/// The fault log is a ring with one word of its own: the boot count.
+const FORMAT: RingFormat = RingFormat {
+    what: "fault log",
+    magic: abi::FAULT_LOG_MAGIC,
+    header_size: 24,
+    min_record_size: std::mem::size_of::<FaultRecord>(),
+    hint: "is this a dump of FAULT_LOG, taken after Jefe has started?",
+};
+
+pub fn run(cfg: &Path, dump: &Path) -> Result<()> {
+    let toml = dump::load_config(cfg)?;
+    let names = dump::task_names(&toml);
+
+    let bytes = std::fs::read(dump)?;
+    let (boots, records) = parse(&bytes)?;
+
+    println!(
+        "system has booted {} times since the log was created",
+        boots
+    );
+    if records.is_empty() {
+        println!("no faults recorded");
+        return Ok(());
+    }
+
+    println!(
+        "{:>5} {:>12} {:<16} {:>3} FAULT",
+        "BOOT", "TICKS", "TASK", "GEN"
+    );
+    for r in &records {
+        let task = match names.get(usize::from(r.task)) {
+            Some(name) => name.to_string(),
+            None => format!("#{}", r.task),
+        };
+        let fault = match ssmarshal::deserialize::<FaultInfo>(&r.fault) {
+            Ok((fault, _)) => describe(&fault, &names),
+            Err(_) => format!("undecodable fault {:x?}", r.fault),
+        };
+        println!(
+            "{:>5} {:>12} {:<16} {:>3} {}",
+            r.boot, r.timestamp, task, r.generation, fault
+        );
+    }
+
+    Ok(())
+}
+
+fn describe(fault: &FaultInfo, names: &[&str]) -> String {
+    let source = |s: &FaultSource| match s {
+        FaultSource::User => "",
+        FaultSource::Kernel => " (in kernel)",
+    };
+
+    match fault {
+        FaultInfo::MemoryAccess { address, source: s } => match address {
+            Some(a) => format!("memory fault at {:#x}{}", a, source(s)),
+            None => format!("memory fault at unknown address{}", source(s)),
+        },
+        FaultInfo::BusError { address, source: s } => match address {
+            Some(a) => format!("bus error at {:#x}{}", a, source(s)),
+            None => format!("bus error at unknown address{}", source(s)),
+        },
+        FaultInfo::StackOverflow { address } => {
+            format!("stack overflow at {:#x}", address)
+        }
+        FaultInfo::DivideByZero => "divide-by-zero".to_string(),
+        FaultInfo::IllegalText => "illegal text".to_string(),
+        FaultInfo::IllegalInstruction => "illegal instruction".to_string(),
+        FaultInfo::InvalidOperation(details) => {
+            format!("invalid operation: {:#010x}", details)
+        }
+        FaultInfo::SyscallUsage(e) => format!("bad syscall usage: {:?}", e),
+        FaultInfo::Panic => "panic".to_string(),
+        FaultInfo::Injected(who) => match names.get(who.index()) {
+            Some(name) => format!("fault injected by {}", name),
+            None => format!("fault injected by #{}", who.index()),
+        },
+    }
+}
+
+/// Pulls the boot count and the records out of a dump of `FAULT_LOG`, oldest
+/// first.
+fn parse(bytes: &[u8]) -> Result<(u32, Vec<FaultRecord>)> {
+    let ring = Ring::parse(bytes, &FORMAT)?;
+    let records = ring
+        .records()
+        .into_iter()
+        .map(|r| FaultRecord {
+            timestamp: dump::u64_at(r, 0),
+            boot: dump::u32_at(r, 8),
+            task: dump::u16_at(r, 12),
+            generation: dump::u16_at(r, 14),
+            fault: r[16..16 + FAULT_RECORD_INFO_SIZE].try_into().unwrap(),
+        })
+        .collect();
+    Ok((ring.header_word(16), records))
+}
@@ -0,0 +1,349 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Who calls whom, from an image configuration.
+//!
+//! A task finds the tasks it sends to through its `task-slots`, or from the
+//! messages they send it; if it has a `sends-to` list, the kernel holds it to
+//! that. Together with the tasks' priorities, these describe all of the IPC
+//! in an application. This prints that as a graph, in Graphviz or JSON, along
+//! with each task's interrupts and peripherals. Slots for tasks that `sends-to`
+//! forbids are drawn too, but marked, since using them faults the task.
+//!
+//! It also points out every call that breaks the uphill send rule: a task
+//! should only send to tasks more important than itself (with a smaller
+//! priority number). Sending to a less important task risks starving the
+//! sender, and tasks of equal priority that send to each other risk deadlock.
+//! `cargo xtask check` (and so `dist`) rejects the first kind; the second is
+//! common enough among drivers that we only warn about it here, unless asked to
+//! treat it as an error.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write;
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use serde_json::json;
+
+use crate::Config;
+
+/// What, if anything, is wrong with a call.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Problem {
+    /// The server is as important as the client.
+    EqualPriority,
+    /// The server is less important than the client.
+    LowerPriority,
+}
+
+impl Problem {
+    /// Applies the uphill send rule to a call from a task of priority
+    /// `client` to one of priority `server`.
+    pub(crate) fn of_call(client: u32, server: u32) -> Option<Problem> {
+        // Priority 0 is the most important.
+        if server > client {
+            Some(Problem::LowerPriority)
+        } else if server == client {
+            Some(Problem::EqualPriority)
+        } else {
+            None
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Problem::EqualPriority => "equal-priority",
+            Problem::LowerPriority => "lower-priority",
+        }
+    }
+}
+
+/// A call from one task to another.
+struct Edge<'a> {
+    from: &'a str,
+    to: &'a str,
+    /// The task slot the call goes through, if any; otherwise, the call is
+    /// only known from `sends-to`.
+    slot: Option<&'a str>,
+    problem: Option<Problem>,
+    /// Whether `sends-to` forbids the call, so that making it would fault the
+    /// caller.
+    forbidden: bool,
+}
+
+impl Edge<'_> {
+    /// Describes how the call is made, for labels and warnings.
+    fn how(&self) -> String {
+        match self.slot {
+            Some(slot) => format!("slot {}", slot),
+            None => "sends-to".to_string(),
+        }
+    }
+}
+
+pub fn run(cfg: &Path, json: bool, deny: bool) -> Result<()> {
+    let cfg_contents = std::fs::read(&cfg)?;
+    let toml: Config = toml::from_slice(&cfg_contents)?;
+
+    let edges = edges(&toml);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&to_json(&toml, &edges))?);
+    } else {
+        print!("{}", to_dot(&toml, &edges));
+    }
+
+    // The graph goes to stdout, so it can be redirected; everything else goes
+    // to stderr.
+    let mut problems = 0;
+    for e in edges.iter().filter(|e| e.forbidden) {
+        eprintln!(
+            "warning: {} has task slot {} for {}, but its sends-to forbids \
+             sending there",
+            e.from,
+            e.slot.unwrap(),
+            e.to
+        );
+    }
+    for e in edges.iter().filter(|e| e.problem.is_some() && !e.forbidden) {
+        eprintln!(
+            "warning: {} (priority {}) can send to {} (priority {}) through \
+             {}: {}",
+            e.from,
+            toml.tasks[e.from].priority,
+            e.to,
+            toml.tasks[e.to].priority,
+            e.how(),
+            e.problem.unwrap().name()
+        );
+        problems += 1;
+    }
+    for cycle in cycles(&toml, &edges) {
+        eprintln!(
+            "warning: tasks can send to each other in a cycle, and may \
+             deadlock: {}",
+            cycle.join(" -> ")
+        );
+    }
+
+    if deny && problems != 0 {
+        bail!("{} call(s) break the uphill send rule", problems);
+    }
+    Ok(())
+}
+
+/// Collects every call in the application: one for each task slot, and one
+/// for each task in `sends-to` that no slot leads to. This leaves out tasks
+/// that don't exist (which `cargo xtask check` reports).
+fn edges(toml: &Config) -> Vec<Edge> {
+    let mut edges = vec![];
+    for (name, task) in &toml.tasks {
+        let problem = |server: &str| {
+            Problem::of_call(task.priority, toml.tasks[server].priority)
+        };
+
+        for (slot, target) in &task.task_slots {
+            if !toml.tasks.contains_key(target) {
+                continue;
+            }
+            edges.push(Edge {
+                from: name,
+                to: target,
+                slot: Some(slot),
+                problem: problem(target),
+                forbidden: task
+                    .sends_to
+                    .as_ref()
+                    .map_or(false, |s| !s.contains(target)),
+            });
+        }
+
+        for peer in task.sends_to.iter().flatten() {
+            if !toml.tasks.contains_key(peer)
+                || task.task_slots.values().any(|t| t == peer)
+            {
+                continue;
+            }
+            edges.push(Edge {
+                from: name,
+                to: peer,
+                slot: None,
+                problem: problem(peer),
+                forbidden: false,
+            });
+        }
+    }
+    edges
+}
+
+/// Finds cycles of calls, each of which is listed starting and ending with the
+/// same task. With the uphill send rule followed, there can't be any. Calls
+/// that `sends-to` forbids can't be made, so they don't count.
+fn cycles<'a>(toml: &'a Config, edges: &[Edge<'a>]) -> Vec<Vec<&'a str>> {
+    #[derive(Copy, Clone, PartialEq)]
+    enum Mark {
+        Unvisited,
+        OnPath,
+        Done,
+    }
+
+    fn visit<'a>(
+        name: &'a str,
+        edges: &[Edge<'a>],
+        marks: &mut HashMap<&'a str, Mark>,
+        path: &mut Vec<&'a str>,
+        cycles: &mut Vec<Vec<&'a str>>,
+    ) {
+        match marks.get(name).copied().unwrap_or(Mark::Unvisited) {
+            Mark::Done => return,
+            Mark::OnPath => {
+                let start = path.iter().position(|&n| n == name).unwrap();
+                let mut cycle = path[start..].to_vec();
+                cycle.push(name);
+                cycles.push(cycle);
+                return;
+            }
+            Mark::Unvisited => (),
+        }
+        marks.insert(name, Mark::OnPath);
+        path.push(name);
+        for e in edges.iter().filter(|e| e.from == name && !e.forbidden) {
+            visit(e.to, edges, marks, path, cycles);
+        }
+        path.pop();
+        marks.insert(name, Mark::Done);
+    }
+
+    let mut marks = HashMap::new();
+    let mut cycles = vec![];
+    for name in toml.tasks.keys() {
+        visit(name, edges, &mut marks, &mut vec![], &mut cycles);
+    }
+    cycles
+}
+
+/// Renders the graph in Graphviz's language. Tasks of the same priority are
+/// drawn level with each other, most important at the top, so calls that
+/// don't go uphill stand out even before they're colored in.
+fn to_dot(toml: &Config, edges: &[Edge]) -> String {
+    let mut out = String::new();
+    // Writing to a String can't fail.
+    let mut line = |s: String| writeln!(out, "{}", s).unwrap();
+
+    line(format!("digraph \"{}\" {{", toml.name));
+    line("    rankdir = BT;".to_string());
+    line("    node [shape = box];".to_string());
+
+    let mut by_priority: Vec<(u32, Vec<&str>)> = vec![];
+    for (name, task) in &toml.tasks {
+        let mut label = format!("{}\\npriority {}", name, task.priority);
+        if !task.interrupts.is_empty() {
+            let irqs = task
+                .interrupts
+                .iter()
+                .map(|(irq, mask)| format!("{} (0b{:b})", irq, mask))
+                .collect::<Vec<_>>();
+            label += &format!("\\nIRQ {}", irqs.join(", "));
+        }
+        line(format!("    \"{}\" [label = \"{}\"];", name, label));
+
+        match by_priority.iter_mut().find(|(p, _)| *p == task.priority) {
+            Some((_, names)) => names.push(name),
+            None => by_priority.push((task.priority, vec![name])),
+        }
+    }
+    for (_, names) in &by_priority {
+        let names = names
+            .iter()
+            .map(|n| format!("\"{}\"", n))
+            .collect::<Vec<_>>();
+        line(format!("    {{ rank = same; {}; }}", names.join("; ")));
+    }
+
+    // Peripherals can be shared, but each gets only one node.
+    let peripherals: BTreeSet<&str> = toml
+        .tasks
+        .values()
+        .flat_map(|task| task.uses.iter().map(|p| p.as_str()))
+        .collect();
+    for p in peripherals {
+        line(format!(
+            "    \"periph:{}\" [label = \"{}\", shape = ellipse, \
+             style = dashed];",
+            p, p
+        ));
+    }
+    for (name, task) in &toml.tasks {
+        for p in &task.uses {
+            line(format!(
+                "    \"{}\" -> \"periph:{}\" [style = dotted, arrowhead = none];",
+                name, p
+            ));
+        }
+    }
+
+    for e in edges {
+        let color = match e.problem {
+            None => "black",
+            Some(Problem::EqualPriority) => "orange",
+            Some(Problem::LowerPriority) => "red",
+        };
+        // Forbidden calls are drawn faintly, since they can't happen.
+        let (label, color, style) = if e.forbidden {
+            (format!("{} (forbidden)", e.how()), "gray", "dashed")
+        } else {
+            (e.how(), color, "solid")
+        };
+        line(format!(
+            "    \"{}\" -> \"{}\" [label = \"{}\", color = {}, style = {}];",
+            e.from, e.to, label, color, style
+        ));
+    }
+
+    line("}".to_string());
+    out
+}
+
+/// Renders the graph as JSON.
+fn to_json(toml: &Config, edges: &[Edge]) -> serde_json::Value {
+    let tasks = toml
+        .tasks
+        .iter()
+        .enumerate()
+        .map(|(index, (name, task))| {
+            let irqs = task
+                .interrupts
+                .iter()
+                .map(|(irq, mask)| json!({ "irq": irq, "notification": mask }))
+                .collect::<Vec<_>>();
+            json!({
+                "name": name,
+                "index": index,
+                "priority": task.priority,
+                "irqs": irqs,
+                "peripherals": task.uses,
+                "task_slots": task.task_slots,
+                "sends_to": task.sends_to,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let edges = edges
+        .iter()
+        .map(|e| {
+            json!({
+                "from": e.from,
+                "to": e.to,
+                "slot": e.slot,
+                "problem": e.problem.map(Problem::name),
+                "forbidden": e.forbidden,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    json!({
+        "name": toml.name,
+        "tasks": tasks,
+        "edges": edges,
+    })
+}
@@ -11,15 +11,25 @@ use serde::Deserialize;
 
 use indexmap::IndexMap;
 
+mod check;
 mod clippy;
 mod dist;
+mod dump;
 mod elf;
+mod faults;
 mod flash;
 mod gdb;
+mod graph;
 mod humility;
 mod license;
+mod run;
+mod sizes;
+mod stacks;
 mod task_slot;
 mod test;
+mod top;
+mod trace;
+mod verify_archive;
 
 #[derive(Debug, StructOpt)]
 #[structopt(
@@ -69,6 +79,20 @@ enum Xtask {
         cfg: PathBuf,
     },
 
+    /// Runs `xtask dist` and then runs the image on this machine, for
+    /// applications built for the kernel's hosted (simulation) backend.
+    Run {
+        /// Request verbosity from tools we shell out to.
+        #[structopt(short)]
+        verbose: bool,
+        /// Run for a fixed time, and then check that tasks ended up where
+        /// the application expects.
+        #[structopt(long)]
+        smoke: bool,
+        /// Path to the image configuration file, in TOML.
+        cfg: PathBuf,
+    },
+
     /// Runs `xtask dist` and then runs a properly configured gdb for you.
     Gdb {
         /// Path to the image configuration file, in TOML.
@@ -87,12 +111,13 @@ enum Xtask {
         options: Vec<String>,
     },
 
-    /// Runs `xtask dist`, `xtask flash` and then `humility test`
+    /// Runs `xtask dist`, `xtask flash` and then `humility test` -- or, for
+    /// boards that QEMU emulates, `xtask dist` and then the image under QEMU
     Test {
         /// Path to the image configuration file, in TOML.
         cfg: PathBuf,
 
-        /// Do not flash a new image; just run `humility test`
+        /// Do not build or flash a new image; just run the tests
         #[structopt(short)]
         noflash: bool,
 
@@ -124,6 +149,95 @@ enum Xtask {
         task_bin: PathBuf,
     },
 
+    /// Decodes a dump of the kernel's trace buffer, from a kernel built with
+    /// the `trace` feature
+    Trace {
+        /// Path to the image configuration file, in TOML.
+        cfg: PathBuf,
+
+        /// Raw dump of the kernel's `KERNEL_TRACE` symbol
+        dump: PathBuf,
+    },
+
+    /// Decodes a dump of the supervisor's fault log
+    Faults {
+        /// Path to the image configuration file, in TOML.
+        cfg: PathBuf,
+
+        /// Raw dump of the supervisor's `FAULT_LOG` symbol
+        dump: PathBuf,
+    },
+
+    /// Compares a dump of tasks' peak stack usage, as tracked by the
+    /// supervisor, with their configured stack sizes
+    Stacks {
+        /// Path to the image configuration file, in TOML.
+        cfg: PathBuf,
+
+        /// Raw dump of the supervisor's `STACK_USAGE` symbol
+        dump: PathBuf,
+    },
+
+    /// Compares what's in each task's memory, after `dist`, with how much it's
+    /// been given, and works out how much it could make do with
+    Sizes {
+        /// Path to the image configuration file, in TOML.
+        cfg: PathBuf,
+
+        /// Writes a copy of the configuration, with each task's `requires`
+        /// cut down to what it could make do with, to this path
+        #[structopt(long)]
+        write: Option<PathBuf>,
+    },
+
+    /// Shows each task's share of the CPU, from a dump of the supervisor's
+    /// copy of the kernel's CPU accounting
+    Top {
+        /// Path to the image configuration file, in TOML.
+        cfg: PathBuf,
+
+        /// Raw dump of the supervisor's `CPU_USAGE` symbol
+        dump: PathBuf,
+
+        /// An earlier dump of `CPU_USAGE`, to show usage since then rather
+        /// than since boot
+        #[structopt(long)]
+        since: Option<PathBuf>,
+    },
+
+    /// Checks an image configuration for mistakes, without building anything
+    Check {
+        /// Path to the image configuration file, in TOML.
+        cfg: PathBuf,
+    },
+
+    /// Prints who calls whom, from an image configuration, as a Graphviz
+    /// graph, and warns about calls that break the uphill send rule
+    Graph {
+        /// Path to the image configuration file, in TOML.
+        cfg: PathBuf,
+
+        /// Prints JSON rather than Graphviz
+        #[structopt(long)]
+        json: bool,
+
+        /// Fails if any call breaks the uphill send rule
+        #[structopt(long)]
+        deny: bool,
+    },
+
+    /// Rebuilds an image from the configuration it was built from, and checks
+    /// that the result matches its build archive
+    VerifyArchive {
+        /// Request verbosity from tools we shell out to.
+        #[structopt(short)]
+        verbose: bool,
+        /// Path to the image configuration file, in TOML.
+        cfg: PathBuf,
+        /// Build archive to check
+        archive: PathBuf,
+    },
+
     /// Check that all .rs files have the MPL header
     LicenseCheck,
 }
@@ -148,6 +262,10 @@ struct Config {
     extratext: IndexMap<String, Peripheral>,
     supervisor: Option<Supervisor>,
     #[serde(default)]
+    locks: IndexMap<String, Lock>,
+    #[serde(default)]
+    shared: IndexMap<String, SharedRegion>,
+    #[serde(default)]
     config: Option<toml::Value>,
 }
 
@@ -193,6 +311,49 @@ struct Supervisor {
     notification: u32,
 }
 
+/// A lock provided by the kernel, which tasks take with `sys_lock`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct Lock {
+    /// Tasks that may take the lock.
+    users: Vec<String>,
+}
+
+/// A region of memory mapped into several tasks, so that they can share data
+/// without copying it through the kernel. Tasks place statics in the region by
+/// putting them in the `.shared.<name>` section.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct SharedRegion {
+    /// Output to allocate the region from; defaults to `ram`.
+    memory: Option<String>,
+    size: u32,
+    /// Tasks that may read and write the region.
+    #[serde(default)]
+    writers: Vec<String>,
+    /// Tasks that may only read the region.
+    #[serde(default)]
+    readers: Vec<String>,
+}
+
+impl SharedRegion {
+    fn memory(&self) -> &str {
+        self.memory.as_deref().unwrap_or("ram")
+    }
+
+    /// Works out whether `task` can use this region and, if so, whether it
+    /// can write to it.
+    fn access(&self, task: &str) -> Option<bool> {
+        if self.writers.iter().any(|t| t == task) {
+            Some(true)
+        } else if self.readers.iter().any(|t| t == task) {
+            Some(false)
+        } else {
+            None
+        }
+    }
+}
+
 #[derive(Clone, Debug, Deserialize)]
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
 struct Output {
@@ -228,6 +389,15 @@ struct Task {
     sections: IndexMap<String, String>,
     #[serde(default, deserialize_with = "deserialize_task_slot")]
     task_slots: IndexMap<String, String>,
+    /// Tasks that this task may send to or post notifications to, enforced by
+    /// the kernel. If this is absent, the task may talk to any task.
+    #[serde(default)]
+    sends_to: Option<Vec<String>>,
+    /// Tasks that must have got as far as waiting in `RECV` before the
+    /// supervisor starts this one. A task with any of these doesn't start at
+    /// boot.
+    #[serde(default)]
+    start_after: Vec<String>,
     #[serde(default)]
     config: Option<toml::Value>,
 }
@@ -386,6 +556,14 @@ fn main() -> Result<()> {
             dist::package(verbose, false, &cfg, None)?;
             flash::run(verbose, &cfg)?;
         }
+        Xtask::Run {
+            verbose,
+            smoke,
+            cfg,
+        } => {
+            dist::package(verbose, false, &cfg, None)?;
+            run::run(verbose, &cfg, smoke)?;
+        }
         Xtask::Gdb { cfg, gdb_cfg } => {
             dist::package(false, false, &cfg, None)?;
             gdb::run(&cfg, &gdb_cfg)?;
@@ -400,7 +578,10 @@ fn main() -> Result<()> {
         } => {
             if !noflash {
                 dist::package(verbose, false, &cfg, None)?;
-                flash::run(verbose, &cfg)?;
+
+                if !test::is_emulated(&cfg)? {
+                    flash::run(verbose, &cfg)?;
+                }
             }
 
             test::run(verbose, &cfg)?;
@@ -416,6 +597,34 @@ fn main() -> Result<()> {
         Xtask::TaskSlots { task_bin } => {
             task_slot::dump_task_slot_table(&task_bin)?;
         }
+        Xtask::Trace { cfg, dump } => {
+            trace::run(&cfg, &dump)?;
+        }
+        Xtask::Faults { cfg, dump } => {
+            faults::run(&cfg, &dump)?;
+        }
+        Xtask::Stacks { cfg, dump } => {
+            stacks::run(&cfg, &dump)?;
+        }
+        Xtask::Sizes { cfg, write } => {
+            sizes::run(&cfg, write.as_deref())?;
+        }
+        Xtask::Top { cfg, dump, since } => {
+            top::run(&cfg, &dump, since.as_deref())?;
+        }
+        Xtask::Check { cfg } => {
+            check::run(&cfg)?;
+        }
+        Xtask::Graph { cfg, json, deny } => {
+            graph::run(&cfg, json, deny)?;
+        }
+        Xtask::VerifyArchive {
+            verbose,
+            cfg,
+            archive,
+        } => {
+            verify_archive::run(verbose, &cfg, &archive)?;
+        }
         Xtask::LicenseCheck => {
             if !license::check()? {
                 std::process::exit(1);
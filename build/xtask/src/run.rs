@@ -0,0 +1,55 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{bail, Context};
+
+use crate::dist::HOSTED_TARGET;
+use crate::Config;
+
+/// Runs an image built by `xtask dist` for the kernel's hosted (simulation)
+/// backend, in this process's stead.
+///
+/// With `smoke`, the kernel runs for a fixed time, and then checks that its
+/// tasks got where the application expects them to be; the result says
+/// whether they did.
+pub fn run(verbose: bool, cfg: &Path, smoke: bool) -> anyhow::Result<()> {
+    let cfg_contents = std::fs::read(&cfg)?;
+    let toml: Config = toml::from_slice(&cfg_contents)?;
+
+    if toml.target != HOSTED_TARGET {
+        bail!(
+            "{} is built for {}; only images for {} can be run here",
+            cfg.display(),
+            toml.target,
+            HOSTED_TARGET
+        );
+    }
+
+    let mut dist_dir = PathBuf::from("target");
+    dist_dir.push(&toml.name);
+    dist_dir.push("dist");
+
+    let mut kernel = Command::new(dist_dir.join("kernel"));
+    if smoke {
+        kernel.arg("--smoke");
+    }
+    kernel.arg(&dist_dir);
+
+    if verbose {
+        println!("running: {:?}", kernel);
+    }
+
+    let status = kernel
+        .status()
+        .with_context(|| format!("failed to run kernel ({:?})", kernel))?;
+
+    if !status.success() {
+        bail!("kernel exited with {}", status);
+    }
+
+    Ok(())
+}
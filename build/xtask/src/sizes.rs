@@ -0,0 +1,283 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Report on how much of its memory each task uses.
+//!
+//! Every task is given a box of each kind of memory, sized by `requires` in
+//! the image configuration. After `dist`, this works out where `dist` put
+//! those boxes, sorts the sections of each task's ELF file into them, and
+//! compares what's in them with their size and with the smallest size they
+//! could be. On ARMv7-M, boxes have to be powers of two (or eighths of one),
+//! so that's often a fair bit bigger than what's in them.
+//!
+//! Optionally, it writes a copy of the image configuration with each task's
+//! `requires` cut down to that smallest size. This leaves stack sizes alone;
+//! `cargo xtask stacks` has what's needed to judge those.
+
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use goblin::elf::program_header::PT_LOAD;
+use goblin::elf::section_header::{
+    SHF_ALLOC, SHF_EXECINSTR, SHF_WRITE, SHT_NOBITS,
+};
+
+use crate::dist::Mpu;
+use crate::Config;
+
+/// What's in one of a task's boxes of memory.
+#[derive(Copy, Clone, Debug, Default)]
+struct Usage {
+    /// Code and constants.
+    text: u32,
+    /// Initialized data: the data itself in RAM, and its initial values in
+    /// flash.
+    data: u32,
+    /// Zeroed and uninitialized data.
+    bss: u32,
+    /// The task's stack.
+    stack: u32,
+    /// Bytes from the start of the box to the end of the last thing in it,
+    /// including any gaps for alignment.
+    used: u32,
+}
+
+impl Usage {
+    /// Notes that `size` bytes at `addr` are in use, in the box at `range`.
+    fn extend(&mut self, range: &Range<u32>, addr: u32, size: u32) {
+        self.used = self.used.max(addr + size - range.start);
+    }
+}
+
+pub fn run(cfg: &Path, write: Option<&Path>) -> Result<()> {
+    let cfg_contents = std::fs::read_to_string(&cfg)?;
+    let toml: Config = toml::from_str(&cfg_contents)?;
+
+    let mut dist = PathBuf::from("target");
+    dist.push(&toml.name);
+    dist.push("dist");
+
+    let allocs = crate::dist::allocate(&toml)?;
+    let mpu = Mpu::for_target(&toml.target)?;
+
+    println!(
+        "{:<16} {:<8} {:>7} {:>7} {:>7} {:>7} {:>7} {:>7} {:>7}",
+        "TASK", "MEMORY", "TEXT", "DATA", "BSS", "STACK", "USED", "SIZE", "MIN"
+    );
+
+    let mut suggested = BTreeMap::new();
+    let mut unused: BTreeMap<&str, (u32, u32)> = BTreeMap::new();
+    for (name, task) in &toml.tasks {
+        let boxes = &allocs.tasks[name];
+        let stacksize = task.stacksize.or(toml.stacksize).unwrap_or(0);
+        let usage = task_usage(&dist.join(name), boxes, stacksize)
+            .with_context(|| format!("can't size task {}", name))?;
+
+        let mut requires = task.requires.clone();
+        for (mem, range) in boxes {
+            let u = usage.get(mem).copied().unwrap_or_default();
+            let size = range.end - range.start;
+            let min = mpu.smallest_region(u.used);
+
+            let note = if u.used > size {
+                "  <- too small?".to_string()
+            } else if min < size {
+                format!("  <- could be {}", min)
+            } else {
+                String::new()
+            };
+            println!(
+                "{:<16} {:<8} {:>7} {:>7} {:>7} {:>7} {:>7} {:>7} {:>7}{}",
+                name,
+                mem,
+                u.text,
+                u.data,
+                u.bss,
+                u.stack,
+                u.used,
+                size,
+                min,
+                note
+            );
+
+            let totals = unused.entry(mem.as_str()).or_default();
+            totals.0 += size.saturating_sub(u.used);
+            totals.1 += size.saturating_sub(min);
+            requires.insert(mem.clone(), min);
+        }
+        suggested.insert(name.as_str(), requires);
+    }
+
+    println!();
+    for (mem, (unused, reclaimable)) in &unused {
+        println!(
+            "{}: {} bytes allocated to tasks but unused, of which {} could be \
+             reclaimed; the rest is padding the MPU needs",
+            mem, unused, reclaimable
+        );
+    }
+
+    if let Some(path) = write {
+        let text = rewrite_requires(&cfg_contents, &toml, &suggested);
+        std::fs::write(path, text)?;
+        println!(
+            "wrote configuration with smallest task sizes to {}",
+            path.display()
+        );
+    }
+
+    Ok(())
+}
+
+/// Works out what's in each of a task's boxes, by memory name, from its ELF
+/// file at `path`.
+fn task_usage(
+    path: &Path,
+    boxes: &BTreeMap<String, Range<u32>>,
+    stacksize: u32,
+) -> Result<BTreeMap<String, Usage>> {
+    let image = std::fs::read(path).with_context(|| {
+        format!(
+            "can't read {}; run `cargo xtask dist` first",
+            path.display()
+        )
+    })?;
+    let elf = goblin::elf::Elf::parse(&image)?;
+
+    let find = |addr: u32| {
+        boxes
+            .iter()
+            .find(|(_, range)| range.contains(&addr))
+            .map(|(mem, range)| (mem.clone(), range.clone()))
+    };
+
+    let mut usage: BTreeMap<String, Usage> = BTreeMap::new();
+
+    // The stack comes first in RAM.
+    if let Some(range) = boxes.get("ram") {
+        let u = usage.entry("ram".to_string()).or_default();
+        u.stack = stacksize;
+        u.extend(range, range.start, stacksize);
+    }
+
+    for section in &elf.section_headers {
+        if section.sh_flags & u64::from(SHF_ALLOC) == 0 || section.sh_size == 0
+        {
+            continue;
+        }
+        let section_name =
+            elf.shdr_strtab.get_at(section.sh_name).unwrap_or("");
+        // Shared regions aren't the task's.
+        if section_name.starts_with(".shared.") {
+            continue;
+        }
+
+        let addr = section.sh_addr as u32;
+        let size = section.sh_size as u32;
+        let (mem, range) = match find(addr) {
+            Some(found) => found,
+            None => bail!(
+                "section {} at {:#x} is outside the task's memory; \
+                 has the configuration changed since `dist`?",
+                section_name,
+                addr
+            ),
+        };
+
+        let u = usage.entry(mem).or_default();
+        u.extend(&range, addr, size);
+        if section.sh_type == SHT_NOBITS {
+            u.bss += size;
+        } else if section.sh_flags & u64::from(SHF_EXECINSTR | SHF_WRITE)
+            == u64::from(SHF_WRITE)
+        {
+            u.data += size;
+        } else {
+            u.text += size;
+        }
+
+        // Initialized data is also loaded somewhere else, from which it's
+        // copied at startup.
+        if section.sh_type == SHT_NOBITS {
+            continue;
+        }
+        let offset = section.sh_offset;
+        let load = elf.program_headers.iter().find(|p| {
+            p.p_type == PT_LOAD
+                && offset >= p.p_offset
+                && offset < p.p_offset + p.p_filesz
+        });
+        if let Some(load) = load {
+            let lma = (load.p_paddr + (offset - load.p_offset)) as u32;
+            if lma != addr {
+                if let Some((mem, range)) = find(lma) {
+                    let u = usage.entry(mem).or_default();
+                    u.extend(&range, lma, size);
+                    u.data += size;
+                }
+            }
+        }
+    }
+
+    Ok(usage)
+}
+
+/// Returns a copy of the configuration `text` with each task's `requires`
+/// replaced by the one in `suggested`. This edits the text, rather than
+/// serializing `toml`, so that comments and layout survive.
+fn rewrite_requires(
+    text: &str,
+    toml: &Config,
+    suggested: &BTreeMap<&str, indexmap::IndexMap<String, u32>>,
+) -> String {
+    let mut out = String::new();
+    let mut table = String::new();
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with('[') {
+            table = trimmed
+                .trim_start_matches('[')
+                .split(']')
+                .next()
+                .unwrap_or("")
+                .trim()
+                .to_string();
+        }
+
+        let task = table.strip_prefix("tasks.").filter(|t| !t.contains('.'));
+        let is_requires = trimmed.starts_with("requires")
+            && trimmed["requires".len()..].trim_start().starts_with('=');
+        match (task.and_then(|t| suggested.get(t)), is_requires) {
+            (Some(requires), true) => {
+                let indent = &line[..line.len() - trimmed.len()];
+                let fields = requires
+                    .iter()
+                    .map(|(mem, size)| format!("{} = {}", mem, size))
+                    .collect::<Vec<_>>();
+                out.push_str(&format!(
+                    "{}requires = {{{}}}\n",
+                    indent,
+                    fields.join(", ")
+                ));
+            }
+            _ => {
+                out.push_str(line);
+                out.push('\n');
+            }
+        }
+    }
+
+    for name in toml.tasks.keys() {
+        let header = format!("[tasks.{}]", name);
+        if !text.lines().any(|l| l.trim() == header) {
+            eprintln!(
+                "warning: couldn't find {} to rewrite its requires",
+                header
+            );
+        }
+    }
+    out
+}
@@ -0,0 +1,59 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Report on task stack usage.
+//!
+//! Jefe keeps the peak stack usage of each task, as measured by the kernel, in
+//! an array at the symbol `STACK_USAGE` (see `task/jefe/src/stacks.rs`). Given
+//! a raw dump of that symbol -- e.g. from GDB, with `dump binary value
+//! stacks.bin STACK_USAGE` -- this compares each task's usage with the
+//! `stacksize` it's given in the image configuration.
+
+use std::path::Path;
+
+use anyhow::{bail, Result};
+
+use crate::dump;
+
+/// Value Jefe records for tasks whose stack usage is unknown.
+const UNKNOWN: u32 = u32::MAX;
+
+/// Fraction of its stack beyond which a task is flagged as being at risk.
+const WARN_PERCENT: u32 = 90;
+
+pub fn run(cfg: &Path, dump: &Path) -> Result<()> {
+    let toml = dump::load_config(cfg)?;
+    let usage =
+        dump::read_task_table(dump, "STACK_USAGE", toml.tasks.len(), 4)?;
+
+    println!(
+        "{:<16} {:>9} {:>9} {:>5}",
+        "TASK", "STACKSIZE", "PEAK", "USED"
+    );
+    for (i, (name, task)) in toml.tasks.iter().enumerate() {
+        let peak = dump::u32_at(&usage[i], 0);
+        let stacksize = match task.stacksize.or(toml.stacksize) {
+            Some(s) => s,
+            None => bail!("task {} has no stacksize", name),
+        };
+
+        if peak == UNKNOWN {
+            println!("{:<16} {:>9} {:>9}", name, stacksize, "-");
+            continue;
+        }
+
+        let percent = u64::from(peak) * 100 / u64::from(stacksize.max(1));
+        let warning = if percent >= u64::from(WARN_PERCENT) {
+            "  <- consider a larger stacksize"
+        } else {
+            ""
+        };
+        println!(
+            "{:<16} {:>9} {:>9} {:>4}%{}",
+            name, stacksize, peak, percent, warning
+        );
+    }
+
+    Ok(())
+}
@@ -2,21 +2,57 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use std::io::{BufRead, BufReader};
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::time::Duration;
 
-use anyhow::Context;
+use anyhow::{bail, Context};
 
+use crate::dist::HOSTED_TARGET;
 use crate::Config;
 
+/// How long we'll wait for the test runner to make progress -- that is, to
+/// emit its next line of output -- before declaring the image hung.
+const QEMU_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Returns the QEMU machine that emulates `board`, if there is one.
+fn qemu_machine(board: &str) -> Option<&'static str> {
+    match board {
+        "mps2-an386" => Some("mps2-an386"),
+        _ => None,
+    }
+}
+
+/// Checks whether the image described by `cfg` is tested under emulation
+/// (or simulation), rather than on hardware attached through a probe.
+pub fn is_emulated(cfg: &Path) -> anyhow::Result<bool> {
+    let cfg_contents = std::fs::read(&cfg)?;
+    let toml: Config = toml::from_slice(&cfg_contents)?;
+
+    Ok(qemu_machine(&toml.board).is_some() || toml.target == HOSTED_TARGET)
+}
+
 pub fn run(verbose: bool, cfg: &Path) -> anyhow::Result<()> {
     let cfg_contents = std::fs::read(&cfg)?;
     let toml: Config = toml::from_slice(&cfg_contents)?;
 
-    let mut archive = PathBuf::from("target");
-    archive.push(&toml.name);
-    archive.push("dist");
-    archive.push(format!("build-{}.zip", &toml.name));
+    let mut dist_dir = PathBuf::from("target");
+    dist_dir.push(&toml.name);
+    dist_dir.push("dist");
+
+    if let Some(machine) = qemu_machine(&toml.board) {
+        return run_qemu(verbose, machine, &dist_dir.join("final.elf"));
+    }
+
+    // Hosted applications don't include the test suite; the best we can do
+    // is a smoke test.
+    if toml.target == HOSTED_TARGET {
+        return crate::run::run(verbose, cfg, true);
+    }
+
+    let archive = dist_dir.join(format!("build-{}.zip", &toml.name));
 
     let mut humility = Command::new("humility");
     humility.arg("-a").arg(archive);
@@ -32,7 +68,139 @@ pub fn run(verbose: bool, cfg: &Path) -> anyhow::Result<()> {
         .with_context(|| format!("failed to run humility ({:?})", humility))?;
 
     if !status.success() {
-        anyhow::bail!("test failed");
+        bail!("test failed");
+    }
+
+    Ok(())
+}
+
+/// Boots `elf` on QEMU's emulation of `machine` and interprets the test
+/// runner's report, which it expects on the emulated serial port. (See
+/// `test/test-runner` for the format.)
+fn run_qemu(verbose: bool, machine: &str, elf: &Path) -> anyhow::Result<()> {
+    let mut qemu = Command::new("qemu-system-arm");
+    qemu.arg("-machine")
+        .arg(machine)
+        .arg("-display")
+        .arg("none")
+        .arg("-monitor")
+        .arg("none")
+        .arg("-serial")
+        .arg("stdio")
+        .arg("-semihosting-config")
+        .arg("enable=on,target=native")
+        .arg("-kernel")
+        .arg(elf)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped());
+
+    if verbose {
+        println!("running: {:?}", qemu);
+    }
+
+    let mut child = qemu
+        .spawn()
+        .with_context(|| format!("failed to run QEMU ({:?})", qemu))?;
+
+    // Lines are handed over from a separate thread so that we can give up on
+    // an image that has stopped talking to us.
+    let stdout = child.stdout.take().unwrap();
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            match line {
+                Ok(line) => {
+                    if tx.send(line).is_err() {
+                        break;
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+    });
+
+    let result = watch_report(verbose, &rx);
+
+    // Whatever happened, the image won't exit on its own.
+    let _ = child.kill();
+    let _ = child.wait();
+
+    result
+}
+
+/// Consumes lines of test runner output from `rx` until the suite is done,
+/// printing the results of each case as it goes.
+fn watch_report(
+    verbose: bool,
+    rx: &mpsc::Receiver<String>,
+) -> anyhow::Result<()> {
+    let mut expected = None;
+    let mut current = None;
+    let mut passed = 0;
+    let mut failed = vec![];
+
+    loop {
+        let line = match rx.recv_timeout(QEMU_TIMEOUT) {
+            Ok(line) => line,
+            Err(mpsc::RecvTimeoutError::Timeout) => match current {
+                Some(name) => bail!("test {} timed out", name),
+                None => bail!("timed out waiting for test runner"),
+            },
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                bail!("QEMU exited before the test suite completed")
+            }
+        };
+        let line = line.trim_end();
+
+        let (word, rest) = match line.split_once(' ') {
+            Some((word, rest)) => (word, rest),
+            None => (line, ""),
+        };
+
+        match word {
+            "meta" | "case" | "run" => {}
+            "expect" => {
+                let n: usize = rest
+                    .parse()
+                    .with_context(|| format!("bad test count in {:?}", line))?;
+                println!("running {} tests", n);
+                expected = Some(n);
+            }
+            "start" => {
+                current = Some(rest.to_string());
+            }
+            "finish" => {
+                let (status, name) = rest.split_once(' ').unwrap_or((rest, ""));
+                if status == "ok" {
+                    println!("test {} ... ok", name);
+                    passed += 1;
+                } else {
+                    println!("test {} ... FAILED", name);
+                    failed.push(name.to_string());
+                }
+                current = None;
+            }
+            "done" => break,
+            _ => {
+                // Anything else is logging from the kernel or tasks, sharing
+                // the console with us.
+                if verbose {
+                    println!("{}", line);
+                }
+            }
+        }
+    }
+
+    println!("test result: {} passed; {} failed", passed, failed.len());
+
+    if let Some(n) = expected {
+        if passed + failed.len() != n {
+            bail!("expected {} tests, but {} ran", n, passed + failed.len());
+        }
+    }
+
+    if !failed.is_empty() {
+        bail!("test failed: {}", failed.join(", "));
     }
 
     Ok(())
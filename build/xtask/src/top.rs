@@ -0,0 +1,80 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Report on which tasks are using the CPU.
+//!
+//! Jefe keeps a copy of the kernel's per-task CPU accounting in an array at the
+//! symbol `CPU_USAGE` (see `task/jefe/src/cpu.rs`). Given a raw dump of that
+//! symbol -- e.g. from GDB, with `dump binary value cpu.bin CPU_USAGE` -- this
+//! shows each task's share of the CPU since boot, busiest first. Given an
+//! earlier dump too, it shows shares over the time between the two instead,
+//! which is usually more interesting.
+
+use std::path::Path;
+
+use anyhow::{bail, Result};
+
+use crate::dump;
+
+/// Size of an `abi::CpuUsage`.
+const RECORD_SIZE: usize = 16;
+
+pub fn run(cfg: &Path, dump: &Path, since: Option<&Path>) -> Result<()> {
+    let toml = dump::load_config(cfg)?;
+    let names = dump::task_names(&toml);
+
+    let now = parse(dump, names.len())?;
+    let usage = match since {
+        Some(since) => {
+            let then = parse(since, names.len())?;
+            now.iter()
+                .zip(&then)
+                .map(|(now, then)| {
+                    if now.0 < then.0 {
+                        bail!(
+                            "CPU time went backwards; was {} dumped before {}?",
+                            since.display(),
+                            dump.display()
+                        );
+                    }
+                    Ok((now.0 - then.0, now.1.wrapping_sub(then.1)))
+                })
+                .collect::<Result<Vec<_>>>()?
+        }
+        None => now,
+    };
+
+    let total: u64 = usage.iter().map(|u| u.0).sum();
+    let mut order: Vec<usize> = (0..names.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(usage[i].0));
+
+    println!(
+        "{:<16} {:>6} {:>14} {:>10}",
+        "TASK", "CPU", "TIME", "SWITCHES"
+    );
+    for i in order {
+        let (time, switches) = usage[i];
+        let percent = if total == 0 {
+            0.0
+        } else {
+            time as f64 * 100.0 / total as f64
+        };
+        println!(
+            "{:<16} {:>5.1}% {:>14} {:>10}",
+            names[i], percent, time, switches
+        );
+    }
+
+    Ok(())
+}
+
+/// Parses a dump of `CPU_USAGE` into (time, switches) for each task.
+fn parse(dump: &Path, tasks: usize) -> Result<Vec<(u64, u32)>> {
+    Ok(
+        dump::read_task_table(dump, "CPU_USAGE", tasks, RECORD_SIZE)?
+            .iter()
+            .map(|r| (dump::u64_at(r, 0), dump::u32_at(r, 8)))
+            .collect(),
+    )
+}
@@ -0,0 +1,228 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Decoder for the kernel's trace buffer.
+//!
+//! A kernel built with the `trace` feature records events in a ring at the
+//! symbol `KERNEL_TRACE` (see `sys/kern/src/trace.rs`). Given a raw dump of
+//! that symbol -- e.g. from GDB, with `dump binary value trace.bin
+//! KERNEL_TRACE` -- this prints the events oldest-first, and then works out
+//! which tasks were left blocked, and on whom.
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::path::Path;
+
+use abi::{Sysnum, TraceFlags, TraceKind, TraceRecord, TRACE_NO_TASK};
+use anyhow::Result;
+
+use crate::dump::{self, Ring, RingFormat};
+
+const FORMAT: RingFormat = RingFormat {
+    what: "trace",
+    magic: abi::TRACE_MAGIC,
+    header_size: 16,
+    min_record_size: std::mem::size_of::<TraceRecord>(),
+    hint: "is this a dump of KERNEL_TRACE from a kernel built with the trace \
+           feature?",
+};
+
+pub fn run(cfg: &Path, dump: &Path) -> Result<()> {
+    let toml = dump::load_config(cfg)?;
+    let names = dump::task_names(&toml);
+
+    let bytes = std::fs::read(dump)?;
+    let records = parse(&bytes)?;
+
+    let task_name = |index: u16| -> String {
+        if index == TRACE_NO_TASK {
+            "-".to_string()
+        } else {
+            match names.get(usize::from(index)) {
+                Some(name) => name.to_string(),
+                None => format!("#{}", index),
+            }
+        }
+    };
+
+    println!("{:>12} {:<16} EVENT", "TICKS", "TASK");
+    for r in &records {
+        let event = match TraceKind::try_from(r.kind) {
+            Ok(TraceKind::Syscall) => {
+                let flags = TraceFlags::from_bits_truncate(r.flags);
+                let mut event = sysnum_name(r.arg).to_string();
+                if r.target != TRACE_NO_TASK {
+                    event += &format!(" {}", task_name(r.target));
+                }
+                if flags.contains(TraceFlags::FAULTED) {
+                    event += " (faulted)";
+                } else if flags.contains(TraceFlags::BLOCKED) {
+                    event += " (blocked)";
+                } else if r.value != 0 {
+                    event += &format!(" = {:#x}", r.value);
+                }
+                event
+            }
+            Ok(TraceKind::ContextSwitch) => {
+                format!("switch to {}", task_name(r.target))
+            }
+            Ok(TraceKind::Irq) => {
+                format!("irq {} notify {:#x}", r.arg, r.value)
+            }
+            Ok(TraceKind::Timer) => format!("timer notify {:#x}", r.value),
+            Ok(TraceKind::Fault) => "fault".to_string(),
+            Err(_) => format!("unknown event kind {}", r.kind),
+        };
+        println!("{:>12} {:<16} {}", r.timestamp, task_name(r.task), event);
+    }
+
+    let blocked = replay(&records);
+    println!();
+    if blocked.is_empty() {
+        println!("no tasks blocked at end of trace");
+        return Ok(());
+    }
+
+    println!("blocked at end of trace:");
+    for (task, wait) in &blocked {
+        let what = match wait {
+            Wait::Send(peer) => {
+                format!("waiting on {} (SEND)", task_name(*peer))
+            }
+            Wait::Recv(None) => "in RECV".to_string(),
+            Wait::Recv(Some(peer)) => {
+                format!("in RECV from {}", task_name(*peer))
+            }
+            Wait::Lock => "in LOCK".to_string(),
+            Wait::Faulted => "faulted".to_string(),
+        };
+        println!("    {:<16} {}", task_name(*task), what);
+    }
+
+    // Following SEND edges from each task will find any cycles, which
+    // can't resolve themselves.
+    for &start in blocked.keys() {
+        let mut chain = vec![start];
+        let mut task = start;
+        while let Some(Wait::Send(peer)) = blocked.get(&task) {
+            if *peer == start {
+                let chain: Vec<String> =
+                    chain.iter().map(|&t| task_name(t)).collect();
+                println!(
+                    "deadlock: {} -> {}",
+                    chain.join(" -> "),
+                    task_name(start)
+                );
+                break;
+            }
+            if chain.contains(peer) {
+                // A cycle that doesn't include `start`; it'll be reported
+                // when we start from one of its members.
+                break;
+            }
+            chain.push(*peer);
+            task = *peer;
+        }
+    }
+
+    Ok(())
+}
+
+/// Pulls the records out of a dump of `KERNEL_TRACE`, oldest first.
+fn parse(bytes: &[u8]) -> Result<Vec<TraceRecord>> {
+    let ring = Ring::parse(bytes, &FORMAT)?;
+    Ok(ring
+        .records()
+        .into_iter()
+        .map(|r| TraceRecord {
+            timestamp: dump::u64_at(r, 0),
+            kind: dump::u16_at(r, 8),
+            task: dump::u16_at(r, 10),
+            target: dump::u16_at(r, 12),
+            flags: dump::u16_at(r, 14),
+            arg: dump::u32_at(r, 16),
+            value: dump::u32_at(r, 20),
+        })
+        .collect())
+}
+
+/// Why a task was blocked.
+enum Wait {
+    /// In SEND to, or awaiting REPLY from, the given task.
+    Send(u16),
+    /// In RECV, possibly closed to a particular task.
+    Recv(Option<u16>),
+    /// In LOCK, waiting for another task to release a lock.
+    Lock,
+    /// Faulted, awaiting the supervisor.
+    Faulted,
+}
+
+/// Works out, from `records`, which tasks were blocked when the trace ended.
+///
+/// Tasks are only known to be unblocked once they're seen running, so this can
+/// only talk about tasks that appear in the trace.
+fn replay(records: &[TraceRecord]) -> BTreeMap<u16, Wait> {
+    let mut blocked = BTreeMap::new();
+
+    for r in records {
+        match TraceKind::try_from(r.kind) {
+            Ok(TraceKind::Syscall) => {
+                let flags = TraceFlags::from_bits_truncate(r.flags);
+                let peer = if r.target == TRACE_NO_TASK {
+                    None
+                } else {
+                    Some(r.target)
+                };
+                if flags.contains(TraceFlags::FAULTED) {
+                    blocked.insert(r.task, Wait::Faulted);
+                } else if flags.contains(TraceFlags::BLOCKED) {
+                    let wait = match (Sysnum::try_from(r.arg), peer) {
+                        (Ok(Sysnum::Send), Some(peer))
+                        | (Ok(Sysnum::SendTimeout), Some(peer)) => {
+                            Wait::Send(peer)
+                        }
+                        (Ok(Sysnum::Lock), _) => Wait::Lock,
+                        _ => Wait::Recv(peer),
+                    };
+                    blocked.insert(r.task, wait);
+                } else {
+                    blocked.remove(&r.task);
+                }
+            }
+            Ok(TraceKind::ContextSwitch) => {
+                blocked.remove(&r.target);
+            }
+            Ok(TraceKind::Fault) => {
+                blocked.insert(r.task, Wait::Faulted);
+            }
+            _ => (),
+        }
+    }
+
+    blocked
+}
+
+fn sysnum_name(nr: u32) -> &'static str {
+    match Sysnum::try_from(nr) {
+        Ok(Sysnum::Send) => "SEND",
+        Ok(Sysnum::Recv) => "RECV",
+        Ok(Sysnum::Reply) => "REPLY",
+        Ok(Sysnum::SetTimer) => "SET_TIMER",
+        Ok(Sysnum::BorrowRead) => "BORROW_READ",
+        Ok(Sysnum::BorrowWrite) => "BORROW_WRITE",
+        Ok(Sysnum::BorrowInfo) => "BORROW_INFO",
+        Ok(Sysnum::IrqControl) => "IRQ_CONTROL",
+        Ok(Sysnum::Panic) => "PANIC",
+        Ok(Sysnum::GetTimer) => "GET_TIMER",
+        Ok(Sysnum::RefreshTaskId) => "REFRESH_TASK_ID",
+        Ok(Sysnum::Post) => "POST",
+        Ok(Sysnum::SendAsync) => "SEND_ASYNC",
+        Ok(Sysnum::AsyncStatus) => "ASYNC_STATUS",
+        Ok(Sysnum::SendTimeout) => "SEND_TIMEOUT",
+        Ok(Sysnum::Lock) => "LOCK",
+        Ok(Sysnum::Unlock) => "UNLOCK",
+        Err(_) => "bad syscall",
+    }
+}
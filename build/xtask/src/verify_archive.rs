@@ -0,0 +1,160 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Checks that a build archive can be reproduced.
+//!
+//! Every build archive has a `manifest.json` recording the SHA-256 of the other
+//! files in it, and what went into the build: the commit, the toolchain, and
+//! the time its files were stamped with. Given the configuration it was built
+//! from, this checks the archive against its manifest, rebuilds the image the
+//! same way, and compares the two.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+
+use crate::dist::{self, sha256_hex};
+use crate::Config;
+
+/// The contents of `manifest.json`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Manifest {
+    version: u32,
+    name: String,
+    git_rev: String,
+    git_dirty: bool,
+    toolchain: String,
+    source_date_epoch: u64,
+    app_toml: String,
+    /// SHA-256 of each file, by path in the archive.
+    files: BTreeMap<String, String>,
+}
+
+pub fn run(verbose: bool, cfg: &Path, archive: &Path) -> Result<()> {
+    // Read this in before we rebuild, which may well overwrite it.
+    let old_bytes = std::fs::read(archive)
+        .with_context(|| format!("can't read {}", archive.display()))?;
+    let old = read_manifest(&old_bytes)
+        .with_context(|| format!("bad archive {}", archive.display()))?;
+
+    // Make sure we're in a position to reproduce it.
+    let cfg_contents = std::fs::read(cfg)?;
+    if sha256_hex(&cfg_contents) != old.app_toml {
+        bail!(
+            "{} isn't the configuration {} was built from",
+            cfg.display(),
+            archive.display()
+        );
+    }
+    let toml: Config = toml::from_slice(&cfg_contents)?;
+    if toml.name != old.name {
+        bail!("archive is for {}, not {}", old.name, toml.name);
+    }
+    let toolchain = dist::toolchain_version()?;
+    if toolchain != old.toolchain {
+        bail!(
+            "archive was built with {}, but this is {}",
+            old.toolchain,
+            toolchain
+        );
+    }
+    let (git_rev, git_dirty) = dist::get_git_status()?;
+    if git_rev != old.git_rev || git_dirty != old.git_dirty {
+        eprintln!(
+            "warning: archive was built from {}{}, but this is {}{}; expect \
+             differences",
+            old.git_rev,
+            if old.git_dirty { "-dirty" } else { "" },
+            git_rev,
+            if git_dirty { "-dirty" } else { "" },
+        );
+    }
+
+    std::env::set_var("SOURCE_DATE_EPOCH", old.source_date_epoch.to_string());
+    dist::package(verbose, false, cfg, None)?;
+
+    let mut new_path = PathBuf::from("target");
+    new_path.push(&toml.name);
+    new_path.push("dist");
+    new_path.push(format!("build-{}.zip", toml.name));
+    let new_bytes = std::fs::read(&new_path)?;
+    let new = read_manifest(&new_bytes)?;
+
+    let paths = old
+        .files
+        .keys()
+        .chain(new.files.keys())
+        .collect::<BTreeSet<_>>();
+    let mut differences = 0;
+    for path in paths {
+        let problem = match (old.files.get(path), new.files.get(path)) {
+            (Some(a), Some(b)) if a == b => continue,
+            (Some(_), Some(_)) => "differs",
+            (Some(_), None) => "is missing from the rebuild",
+            (None, _) => "is only in the rebuild",
+        };
+        println!("{} {}", path, problem);
+        differences += 1;
+    }
+    if differences != 0 {
+        bail!(
+            "{} file(s) in {} differ from the rebuild",
+            differences,
+            archive.display()
+        );
+    }
+
+    if old_bytes == new_bytes {
+        println!("rebuild matches {} exactly", archive.display());
+    } else {
+        // This can happen if the archive was written by a different version
+        // of this tool.
+        println!(
+            "rebuild matches the contents of {}, but not its layout",
+            archive.display()
+        );
+    }
+    Ok(())
+}
+
+/// Reads the manifest from the build archive in `bytes`, and checks that every
+/// file in the archive matches it.
+fn read_manifest(bytes: &[u8]) -> Result<Manifest> {
+    let mut zip = zip::ZipArchive::new(Cursor::new(bytes))?;
+
+    let mut text = String::new();
+    zip.by_name("manifest.json")
+        .map_err(|_| anyhow!("no manifest.json; is it from an older build?"))?
+        .read_to_string(&mut text)?;
+    let manifest: Manifest = serde_json::from_str(&text)?;
+    if manifest.version != 1 {
+        bail!("unknown manifest version {}", manifest.version);
+    }
+
+    for i in 0..zip.len() {
+        let mut file = zip.by_index(i)?;
+        let name = file.name().to_string();
+        if name == "manifest.json" {
+            continue;
+        }
+        let mut contents = vec![];
+        file.read_to_end(&mut contents)?;
+        match manifest.files.get(&name) {
+            Some(hash) if *hash == sha256_hex(&contents) => (),
+            Some(_) => bail!("{} doesn't match the manifest", name),
+            None => bail!("{} isn't in the manifest", name),
+        }
+    }
+    for name in manifest.files.keys() {
+        if zip.by_name(name).is_err() {
+            bail!("{} is in the manifest, but not the archive", name);
+        }
+    }
+
+    Ok(manifest)
+}
@@ -41,6 +41,8 @@ pub enum Op {
 pub enum ResponseCode {
     /// Server has died
     Dead = core::u32::MAX,
+    /// Server did not reply within the device's timeout
+    TimedOut = SEND_TIMED_OUT,
     /// Bad response from server
     BadResponse = 1,
     /// Bad argument sent to server
@@ -177,6 +179,7 @@ pub struct I2cDevice {
     pub port: PortIndex,
     pub segment: Option<(Mux, Segment)>,
     pub address: u8,
+    pub timeout: Option<u64>,
 }
 
 type I2cMessage = (u8, Controller, PortIndex, Option<(Mux, Segment)>);
@@ -259,6 +262,40 @@ impl I2cDevice {
             port: port,
             segment: segment,
             address: address,
+            timeout: None,
+        }
+    }
+
+    ///
+    /// Returns a copy of this [`I2cDevice`] whose operations will give up
+    /// with [`ResponseCode::TimedOut`] if the I2C server hasn't replied
+    /// within `ticks` of their being issued, e.g. because the bus is stuck.
+    /// Without a timeout, operations wait for as long as the server takes.
+    ///
+    pub fn with_timeout(self, ticks: u64) -> Self {
+        Self {
+            timeout: Some(ticks),
+            ..self
+        }
+    }
+
+    fn send(
+        &self,
+        operation: u16,
+        outgoing: &[u8],
+        incoming: &mut [u8],
+        leases: &[Lease<'_>],
+    ) -> (u32, usize) {
+        match self.timeout {
+            None => sys_send(self.task, operation, outgoing, incoming, leases),
+            Some(ticks) => sys_send_with_deadline(
+                self.task,
+                operation,
+                outgoing,
+                incoming,
+                leases,
+                sys_get_timer().now + ticks,
+            ),
         }
     }
 }
@@ -294,8 +331,7 @@ impl I2cDevice {
         let mut val = V::default();
         let mut response = 0_usize;
 
-        let (code, _) = sys_send(
-            self.task,
+        let (code, _) = self.send(
             Op::WriteRead as u16,
             &Marshal::marshal(&(
                 self.address,
@@ -327,8 +363,7 @@ impl I2cDevice {
     ) -> Result<usize, ResponseCode> {
         let mut response = 0_usize;
 
-        let (code, _) = sys_send(
-            self.task,
+        let (code, _) = self.send(
             Op::WriteRead as u16,
             &Marshal::marshal(&(
                 self.address,
@@ -362,8 +397,7 @@ impl I2cDevice {
     ) -> Result<usize, ResponseCode> {
         let mut response = 0_usize;
 
-        let (code, _) = sys_send(
-            self.task,
+        let (code, _) = self.send(
             Op::WriteReadBlock as u16,
             &Marshal::marshal(&(
                 self.address,
@@ -397,8 +431,7 @@ impl I2cDevice {
         let mut val = V::default();
         let mut response = 0_usize;
 
-        let (code, _) = sys_send(
-            self.task,
+        let (code, _) = self.send(
             Op::WriteRead as u16,
             &Marshal::marshal(&(
                 self.address,
@@ -427,8 +460,7 @@ impl I2cDevice {
         let empty = [0u8; 1];
         let mut response = 0_usize;
 
-        let (code, _) = sys_send(
-            self.task,
+        let (code, _) = self.send(
             Op::WriteRead as u16,
             &Marshal::marshal(&(
                 self.address,
@@ -456,8 +488,7 @@ impl I2cDevice {
         let empty = [0u8; 1];
         let mut response = 0_usize;
 
-        let (code, _) = sys_send(
-            self.task,
+        let (code, _) = self.send(
             Op::WriteRead as u16,
             &Marshal::marshal(&(
                 self.address,
@@ -125,17 +125,16 @@ pub fn begin_bitstream_load(
     // Lock SPI controller and assert CS.
     spi.lock(spi_api::CsState::Asserted)?;
 
-    // Minimum duration of reset pulse is 200ns. One of our 1ms ticks will be
-    // fine.
-    hl::sleep_for(1);
+    // Minimum duration of reset pulse is 200ns.
+    hl::sleep_for_micros(1);
 
     // Deassert reset (active low).
     gpio.set_reset(config.creset_port, config.creset_pin_mask, 0)
         .unwrap();
 
     // Minimum time to stabilize here is either 300us or 800us, depending on
-    // which Lattice doc you're reading. Give it 2ms to be sure.
-    hl::sleep_for(2);
+    // which Lattice doc you're reading. Give it 1ms to be sure.
+    hl::sleep_for_micros(1_000);
 
     // At this point, the iCE40 is _supposed_ to be chilling in programming mode
     // listening for a bitstream. If this is the case it will be asserting
@@ -479,6 +479,37 @@ fn led_toggle(led: Led) {
     gpio_driver.toggle(pin).unwrap();
 }
 
+///////////////////////////////////////////////////////////////////////////////
+// The hosted (simulation) bits.
+//
+// There are no LEDs to drive, so we keep their states in a word, where they can
+// at least be seen from a debugger.
+
+#[cfg(feature = "hosted")]
+static LED_STATE: core::sync::atomic::AtomicU32 =
+    core::sync::atomic::AtomicU32::new(0);
+
+#[cfg(feature = "hosted")]
+fn enable_led_pins() {}
+
+#[cfg(feature = "hosted")]
+fn led_on(led: Led) {
+    use core::sync::atomic::Ordering;
+    LED_STATE.fetch_or(1 << led as u32, Ordering::Relaxed);
+}
+
+#[cfg(feature = "hosted")]
+fn led_off(led: Led) {
+    use core::sync::atomic::Ordering;
+    LED_STATE.fetch_and(!(1 << led as u32), Ordering::Relaxed);
+}
+
+#[cfg(feature = "hosted")]
+fn led_toggle(led: Led) {
+    use core::sync::atomic::Ordering;
+    LED_STATE.fetch_xor(1 << led as u32, Ordering::Relaxed);
+}
+
 mod idl {
     use super::LedError;
 
@@ -47,6 +47,10 @@ impl TaskId {
     /// Reserved TaskId for an unbound userlib::task_slot!()
     pub const UNBOUND: Self = Self(Self::INDEX_MASK - 1);
 
+    /// The supervisor is always task index 0, and (being the one that restarts
+    /// other tasks) is itself never restarted, so its `TaskId` is fixed.
+    pub const SUPERVISOR: Self = Self(0);
+
     /// Number of bits in a `TaskId` used to represent task index, rather than
     /// generation number. This must currently be 15 or smaller.
     pub const INDEX_BITS: u32 = 10;
@@ -95,6 +99,12 @@ impl From<u8> for Generation {
     }
 }
 
+impl From<Generation> for u8 {
+    fn from(x: Generation) -> Self {
+        x.0
+    }
+}
+
 /// Indicates priority of a task.
 ///
 /// Priorities are small numbers starting from zero. Numerically lower
@@ -142,10 +152,12 @@ pub struct App {
     pub irq_count: u32,
     /// Bitmask to post to task 0 when any task faults.
     pub fault_notification: u32,
+    /// Number of lock records that will follow the `Interrupt` records.
+    pub lock_count: u32,
 
     /// Reserved expansion space; pads this structure out to 32 bytes. You will
     /// need to adjust this when you add fields above.
-    pub zeroed_expansion_space: [u8; 32 - (5 * 4)],
+    pub zeroed_expansion_space: [u8; 32 - (6 * 4)],
 }
 
 /// Record describing a single task.
@@ -172,6 +184,28 @@ pub struct TaskDesc {
     pub priority: u32,
     /// Collection of boolean flags controlling task behavior.
     pub flags: TaskFlags,
+    /// If `flags` contains `RESTRICT_IPC`, the tasks this task may send
+    /// messages or post notifications to, as a bitmap by task index: task `i`
+    /// is bit `i % 32` of word `i / 32`. Otherwise, this should be all zeroes.
+    pub ipc_allow: [u32; IPC_ALLOW_WORDS],
+}
+
+/// Number of words in the `TaskDesc::ipc_allow` bitmap. A task whose IPC is
+/// restricted can only be allowed to talk to tasks with indices below
+/// `32 * IPC_ALLOW_WORDS`.
+pub const IPC_ALLOW_WORDS: usize = 2;
+
+impl TaskDesc {
+    /// Checks whether this task may send to, or post notifications to, the
+    /// task at `index`.
+    pub fn may_send_to(&self, index: usize) -> bool {
+        if !self.flags.contains(TaskFlags::RESTRICT_IPC) {
+            return true;
+        }
+        self.ipc_allow
+            .get(index / 32)
+            .map_or(false, |word| word & (1 << (index % 32)) != 0)
+    }
 }
 
 bitflags::bitflags! {
@@ -179,7 +213,10 @@ bitflags::bitflags! {
     #[repr(transparent)]
     pub struct TaskFlags: u32 {
         const START_AT_BOOT = 1 << 0;
-        const RESERVED = !1;
+        /// Task may only send to (or post to) the tasks in its `ipc_allow`
+        /// bitmap.
+        const RESTRICT_IPC = 1 << 1;
+        const RESERVED = !0b11;
     }
 }
 
@@ -201,7 +238,9 @@ pub struct RegionDesc {
     pub base: u32,
     /// Size of region, in bytes. The platform likely has alignment requirements
     /// for this; it must meet them. (For example, on ARMv7-M, it must be a
-    /// power of two greater than 16.)
+    /// power of two greater than 16, or a whole number of eighths of a power of
+    /// two of at least 256, in which case the base must be aligned for that
+    /// power of two.)
     pub size: u32,
     /// Flags describing what can be done with this region.
     pub attributes: RegionAttributes,
@@ -246,6 +285,25 @@ pub struct Interrupt {
     pub notification: u32,
 }
 
+/// Description of one lock, which tasks can take and release with the `LOCK`
+/// and `UNLOCK` syscalls to share a resource without going through a server.
+#[derive(Clone, Debug, FromBytes)]
+#[repr(C)]
+pub struct LockDesc {
+    /// The tasks that may take this lock, as a bitmap by task index in the
+    /// same format as `TaskDesc::ipc_allow`.
+    pub users: [u32; IPC_ALLOW_WORDS],
+}
+
+impl LockDesc {
+    /// Checks whether the task at `index` may take this lock.
+    pub fn may_be_used_by(&self, index: usize) -> bool {
+        self.users
+            .get(index / 32)
+            .map_or(false, |word| word & (1 << (index % 32)) != 0)
+    }
+}
+
 /// Structure describing a lease in task memory.
 ///
 /// At SEND, the task gives us the base and length of a section of memory that
@@ -297,6 +355,64 @@ pub const fn extract_new_generation(code: u32) -> Option<Generation> {
 /// Response code returned by the kernel if a lender has defected.
 pub const DEFECT: u32 = 1;
 
+/// Number of asynchronous sends (`SEND_ASYNC`) that each task can have
+/// outstanding at once.
+pub const ASYNC_SENDS_PER_TASK: usize = 2;
+
+/// Response code returned by the kernel from `SEND_ASYNC` if all of the
+/// caller's asynchronous send slots are in use.
+pub const ASYNC_QUEUE_FULL: u32 = 0xffff_fe00;
+
+/// Response code returned by the kernel from `SEND_ASYNC` if the caller already
+/// has an asynchronous send outstanding to the same task. (Only one is allowed,
+/// so that the peer's REPLY is unambiguous.)
+pub const ASYNC_PEER_BUSY: u32 = 0xffff_fe01;
+
+/// Response code returned by the kernel from `SEND_TIMEOUT` if the caller's
+/// timer fired before the callee replied -- and from any send to a callee that
+/// hasn't yet replied to a message that timed out.
+pub const SEND_TIMED_OUT: u32 = 0xffff_fe02;
+
+/// Response code returned by the kernel from `LOCK` if the lock was acquired,
+/// but its previous owner faulted or was restarted while holding it, so
+/// whatever it protects may be in an inconsistent state.
+pub const LOCK_OWNER_DIED: u32 = 0xffff_fe03;
+
+/// Response code returned by the kernel from `LOCK` with `LOCK_TRY` if the lock
+/// is held by another task.
+pub const LOCK_BUSY: u32 = 0xffff_fe04;
+
+/// Bit in the second argument to `LOCK` that asks the kernel to return
+/// `LOCK_BUSY` instead of blocking if the lock is held.
+pub const LOCK_TRY: u32 = 1 << 0;
+
+/// Length of a kernel tick, in microseconds.
+///
+/// Every application configures the tick as one millisecond (the
+/// `tick_divisor` passed to `start_kernel` is the number of CPU cycles per
+/// millisecond), and tasks think of time in those units. The kernel only relies
+/// on this to offer finer-grained timers.
+pub const MICROS_PER_TICK: u64 = 1000;
+
+/// Bit in the first argument to `SET_TIMER` that enables the timer.
+pub const TIMER_ENABLE: u32 = 1 << 0;
+
+/// Bit in the first argument to `SET_TIMER` that says the deadline is given in
+/// microseconds since boot, rather than in ticks.
+pub const TIMER_MICROS: u32 = 1 << 1;
+
+/// Outcome of an asynchronous send, as reported by the `ASYNC_STATUS` syscall.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[repr(u32)]
+pub enum AsyncOutcome {
+    /// The reply has arrived; the slot has been freed.
+    Complete = 0,
+    /// The send is still waiting to be received or replied to.
+    Pending = 1,
+    /// The send was cancelled at the caller's request.
+    Cancelled = 2,
+}
+
 /// State used to make scheduling decisions.
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub enum TaskState {
@@ -363,6 +479,8 @@ pub enum SchedState {
     /// This task is blocked waiting for messages, either from any source
     /// (`None`) or from a particular sender only.
     InRecv(Option<TaskId>),
+    /// This task is blocked waiting to take the lock with the given index.
+    InLock(u32),
 }
 
 impl From<SchedState> for TaskState {
@@ -417,6 +535,162 @@ impl From<UsageError> for FaultInfo {
     }
 }
 
+/// How much CPU a task has used since boot, as reported by the kernel.
+#[derive(
+    Copy,
+    Clone,
+    Debug,
+    Default,
+    Eq,
+    PartialEq,
+    FromBytes,
+    AsBytes,
+    Serialize,
+    Deserialize,
+)]
+#[repr(C)]
+pub struct CpuUsage {
+    /// Time the task has spent running, including time the kernel spent
+    /// working on its behalf, in ticks of the architecture's cycle counter:
+    /// CPU cycles on ARM, nanoseconds when hosted.
+    pub time: u64,
+    /// Number of times the task has been switched to.
+    pub switches: u32,
+    pub reserved: u32,
+}
+
+/// What the kernel has seen of an interrupt since boot, when it's built with
+/// the `irq-stats` feature.
+///
+/// Times are in ticks of the architecture's cycle counter, as for `CpuUsage`.
+#[derive(
+    Copy,
+    Clone,
+    Debug,
+    Default,
+    Eq,
+    PartialEq,
+    FromBytes,
+    AsBytes,
+    Serialize,
+    Deserialize,
+)]
+#[repr(C)]
+pub struct IrqStats {
+    /// Number of times the interrupt has fired.
+    pub count: u32,
+    /// Longest time from the interrupt firing to its task being switched to.
+    pub max_wake: u32,
+    /// Total time from the interrupt firing to its task being switched to,
+    /// over every time it has fired.
+    pub total_wake: u64,
+    /// Longest time the interrupt has stayed disabled after firing, before its
+    /// task re-enabled it.
+    pub max_masked: u32,
+    pub reserved: u32,
+    /// Total time the interrupt has stayed disabled after firing, over every
+    /// time it has fired.
+    pub total_masked: u64,
+}
+
+/// Most bytes of a panic message that the kernel keeps in a `FaultDetail`.
+/// Every task has one, in kernel RAM, so this is kept short.
+pub const PANIC_MESSAGE_MAX: usize = 32;
+
+/// Most bytes of a panic's location (`file:line:column`) that the kernel keeps
+/// in a `FaultDetail`, separately from the message, so that a long message
+/// doesn't crowd it out. A location that doesn't fit loses its start.
+pub const PANIC_LOCATION_MAX: usize = 32;
+
+/// What the kernel knows about a task's most recent fault beyond its
+/// `FaultInfo`, for post-mortem tooling. This survives the task being
+/// restarted, and is replaced when it next faults.
+#[derive(Copy, Clone, Debug, FromBytes, AsBytes)]
+#[repr(C)]
+pub struct FaultDetail {
+    /// Program counter at the fault, if `pc_valid` is nonzero.
+    pub pc: u32,
+    /// Nonzero if `pc` is known, which it is only for processor faults that
+    /// left an intact exception frame on the task's stack.
+    pub pc_valid: u8,
+    /// Number of bytes of `message` that are valid.
+    pub message_len: u8,
+    /// Number of bytes of `location` that are valid.
+    pub location_len: u8,
+    pub reserved: u8,
+    /// For a panic, as much of the message as fits.
+    pub message: [u8; PANIC_MESSAGE_MAX],
+    /// For a panic, as much of the end of its location as fits.
+    pub location: [u8; PANIC_LOCATION_MAX],
+}
+
+impl FaultDetail {
+    /// A `FaultDetail` that says nothing.
+    pub const EMPTY: Self = Self {
+        pc: 0,
+        pc_valid: 0,
+        message_len: 0,
+        location_len: 0,
+        reserved: 0,
+        message: [0; PANIC_MESSAGE_MAX],
+        location: [0; PANIC_LOCATION_MAX],
+    };
+
+    /// Records that the fault happened at `pc`.
+    pub fn with_pc(pc: u32) -> Self {
+        Self {
+            pc,
+            pc_valid: 1,
+            ..Self::EMPTY
+        }
+    }
+
+    /// Records as much of the panic message `message` as fits. This expects
+    /// the message as `userlib` formats it, `panicked at 'msg', file:line:col`,
+    /// and keeps the location separately if it can find it.
+    pub fn with_message(message: &[u8]) -> Self {
+        const SEPARATOR: &[u8] = b"', ";
+        let (message, location) = match message
+            .windows(SEPARATOR.len())
+            .rposition(|w| w == SEPARATOR)
+        {
+            Some(i) => (&message[..i], &message[i + SEPARATOR.len()..]),
+            None => (message, &[][..]),
+        };
+
+        let mut detail = Self::EMPTY;
+        let len = message.len().min(PANIC_MESSAGE_MAX);
+        detail.message[..len].copy_from_slice(&message[..len]);
+        detail.message_len = len as u8;
+        let len = location.len().min(PANIC_LOCATION_MAX);
+        detail.location[..len]
+            .copy_from_slice(&location[location.len() - len..]);
+        detail.location_len = len as u8;
+        detail
+    }
+
+    pub fn pc(&self) -> Option<u32> {
+        if self.pc_valid != 0 {
+            Some(self.pc)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the start of the panic message, which may have been cut short.
+    pub fn message(&self) -> &[u8] {
+        let len = usize::from(self.message_len).min(PANIC_MESSAGE_MAX);
+        &self.message[..len]
+    }
+
+    /// Returns the end of the panic's location, which may have been cut
+    /// short, or nothing if it isn't known.
+    pub fn location(&self) -> &[u8] {
+        let len = usize::from(self.location_len).min(PANIC_LOCATION_MAX);
+        &self.location[..len]
+    }
+}
+
 /// A kernel-defined fault, arising from how a user task behaved.
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub enum UsageError {
@@ -438,6 +712,22 @@ pub enum UsageError {
     OffsetOutOfRange,
     NoIrq,
     BadKernelMessage,
+    /// A program named an asynchronous send slot that is out of range or not
+    /// in use.
+    BadAsyncSlot,
+    /// A program attempted a synchronous SEND to a task that it already has an
+    /// asynchronous send outstanding to; the peer's REPLY would be ambiguous.
+    AsyncSendOutstanding,
+    /// A program attempted to send to, or post to, a task that the application
+    /// doesn't allow it to talk to.
+    IpcNotPermitted,
+    /// A program named a lock that doesn't exist, or that the application
+    /// doesn't allow it to use.
+    LockOutOfRange,
+    /// A program attempted to take a lock that it already holds.
+    LockAlreadyHeld,
+    /// A program attempted to release a lock that it doesn't hold.
+    LockNotHeld,
 }
 
 /// Origin of a fault.
@@ -464,6 +754,11 @@ pub enum Sysnum {
     GetTimer = 9,
     RefreshTaskId = 10,
     Post = 11,
+    SendAsync = 12,
+    AsyncStatus = 13,
+    SendTimeout = 14,
+    Lock = 15,
+    Unlock = 16,
 }
 
 /// We're using an explicit `TryFrom` impl for `Sysnum` instead of
@@ -486,7 +781,157 @@ impl core::convert::TryFrom<u32> for Sysnum {
             9 => Ok(Self::GetTimer),
             10 => Ok(Self::RefreshTaskId),
             11 => Ok(Self::Post),
+            12 => Ok(Self::SendAsync),
+            13 => Ok(Self::AsyncStatus),
+            14 => Ok(Self::SendTimeout),
+            15 => Ok(Self::Lock),
+            16 => Ok(Self::Unlock),
             _ => Err(()),
         }
     }
 }
+
+/// Magic number at the start of the kernel's trace buffer (`KERNEL_TRACE`,
+/// present when the kernel is built with the `trace` feature), to reassure
+/// tools that they've found the right thing.
+pub const TRACE_MAGIC: u32 = 0x7ace_b0f0;
+
+/// Task index recorded in trace records when there is no task to name (for
+/// instance, a SEND to the kernel).
+pub const TRACE_NO_TASK: u16 = 0xFFFF;
+
+/// Header of the kernel's trace buffer. An array of `TraceRecord`s, `capacity`
+/// long, immediately follows it.
+#[derive(Copy, Clone, Debug, FromBytes, AsBytes)]
+#[repr(C)]
+pub struct TraceHeader {
+    /// Should have the value `TRACE_MAGIC`.
+    pub magic: u32,
+    /// Size of each record, in bytes, in case `TraceRecord` grows.
+    pub record_size: u32,
+    /// Number of records in the ring.
+    pub capacity: u32,
+    /// Number of records written since boot, wrapping. The next record will be
+    /// written at index `count % capacity`.
+    pub count: u32,
+}
+
+/// A single event recorded in the kernel's trace buffer.
+///
+/// The meaning of the `task`, `target`, `arg` and `value` fields depends on
+/// the `kind`; see `TraceKind`.
+#[derive(Copy, Clone, Debug, FromBytes, AsBytes)]
+#[repr(C)]
+pub struct TraceRecord {
+    /// Kernel time at which the event happened, in ticks.
+    pub timestamp: u64,
+    /// A `TraceKind`.
+    pub kind: u16,
+    /// Index of the task the event happened to, or `TRACE_NO_TASK`.
+    pub task: u16,
+    /// Index of the other task involved, or `TRACE_NO_TASK`.
+    pub target: u16,
+    /// Collection of `TraceFlags`.
+    pub flags: u16,
+    pub arg: u32,
+    pub value: u32,
+}
+
+impl TraceRecord {
+    /// An all-zeroes record, which has no valid `kind`.
+    pub const EMPTY: Self = Self {
+        timestamp: 0,
+        kind: 0,
+        task: 0,
+        target: 0,
+        flags: 0,
+        arg: 0,
+        value: 0,
+    };
+}
+
+/// Kinds of events recorded in the kernel's trace buffer.
+#[repr(u16)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TraceKind {
+    /// `task` made syscall number `arg`. `target` is the peer it named, if
+    /// any. `value` is the response code if the syscall failed recoverably, or
+    /// zero otherwise; `flags` says whether it faulted or left `task` blocked.
+    Syscall = 1,
+    /// The kernel switched from running `task` to running `target`.
+    ContextSwitch = 2,
+    /// Interrupt `arg` was dispatched to `task` as notification bits `value`.
+    Irq = 3,
+    /// A timer set by `task` fired, posting notification bits `value`.
+    Timer = 4,
+    /// `task` was marked as faulted.
+    Fault = 5,
+}
+
+impl core::convert::TryFrom<u16> for TraceKind {
+    type Error = ();
+
+    fn try_from(x: u16) -> Result<Self, Self::Error> {
+        match x {
+            1 => Ok(Self::Syscall),
+            2 => Ok(Self::ContextSwitch),
+            3 => Ok(Self::Irq),
+            4 => Ok(Self::Timer),
+            5 => Ok(Self::Fault),
+            _ => Err(()),
+        }
+    }
+}
+
+bitflags::bitflags! {
+    pub struct TraceFlags: u16 {
+        /// The syscall left the calling task blocked.
+        const BLOCKED = 1 << 0;
+        /// The syscall caused the calling task to fault.
+        const FAULTED = 1 << 1;
+    }
+}
+
+/// Magic number at the start of the supervisor's fault log (`FAULT_LOG`), to
+/// tell it apart from uninitialized RAM after a cold boot, and to reassure
+/// tools that they've found the right thing.
+pub const FAULT_LOG_MAGIC: u32 = 0xfa17_1095;
+
+/// Number of bytes set aside in a `FaultRecord` for the fault itself.
+pub const FAULT_RECORD_INFO_SIZE: usize = 16;
+
+/// Header of the supervisor's fault log. An array of `FaultRecord`s,
+/// `capacity` long, immediately follows it.
+#[derive(Copy, Clone, Debug, FromBytes, AsBytes)]
+#[repr(C)]
+pub struct FaultLogHeader {
+    /// Should have the value `FAULT_LOG_MAGIC`.
+    pub magic: u32,
+    /// Size of each record, in bytes, in case `FaultRecord` grows.
+    pub record_size: u32,
+    /// Number of records in the ring.
+    pub capacity: u32,
+    /// Number of records written since the log was last cleared. The next
+    /// record will be written at index `count % capacity`.
+    pub count: u32,
+    /// Number of times the system has booted since the log was created.
+    pub boots: u32,
+    pub reserved: u32,
+}
+
+/// A task fault, as recorded in the supervisor's fault log.
+#[derive(Copy, Clone, Debug, FromBytes, AsBytes)]
+#[repr(C)]
+pub struct FaultRecord {
+    /// Kernel time at which the supervisor noticed the fault, in ticks since
+    /// boot.
+    pub timestamp: u64,
+    /// Value of the header's `boots` at the time, i.e. which boot this was.
+    pub boot: u32,
+    /// Index of the task that faulted.
+    pub task: u16,
+    /// Generation of the task that faulted.
+    pub generation: u16,
+    /// The `FaultInfo`, serialized with `ssmarshal` and padded with zeroes.
+    pub fault: [u8; FAULT_RECORD_INFO_SIZE],
+}
@@ -2,13 +2,87 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use serde::Deserialize;
 use std::env;
 use std::fs::File;
 use std::io::Write;
 use std::path::PathBuf;
 
+/// The parts of the app-wide configuration that the kernel cares about.
+#[derive(Debug, Default, Deserialize)]
+struct AppConfig {
+    #[serde(default)]
+    sleep: SleepConfig,
+}
+
+/// How the processor sleeps while the idle task waits for an interrupt, from
+/// `[config.sleep]`. This depends on the chip, and on how the board has set up
+/// its clocks, so it's configured per application.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct SleepConfig {
+    #[serde(default)]
+    mode: SleepMode,
+    /// Frequency, in kHz, of SysTick's external reference clock, if SysTick
+    /// should count that rather than the processor clock.
+    systick_reference_khz: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum SleepMode {
+    /// Ordinary sleep, which only stops the processor clock.
+    Sleep,
+    /// The chip's deep sleep mode.
+    Deep,
+}
+
+impl Default for SleepMode {
+    fn default() -> Self {
+        SleepMode::Sleep
+    }
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    build_util::expose_m_profile();
+    // The hosted (simulation) backend has no M-profile to speak of.
+    if env::var("CARGO_CFG_TARGET_OS").unwrap() == "none" {
+        build_util::expose_m_profile();
+    }
+
+    // Most applications have no app-wide configuration at all.
+    println!("cargo:rerun-if-env-changed=HUBRIS_APP_CONFIG");
+    let config = if env::var_os("HUBRIS_APP_CONFIG").is_some() {
+        build_util::config::<AppConfig>()?
+    } else {
+        AppConfig::default()
+    };
+    let sleep = config.sleep;
+
+    // The kernel's clock is SysTick, so it only keeps time if SysTick keeps
+    // counting while the processor sleeps. Every chip we know of stops the
+    // processor clock in deep sleep, so deep sleep needs SysTick to count a
+    // reference clock that the board keeps running instead.
+    if let SleepMode::Deep = sleep.mode {
+        if sleep.systick_reference_khz.is_none() {
+            return Err("`config.sleep.mode = \"deep\"` stops the processor \
+                clock, and SysTick with it; set \
+                `config.sleep.systick-reference-khz` to a reference clock \
+                that the board keeps running in deep sleep"
+                .into());
+        }
+    }
+    // The kernel keeps time to the microsecond, so it needs at least one count
+    // per microsecond.
+    if let Some(khz) = sleep.systick_reference_khz {
+        if khz < 1000 {
+            return Err(format!(
+                "`config.sleep.systick-reference-khz` is {}, but must be at \
+                 least 1000",
+                khz
+            )
+            .into());
+        }
+    }
 
     let out = &PathBuf::from(env::var_os("OUT_DIR").unwrap());
     let mut const_file = File::create(out.join("consts.rs")).unwrap();
@@ -43,5 +117,18 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         writeln!(const_file, "pub const EXC_RETURN_CONST : u32 = 0xFFFFFFED;")
             .unwrap();
     }
+
+    writeln!(
+        const_file,
+        "pub const DEEP_SLEEP: bool = {};",
+        matches!(sleep.mode, SleepMode::Deep)
+    )
+    .unwrap();
+    writeln!(
+        const_file,
+        "pub const SYSTICK_REFERENCE_KHZ: Option<u32> = {:?};",
+        sleep.systick_reference_khz
+    )
+    .unwrap();
     Ok(())
 }
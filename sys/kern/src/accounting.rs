@@ -0,0 +1,81 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Per-task CPU time accounting.
+//!
+//! The kernel charges the time between one context switch and the next to the
+//! task that was running, as measured by the architecture's free-running cycle
+//! counter (`arch::cycle_count`), and counts how many times each task has been
+//! switched to. Time spent in the kernel is charged to whichever task was
+//! running when it was entered. Both accumulate from boot, across task
+//! restarts, and can be read with the `read_cpu_usage` kernel IPC.
+//!
+//! The cycle counter is only 32 bits wide, so the running task is also charged
+//! on every timer tick; as long as ticks are less than 2^32 cycles apart, the
+//! counter can't wrap between charges.
+//!
+//! All of these functions must be called from kernel context, which can't be
+//! preempted by other kernel entry points, so the table needs no locking.
+
+use abi::CpuUsage;
+
+/// Usage for each task, by index. This lives alongside the task table rather
+/// than in it, so that it can be updated while the task table is borrowed.
+static mut USAGE: Option<&'static mut [CpuUsage]> = None;
+
+/// Value of the cycle counter when we last charged a task.
+static mut LAST_CHARGED: u32 = 0;
+
+/// Hands us the table to keep usage in, which should have one entry per task.
+///
+/// # Safety
+///
+/// This must be called only once, at startup, before any of the other
+/// functions in this module.
+pub unsafe fn set_usage_table(table: &'static mut [CpuUsage]) {
+    let prev = core::mem::replace(&mut USAGE, Some(table));
+    uassert!(prev.is_none());
+}
+
+/// Notes that we're switching from task `previous` (if any task was running)
+/// to task `next`, which may be the same task.
+pub fn context_switch(previous: Option<usize>, next: usize) {
+    charge(previous);
+    if previous != Some(next) {
+        if let Some(usage) = usage_mut(next) {
+            usage.switches = usage.switches.wrapping_add(1);
+        }
+    }
+}
+
+/// Notes a timer tick, while task `current` (if any) is running.
+pub fn tick(current: Option<usize>) {
+    charge(current);
+}
+
+/// Returns the usage recorded for task `index`.
+pub fn usage(index: usize) -> CpuUsage {
+    usage_mut(index).map(|u| *u).unwrap_or_default()
+}
+
+/// Charges the time since we last charged anyone to task `task`.
+fn charge(task: Option<usize>) {
+    let now = crate::arch::cycle_count();
+    // Safety: we're only called from kernel context, which is never
+    // reentered.
+    let elapsed = unsafe {
+        let elapsed = now.wrapping_sub(LAST_CHARGED);
+        LAST_CHARGED = now;
+        elapsed
+    };
+    if let Some(usage) = task.and_then(usage_mut) {
+        usage.time = usage.time.wrapping_add(u64::from(elapsed));
+    }
+}
+
+fn usage_mut(index: usize) -> Option<&'static mut CpuUsage> {
+    // Safety: we're only called from kernel context, which is never
+    // reentered, and we never hand out more than one of these at a time.
+    unsafe { USAGE.as_mut().and_then(|table| table.get_mut(index)) }
+}
@@ -14,6 +14,9 @@
 //!
 //! For this to work, each architecture support module must define the same set
 //! of names.
+//!
+//! Besides real hardware, there's a `hosted` backend that runs the kernel and
+//! its tasks as threads of a 32-bit Linux process, for simulation and testing.
 
 cfg_if::cfg_if! {
     // Note: cfg_if! is slightly touchy about ordering and expression
@@ -25,6 +28,10 @@ cfg_if::cfg_if! {
         #[macro_use]
         pub mod arm_m;
         pub use arm_m::*;
+    } else if #[cfg(all(target_arch = "x86", target_os = "linux"))] {
+        #[macro_use]
+        pub mod hosted;
+        pub use hosted::*;
     } else {
         compile_error!("support for this architecture not implemented");
     }
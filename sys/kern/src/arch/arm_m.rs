@@ -15,6 +15,14 @@
 //! to maintain `TICKS`, but has the upside that we don't need special SoC
 //! support for timing.
 //!
+//! With the `tickless` feature, we instead stretch each timer period to reach
+//! the next timer deadline (or as far as the 24-bit counter will go), and keep
+//! time by adding up how long the periods were. The kernel rarely takes an
+//! interrupt it doesn't need, so the idle task's `WFI` can sleep for as long as
+//! nothing is due to happen -- and, since periods needn't be whole ticks,
+//! timers can fire between ticks, to the microsecond. See the `tickless`
+//! module.
+//!
 //! # Notes on ARM-M interrupts
 //!
 //! For performance and (believe it or not) simplicity, this implementation uses
@@ -166,6 +174,11 @@ static mut CURRENT_TASK_PTR: Option<NonNull<task::Task>> = None;
 #[no_mangle]
 static mut CLOCK_FREQ_KHZ: u32 = 0;
 
+/// Rate at which SysTick counts, and so the number of counts in a tick. This is
+/// the processor clock, unless the board has SysTick count a reference clock
+/// instead; see `SYSTICK_REFERENCE_KHZ`.
+static mut SYSTICK_KHZ: u32 = 0;
+
 /// ARMvx-M volatile registers that must be saved across context switches.
 #[repr(C)]
 #[derive(Debug, Default)]
@@ -253,6 +266,9 @@ impl task::ArchState for SavedState {
     fn ret5(&mut self, x: u32) {
         self.r9 = x
     }
+    fn ret6(&mut self, x: u32) {
+        self.r10 = x
+    }
 }
 
 /// Stuff placed on the stack at exception entry whether or not an FPU is
@@ -317,6 +333,38 @@ pub unsafe fn set_irq_table(irqs: &[abi::Interrupt]) {
     IRQ_TABLE_SIZE = irqs.len();
 }
 
+/// Pattern written over a task's stack when it's (re)initialized.
+const STACK_PAINT: u32 = 0xbaddcafe;
+
+/// Finds the base of the task's stack, which is the base of the region that
+/// contains its initial stack pointer.
+fn stack_base(task: &task::Task) -> Option<u32> {
+    let initial_stack = task.descriptor().initial_stack;
+    task.region_table()
+        .iter()
+        .find(|region| {
+            initial_stack >= region.base
+                && initial_stack <= region.base + region.size
+        })
+        .map(|region| region.base)
+}
+
+/// Returns the most stack, in bytes, that the task has used since it was last
+/// (re)initialized, or `None` if we can't tell.
+///
+/// This relies on the task's stack being painted by `reinitialize`: the peak
+/// is wherever the paint stops.
+pub fn stack_usage(task: &task::Task) -> Option<u32> {
+    let initial_stack = task.descriptor().initial_stack;
+    let base = stack_base(task)?;
+    let uslice: USlice<u32> =
+        USlice::from_raw(base as usize, (initial_stack - base) as usize >> 2)
+            .ok()?;
+    let stack = task.try_read(&uslice).ok()?;
+    let painted = stack.iter().take_while(|&&w| w == STACK_PAINT).count();
+    Some(initial_stack - base - (painted as u32) * 4)
+}
+
 pub fn reinitialize(task: &mut task::Task) {
     *task.save_mut() = SavedState::default();
     let initial_stack = task.descriptor().initial_stack;
@@ -338,25 +386,18 @@ pub fn reinitialize(task: &mut task::Task) {
         USlice::from_raw(initial_stack as usize - frame_size, 1).unwrap();
     // Before we set our frame, find the region that contains our initial stack
     // pointer, and zap the region from the base to the stack pointer with a
-    // distinct (and storied) pattern.
-    for region in task.region_table().iter() {
-        if initial_stack < region.base {
-            continue;
-        }
-
-        if initial_stack > region.base + region.size {
-            continue;
-        }
-
+    // distinct (and storied) pattern. This also lets `stack_usage` find out
+    // how much of the stack the task has used since.
+    if let Some(base) = stack_base(task) {
         let mut uslice: USlice<u32> = USlice::from_raw(
-            region.base as usize,
-            (initial_stack as usize - frame_size - region.base as usize) >> 2,
+            base as usize,
+            (initial_stack as usize - frame_size - base as usize) >> 2,
         )
         .unwrap();
 
         let zap = task.try_write(&mut uslice).unwrap();
         for word in zap.iter_mut() {
-            *word = 0xbaddcafe;
+            *word = STACK_PAINT;
         }
     }
 
@@ -436,28 +477,35 @@ pub fn apply_memory_protection(task: &task::Task) {
         // quickly, because this is called on every context switch.
         //
         // The image-generation tools check at build time that region sizes are
-        // powers of two. So, we can assume that the size has a single 1 bit. We
-        // can cheaply compute log2 of this by counting trailing zeroes, but
-        // ARMv7-M doesn't have a native instruction for that -- only leading
-        // zeroes. The equivalent using leading zeroes is
+        // powers of two, or a whole number of eighths of one -- in which case
+        // the region is the next power of two up, with the top subregions
+        // disabled. So, we want log2 of the size rounded up to a power of two.
+        // We can compute that by counting leading zeroes of one less than the
+        // size:
         //
-        //   log2(N) = bits_in_word - 1 - clz(N)
+        //   log2(N rounded up) = bits_in_word - clz(N - 1)
         //
         // Because we want log2 _minus one_ we compute it as...
         //
-        //   log2_m1(N) = bits_in_word - 2 - clz(N)
+        //   log2_m1(N) = bits_in_word - 1 - clz(N - 1)
         //
         // If the size is zero or one, this subtraction will underflow. This
         // should not occur in a valid image, but could occur due to runtime
         // flash corruption. Any region size under 32 bytes is illegal on
         // ARMv7-M anyway, so panicking is better than triggering possibly
         // undefined hardware behavior.
-        let l2size = 30 - region.size.leading_zeros();
+        let l2size = 31 - (region.size - 1).leading_zeros();
+        // Each subregion is an eighth of the region, so the number of them
+        // that we're using is the size divided by 2**(l2size + 1 - 3). For a
+        // power of two that's all eight, which disables none of them.
+        let subregions = region.size >> (l2size - 2);
+        let srd = (0xFF << subregions) & 0xFF;
 
         let rasr = (xn as u32) << 28
             | ap << 24
             | tex << 19
             | scb << 16
+            | srd << 8
             | l2size << 1
             | (1 << 0); // enable
         unsafe {
@@ -523,8 +571,9 @@ pub fn apply_memory_protection(task: &task::Task) {
             (0b0100_0100 | rw | rw << 4, 0b00)
         };
 
-        // RLAR = our upper bound
-        let rlar = region.base + region.size
+        // RLAR = our upper bound. This is inclusive: it's the address of the
+        // last 32-byte block in the region, not the end of it.
+        let rlar = (region.base + region.size - 32)
                 | (i as u32) << 1 // AttrIndx
                 | (1 << 0); // enable
 
@@ -628,16 +677,37 @@ pub fn start_first_task(tick_divisor: u32, task: &task::Task) -> ! {
         }
     }
 
+    // If the board has SysTick count a reference clock, rather than the
+    // processor clock, that's what sets the length of a tick.
+    let systick_khz = SYSTICK_REFERENCE_KHZ.unwrap_or(tick_divisor);
+
     // Safety: this, too, is safe in practice but unsafe in API.
     unsafe {
         // Configure the timer.
         let syst = &*cortex_m::peripheral::SYST::ptr();
         // Program reload value.
-        syst.rvr.write(tick_divisor - 1);
+        syst.rvr.write(systick_khz - 1);
         // Clear current value.
         syst.cvr.write(0);
-        // Enable counter and interrupt.
-        syst.csr.modify(|v| v | 0b111);
+        // Enable counter and interrupt, counting the processor clock unless
+        // we've been told to count the reference clock.
+        const CLKSOURCE: u32 = 1 << 2;
+        if SYSTICK_REFERENCE_KHZ.is_some() {
+            syst.csr.modify(|v| (v & !CLKSOURCE) | 0b011);
+        } else {
+            syst.csr.modify(|v| v | CLKSOURCE | 0b011);
+        }
+        #[cfg(feature = "tickless")]
+        tickless::start(systick_khz);
+
+        // The idle task's WFI is the only one there is, so the sleep mode can
+        // be chosen once and for all. The build makes sure that deep sleep
+        // leaves SysTick running, so timers keep working through it.
+        if DEEP_SLEEP {
+            const SLEEPDEEP: u32 = 1 << 2;
+            let scb = &*cortex_m::peripheral::SCB::ptr();
+            scb.scr.modify(|v| v | SLEEPDEEP);
+        }
     }
     // We are manufacturing authority to interact with the MPU here, because we
     // can't thread a cortex-specific peripheral through an
@@ -656,10 +726,25 @@ pub fn start_first_task(tick_divisor: u32, task: &task::Task) -> ! {
         mpu.ctrl.write(ENABLE | PRIVDEFENA);
     }
 
+    // Start the cycle counter, which we use to account for the time each task
+    // spends running.
+    //
+    // Safety: this, too, is safe in practice but unsafe in API.
+    unsafe {
+        const TRCENA: u32 = 1 << 24;
+        const CYCCNTENA: u32 = 1 << 0;
+        let dcb = &*cortex_m::peripheral::DCB::ptr();
+        dcb.demcr.modify(|v| v | TRCENA);
+        let dwt = &*cortex_m::peripheral::DWT::ptr();
+        dwt.ctrl.modify(|v| v | CYCCNTENA);
+    }
+
     unsafe {
         CLOCK_FREQ_KHZ = tick_divisor;
+        SYSTICK_KHZ = systick_khz;
         CURRENT_TASK_PTR = Some(NonNull::from(task));
     }
+    crate::accounting::context_switch(None, task_index(NonNull::from(task)));
 
     extern "C" {
         // Exposed by the linker script.
@@ -809,19 +894,94 @@ pub fn with_irq_table<R>(body: impl FnOnce(&[abi::Interrupt]) -> R) -> R {
 /// This records a pointer that aliases `task`. As long as you don't read that
 /// pointer except at syscall entry, you'll be okay.
 pub unsafe fn set_current_task(task: &mut task::Task) {
+    let next = NonNull::from(&mut *task);
+    if cfg!(feature = "trace") && CURRENT_TASK_PTR != Some(next) {
+        crate::trace::context_switch(
+            CURRENT_TASK_PTR.map(task_index),
+            task_index(next),
+        );
+    }
+    crate::accounting::context_switch(
+        CURRENT_TASK_PTR.map(task_index),
+        task_index(next),
+    );
+    crate::irq_stats::switched_to(task_index(next));
     CURRENT_TASK_PTR = Some(NonNull::from(task));
 }
 
-/// Reads the tick counter.
+/// Works out the index of `task` within the task table.
+fn task_index(task: NonNull<task::Task>) -> usize {
+    // Safety: this only reads the base pointer, which doesn't change once the
+    // kernel has started.
+    let base = unsafe { TASK_TABLE_BASE.expect("kernel not started") };
+    (task.as_ptr() as usize - base.as_ptr() as usize)
+        / core::mem::size_of::<task::Task>()
+}
+
+/// Resets the system.
+pub fn reset() -> ! {
+    cortex_m::peripheral::SCB::sys_reset()
+}
+
+/// Reads the kernel clock, using the SysTick counter to tell how far we are
+/// into the current tick.
+#[cfg(not(feature = "tickless"))]
+pub fn now() -> Timestamp {
+    // Safety: we're only called from kernel context, which can't be preempted
+    // by SysTick, so TICKS can't change under us; the rest is register reads.
+    unsafe {
+        let syst = &*cortex_m::peripheral::SYST::ptr();
+        let cvr = syst.cvr.read();
+        let (ticks, cvr) = if cortex_m::peripheral::SCB::is_pendst_pending() {
+            // The tick ended since we entered the kernel, but SysTick can't
+            // preempt us to count it, so we have to.
+            (TICKS + 1, syst.cvr.read())
+        } else {
+            (TICKS, cvr)
+        };
+        let cycles_per_micro =
+            (u64::from(SYSTICK_KHZ) / crate::time::MICROS_PER_TICK).max(1);
+        let elapsed = u64::from(syst.rvr.read() - cvr) / cycles_per_micro;
+        Timestamp::from_micros(
+            ticks * crate::time::MICROS_PER_TICK
+                + elapsed.min(crate::time::MICROS_PER_TICK - 1),
+        )
+    }
+}
+
+/// Reads the kernel clock, which, with the `tickless` feature, is precise to
+/// the microsecond.
+#[cfg(feature = "tickless")]
 pub fn now() -> Timestamp {
-    Timestamp::from(unsafe { TICKS })
+    // Safety: we're only called from kernel context, which can't be preempted
+    // by SysTick.
+    unsafe { tickless::now() }
 }
 
-/// Kernel global for tracking the current timestamp, measured in ticks.
+/// Arranges for the next timer interrupt to arrive in time for `deadline`, or
+/// as late as the hardware allows if there's no deadline.
+#[cfg(feature = "tickless")]
+pub fn set_tick_deadline(deadline: Option<Timestamp>) {
+    // Safety: we're only called from kernel context, which can't be preempted
+    // by SysTick.
+    unsafe {
+        tickless::set_deadline(deadline);
+    }
+}
+
+/// Reads the free-running cycle counter, which `start_first_task` starts.
+pub fn cycle_count() -> u32 {
+    // Safety: reading this register has no side effects.
+    unsafe { (*cortex_m::peripheral::DWT::ptr()).cyccnt.read() }
+}
+
+/// Kernel global for tracking the current timestamp, measured in ticks. (With
+/// the `tickless` feature, the `tickless` module keeps time instead.)
 ///
 /// This is a mutable `u64` instead of an `AtomicU64` because ARMv7-M doesn't
 /// have any 64-bit atomic operations. So, we access it carefully from
 /// non-preemptible contexts.
+#[cfg(not(feature = "tickless"))]
 static mut TICKS: u64 = 0;
 
 /// Handler that gets linked into the vector table for the System Tick Timer
@@ -833,13 +993,17 @@ pub unsafe extern "C" fn SysTick() {
     // there's no way this can preempt the kernel -- it will only preempt user
     // code. As a result, we can manufacture exclusive references to various
     // bits of kernel state.
-    let ticks = &mut TICKS;
-    with_task_table(|tasks| safe_sys_tick_handler(ticks, tasks));
+    crate::accounting::tick(CURRENT_TASK_PTR.map(task_index));
+    #[cfg(not(feature = "tickless"))]
+    let now = advance_ticks(&mut TICKS);
+    #[cfg(feature = "tickless")]
+    let now = tickless::end_period();
+    with_task_table(|tasks| safe_sys_tick_handler(now, tasks));
 }
 
-/// The meat of the systick handler, after we do the unsafe things.
-fn safe_sys_tick_handler(ticks: &mut u64, tasks: &mut [task::Task]) {
-    // Advance the kernel's notion of time.
+/// Advances the kernel's notion of time by a tick, returning the new time.
+#[cfg(not(feature = "tickless"))]
+fn advance_ticks(ticks: &mut u64) -> Timestamp {
     // This increment is not expected to overflow in a working system, since it
     // would indicate that 2^64 ticks have passed, and ticks are expected to be
     // in the range of nanoseconds to milliseconds -- meaning over 500 years.
@@ -847,13 +1011,15 @@ fn safe_sys_tick_handler(ticks: &mut u64, tasks: &mut [task::Task]) {
     // to e.g. memory corruption, we'd rather panic and reboot than attempt to
     // limp forward.
     *ticks += 1;
-    // Now, give up mutable access to *ticks so there's no chance of a
-    // double-increment due to bugs below.
-    let now = Timestamp::from(*ticks);
-    drop(ticks);
+    Timestamp::from_ticks(*ticks)
+}
 
-    // Process any timers.
+/// The meat of the systick handler, after we do the unsafe things.
+fn safe_sys_tick_handler(now: Timestamp, tasks: &mut [task::Task]) {
+    // Process any timers, and then work out when we next need to hear from
+    // the timer.
     let switch = task::process_timers(tasks, now);
+    task::reschedule_tick(tasks);
 
     // If any timers fired, we need to defer a context switch, because the entry
     // sequence to this ISR doesn't save state correctly for efficiency.
@@ -862,6 +1028,152 @@ fn safe_sys_tick_handler(ticks: &mut u64, tasks: &mut [task::Task]) {
     }
 }
 
+/// Stretching SysTick periods to reach the next timer deadline.
+///
+/// SysTick counts down from its reload value to zero, interrupts, and reloads.
+/// When it reloads, it picks up whatever is in the reload register at the time,
+/// so we can set up the length of the *next* period at any point during the
+/// current one without disturbing the count -- which is what keeps our time
+/// from drifting. We keep time in CPU cycles since boot, adding up the lengths
+/// of the periods as they end, and aim for each period to end just in time for
+/// the earliest deadline, to the microsecond.
+///
+/// The only way to cut the *current* period short is to restart the counter,
+/// which loses the few cycles it takes us to do so. We only need to do that
+/// when a task sets a timer that's due before the current period ends.
+///
+/// None of this is reentrant; it must only be used from kernel context.
+#[cfg(feature = "tickless")]
+mod tickless {
+    use crate::time::Timestamp;
+    use cortex_m::peripheral::{SCB, SYST};
+
+    /// The SysTick counter is 24 bits wide, which limits how long a period can
+    /// be, in cycles.
+    const MAX_PERIOD: u32 = 1 << 24;
+
+    /// Don't touch the counter if it's within this many cycles of the end of
+    /// its period, and don't make periods any shorter. This needs to be longer
+    /// than it takes us to get from checking the counter to writing it.
+    const MARGIN: u32 = 128;
+
+    /// Time at which the current period began, in cycles since boot.
+    static mut START: u64 = 0;
+
+    /// Length of the current period, in cycles.
+    static mut PERIOD: u32 = 0;
+
+    fn cycles_per_tick() -> u32 {
+        // Safety: this is only written before the first task starts.
+        unsafe { super::SYSTICK_KHZ }
+    }
+
+    fn cycles_per_micro() -> u64 {
+        u64::from(cycles_per_tick()) / crate::time::MICROS_PER_TICK
+    }
+
+    fn to_timestamp(cycles: u64) -> Timestamp {
+        Timestamp::from_micros(cycles / cycles_per_micro())
+    }
+
+    fn to_cycles(time: Timestamp) -> u64 {
+        time.micros().saturating_mul(cycles_per_micro())
+    }
+
+    /// Notes the length of the first period, which `start_first_task` has just
+    /// started.
+    pub unsafe fn start(systick_khz: u32) {
+        // We can't count microseconds with a clock slower than a megahertz.
+        uassert!(u64::from(systick_khz) >= crate::time::MICROS_PER_TICK);
+        PERIOD = systick_khz;
+    }
+
+    /// Accounts for the period that has just ended, returning the time at
+    /// which it did.
+    pub unsafe fn end_period() -> Timestamp {
+        START += u64::from(PERIOD);
+        // The counter reloaded as the period ended, starting the next.
+        PERIOD = (*SYST::ptr()).rvr.read() + 1;
+        to_timestamp(START)
+    }
+
+    /// Returns the current time.
+    pub unsafe fn now() -> Timestamp {
+        let syst = &*SYST::ptr();
+        let cvr = syst.cvr.read();
+        let cycles = if SCB::is_pendst_pending() {
+            // The period ended since we entered the kernel, but SysTick can't
+            // preempt us to account for it, so we have to: the counter is
+            // partway through the next period.
+            START
+                + u64::from(PERIOD)
+                + u64::from(syst.rvr.read() - syst.cvr.read())
+        } else {
+            START + u64::from(PERIOD - 1 - cvr)
+        };
+        to_timestamp(cycles)
+    }
+
+    /// Sets up timer periods to end at `deadline`, or as late as possible.
+    pub unsafe fn set_deadline(deadline: Option<Timestamp>) {
+        let syst = &*SYST::ptr();
+
+        // Make sure we're not racing the end of the period. Reading the
+        // counter before checking for the interrupt means that, if the period
+        // ends in between, we'll see it.
+        loop {
+            let cvr = syst.cvr.read();
+            if SCB::is_pendst_pending() {
+                // The period is over, and SysTick will be along to sort
+                // things out as soon as we leave the kernel.
+                return;
+            }
+            if cvr >= MARGIN {
+                break;
+            }
+        }
+
+        let deadline = deadline.map(to_cycles);
+        let mut end = START + u64::from(PERIOD);
+
+        if let Some(deadline) = deadline.filter(|&d| d < end) {
+            // The current period would overshoot the deadline, so restart the
+            // counter with one that ends on it instead.
+            let now = START + u64::from(PERIOD - 1 - syst.cvr.read());
+            // This can't be more than PERIOD, since the deadline is before
+            // the end of the period, so it fits.
+            let period = deadline.saturating_sub(now).max(u64::from(MARGIN));
+            let period = period as u32;
+            syst.rvr.write(period - 1);
+            syst.cvr.write(0);
+            // If the old period ran out while we were at it, forget it:
+            // we've accounted for all of it in `now`.
+            SCB::clear_pendst();
+            // The counter picks up the new period on its next cycle; wait
+            // for that before we change the reload value again, below.
+            while syst.cvr.read() == 0 {}
+
+            START = now;
+            PERIOD = period;
+            end = now + u64::from(period);
+        }
+
+        // Set up the period after this one to end on the deadline, if it's
+        // after this one. If the deadline is at the end of this one, we won't
+        // know what's due next until the timer has fired; a spare interrupt is
+        // cheaper than having to cut a long period short, so make the next
+        // period a single tick. Otherwise, make it as long as possible.
+        let period = match deadline {
+            Some(d) if d > end => {
+                (d - end).clamp(u64::from(MARGIN), u64::from(MAX_PERIOD)) as u32
+            }
+            Some(_) => cycles_per_tick(),
+            None => MAX_PERIOD,
+        };
+        syst.rvr.write(period - 1);
+    }
+}
+
 fn pend_context_switch_from_isr() {
     // This sets the bit to pend a PendSV interrupt. PendSV will happen after
     // the current ISR (and any chained ISRs) returns, and perform the context
@@ -965,16 +1277,25 @@ pub unsafe extern "C" fn DefaultHandler() {
                     // response, change the irq_table data structure to
                     // something we can access in O(1), or at least O(log n),
                     // time.
-                    for entry in irqs {
+                    for (i, entry) in irqs.iter().enumerate() {
                         if entry.irq == irq_num {
                             // Early exit on the first (and should be sole)
                             // match.
 
                             disable_irq(irq_num);
+                            crate::irq_stats::fired(
+                                i,
+                                CURRENT_TASK_PTR.map(task_index),
+                            );
 
                             // Now, post the notification and return the
                             // scheduling hint.
                             let n = task::NotificationSet(entry.notification);
+                            crate::trace::irq(
+                                irq_num,
+                                entry.task as usize,
+                                entry.notification,
+                            );
                             return Ok(tasks[entry.task as usize].post(n));
                         }
                     }
@@ -1012,6 +1333,17 @@ pub fn enable_irq(n: u32) {
     }
 }
 
+/// Makes interrupt `n` pending, as if its peripheral had raised it.
+pub fn raise_irq(n: u32) {
+    // Pend the interrupt by poking the Interrupt Set Pending Register.
+    let nvic = unsafe { &*cortex_m::peripheral::NVIC::ptr() };
+    let reg_num = (n / 32) as usize;
+    let bit_mask = 1 << (n % 32);
+    unsafe {
+        nvic.ispr[reg_num].write(bit_mask);
+    }
+}
+
 #[repr(u8)]
 #[allow(dead_code)]
 enum FaultType {
@@ -1228,6 +1560,23 @@ unsafe extern "C" fn handle_fault(
         ),
     };
 
+    // Unless the processor failed to stack the task's registers, the exception
+    // frame on its stack tells us where it was when it faulted. We still check
+    // that the frame is somewhere the task could have put it.
+    let detail = if stackinvalid || cfsr.contains(Cfsr::STKERR) {
+        abi::FaultDetail::EMPTY
+    } else {
+        USlice::<BaseExceptionFrame>::from_raw((*task).save().psp as usize, 1)
+            .ok()
+            .and_then(|frame| {
+                (*task)
+                    .try_read(&frame)
+                    .ok()
+                    .map(|frame| abi::FaultDetail::with_pc(frame[0].pc))
+            })
+            .unwrap_or(abi::FaultDetail::EMPTY)
+    };
+
     // Because we are responsible for clearing all conditions, we write back
     // the value of CFSR that we read
     scb.cfsr.write(cfsr.bits());
@@ -1257,11 +1606,12 @@ unsafe extern "C" fn handle_fault(
         let idx = (task as usize - tasks.as_ptr() as usize)
             / core::mem::size_of::<task::Task>();
 
-        let next = match task::force_fault(tasks, idx, fault) {
-            task::NextTask::Specific(i) => i,
-            task::NextTask::Other => task::select(idx, tasks),
-            task::NextTask::Same => idx,
-        };
+        let next =
+            match task::force_fault_with_detail(tasks, idx, fault, detail) {
+                task::NextTask::Specific(i) => i,
+                task::NextTask::Other => task::select(idx, tasks),
+                task::NextTask::Same => idx,
+            };
 
         if next == idx {
             panic!("attempt to return to Task #{} after fault", idx);
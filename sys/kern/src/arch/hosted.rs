@@ -0,0 +1,1098 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Architecture support for running the kernel as an ordinary Linux process.
+//!
+//! This exists so that the portable parts of the kernel -- syscalls, the
+//! scheduler, kernel IPC -- and the tasks built on them can be exercised on a
+//! workstation, without hardware or an emulator. It targets 32-bit Linux (e.g.
+//! `i686-unknown-linux-gnu`), because the kernel and the task ABI assume that
+//! addresses fit in a `u32`.
+//!
+//! # Tasks as threads
+//!
+//! Each task runs on its own host thread. The thread starts on a small host
+//! stack, then switches to the task's `initial_stack` and calls its
+//! `entry_point`, so task memory -- including the stack -- lives in the regions
+//! described by the task's region table, just like on hardware.
+//!
+//! Only one thread may run task or kernel code at a time. We model "the CPU"
+//! as a token passed between per-thread semaphores: a thread that discovers
+//! that it is no longer the current task posts the semaphore of the thread
+//! that is, then sleeps on its own. See `wait_for_cpu`.
+//!
+//! Restarting a task abandons its old thread, which is parked forever on a
+//! semaphore nobody will post, and spawns a fresh one. The old thread (and its
+//! small host stack) is leaked; this is a simulator, and crash loops that run
+//! long enough to matter are a bug anyway.
+//!
+//! # Loading
+//!
+//! Tasks are built and linked by `cargo xtask dist` just as they are for
+//! hardware, at the addresses it allocates them, and the kernel is an ordinary
+//! program. `boot` stands in for flashing the image and resetting: it maps host
+//! memory at the addresses of the application's regions, copies the tasks in,
+//! and starts the kernel with the application descriptor that `dist` wrote
+//! alongside them.
+//!
+//! # Syscalls
+//!
+//! Tasks make syscalls through `hubris_hosted_syscall`, which stands in for
+//! the `SVC` instruction. Tasks are linked separately from the kernel, so
+//! rather than finding it by name, they're given its address as the argument
+//! to their entry point. Its own argument is an array mirroring `r4`-`r11` on
+//! ARM: seven argument/return registers followed by the syscall number. The
+//! entry sequence switches back onto the thread's host stack before running
+//! kernel code, since task stacks are sized for tasks, not for the kernel.
+//!
+//! # Timer
+//!
+//! A dedicated thread plays the part of SysTick, advancing `TICKS` once per
+//! millisecond. If processing timers makes a different task current, we
+//! preempt the running thread by sending it `PREEMPT_SIGNAL`, whose handler
+//! gives up the CPU (this is our PendSV).
+//!
+//! # Memory protection
+//!
+//! There's no MPU here, and host page protection is far too coarse to model
+//! Hubris regions. Instead, the checks are done in software against the
+//! task's `RegionDesc`s: the kernel already validates every user slice it
+//! touches, and on each kernel entry we check that the task's stack pointer
+//! still lies within a writable region of its own. Stray accesses that the
+//! host catches (`SIGSEGV` and friends) are turned into task faults. Stray
+//! accesses that land in some other task's memory go undetected.
+//!
+//! # Interrupts
+//!
+//! There's no interrupt controller either. Simulated peripherals can call
+//! `raise_irq` to mark an interrupt pending; pending, enabled interrupts are
+//! delivered to their tasks on the next tick.
+
+use core::cell::{Cell, UnsafeCell};
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU32, Ordering};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::app;
+use crate::task;
+use crate::time::Timestamp;
+use abi::{FaultInfo, FaultSource};
+
+/// Log things from kernel context. On hardware this goes out over ITM or
+/// semihosting; hosted, we've got a perfectly good stderr.
+macro_rules! klog {
+    ($s:expr) => {
+        eprintln!($s);
+    };
+    ($s:expr, $($tt:tt)*) => {
+        eprintln!($s, $($tt)*);
+    };
+}
+
+macro_rules! uassert {
+    ($cond : expr) => {
+        if !$cond {
+            panic!("Assertion failed!");
+        }
+    };
+}
+
+macro_rules! uassert_eq {
+    ($cond1 : expr, $cond2 : expr) => {
+        if !($cond1 == $cond2) {
+            panic!("Assertion failed!");
+        }
+    };
+}
+
+/// As on ARMvx-M, we use a global to record the task table position and
+/// extent.
+#[no_mangle]
+static mut TASK_TABLE_BASE: Option<NonNull<task::Task>> = None;
+#[no_mangle]
+static mut TASK_TABLE_SIZE: usize = 0;
+
+/// As on ARMvx-M, we use a global to record the interrupt table position and
+/// extent.
+#[no_mangle]
+static mut IRQ_TABLE_BASE: Option<NonNull<abi::Interrupt>> = None;
+#[no_mangle]
+static mut IRQ_TABLE_SIZE: usize = 0;
+
+/// Pointer to the current task. This is only read by kernel code holding the
+/// kernel lock; threads that need to know who's current without taking the
+/// lock use `CURRENT_THREAD` instead.
+#[no_mangle]
+static mut CURRENT_TASK_PTR: Option<NonNull<task::Task>> = None;
+
+/// Host thread belonging to the current task, or null before the kernel has
+/// started. Kept in sync with `CURRENT_TASK_PTR` by `set_current_task`.
+static CURRENT_THREAD: AtomicPtr<HostThread> =
+    AtomicPtr::new(core::ptr::null_mut());
+
+/// Recorded for parity with ARMvx-M, where debuggers read it to find the
+/// clock frequency. Hosted, ticks are always one millisecond.
+#[no_mangle]
+static mut CLOCK_FREQ_KHZ: u32 = 0;
+
+/// Period of the simulated SysTick.
+const TICK: Duration = Duration::from_millis(1);
+
+/// Our "cycles" are nanoseconds (see `cycle_count`), so this is how many of
+/// them make a tick.
+const CYCLES_PER_TICK: u32 = 1_000_000;
+
+/// Host memory given to the kernel for its own use, in place of the RAM
+/// that's set aside for it on hardware.
+const KERNEL_SCRATCH_SIZE: usize = 64 * 1024;
+
+/// Signal used to ask the running thread to give up the CPU.
+const PREEMPT_SIGNAL: libc::c_int = libc::SIGUSR1;
+
+/// Size of the host stack each task thread starts on, and on which it runs
+/// kernel code.
+const HOST_STACK_SIZE: usize = 256 * 1024;
+
+/// Size of the alternate stack used by signal handlers, which must not run on
+/// a task stack that may be tiny (or overflowed).
+const SIGNAL_STACK_SIZE: usize = 64 * 1024;
+
+/// On hardware, the kernel can't be reentered because all of its entry points
+/// run at the same interrupt priority. Here, every kernel entry point takes
+/// this lock instead.
+static mut KERNEL_LOCK: libc::pthread_mutex_t = libc::PTHREAD_MUTEX_INITIALIZER;
+
+/// Holds `KERNEL_LOCK` until dropped.
+struct KernelGuard(());
+
+impl KernelGuard {
+    fn acquire() -> Self {
+        let rc = unsafe { libc::pthread_mutex_lock(&mut KERNEL_LOCK) };
+        uassert_eq!(rc, 0);
+        KernelGuard(())
+    }
+}
+
+impl Drop for KernelGuard {
+    fn drop(&mut self) {
+        unsafe {
+            libc::pthread_mutex_unlock(&mut KERNEL_LOCK);
+        }
+    }
+}
+
+/// Host-side state for one incarnation of a task.
+struct HostThread {
+    /// The task this thread runs.
+    task: NonNull<task::Task>,
+    /// Posted to hand this thread the CPU.
+    wake: UnsafeCell<libc::sem_t>,
+    /// Set while the thread is in the kernel or waiting for the CPU, during
+    /// which preemption requests are ignored (the thread will notice on its
+    /// way out).
+    in_kernel: AtomicBool,
+    /// Host stack pointer recorded just before switching to the task stack;
+    /// kernel entries run below it.
+    kernel_sp: u32,
+    /// Copied from the task descriptor at creation.
+    entry_point: u32,
+    initial_stack: u32,
+    pthread: libc::pthread_t,
+}
+
+std::thread_local! {
+    static THIS_THREAD: Cell<*mut HostThread> =
+        Cell::new(core::ptr::null_mut());
+}
+
+fn this_thread() -> *mut HostThread {
+    THIS_THREAD.with(|t| t.get())
+}
+
+/// Registers that must be saved across context switches. Since each task has
+/// a thread of its own, that's only the syscall registers.
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct SavedState {
+    /// Syscall argument and return registers, mirroring `r4`-`r10` on ARM.
+    regs: [u32; 7],
+    /// Syscall number, mirroring `r11` on ARM.
+    descriptor: u32,
+    /// Task stack pointer at the last kernel entry.
+    sp: u32,
+    /// Host thread running the current incarnation of this task.
+    thread: Option<NonNull<HostThread>>,
+}
+
+/// Map the saved registers to (architecture-independent) syscall argument and
+/// return slots.
+impl task::ArchState for SavedState {
+    fn stack_pointer(&self) -> u32 {
+        self.sp
+    }
+
+    /// Reads syscall argument register 0.
+    fn arg0(&self) -> u32 {
+        self.regs[0]
+    }
+    fn arg1(&self) -> u32 {
+        self.regs[1]
+    }
+    fn arg2(&self) -> u32 {
+        self.regs[2]
+    }
+    fn arg3(&self) -> u32 {
+        self.regs[3]
+    }
+    fn arg4(&self) -> u32 {
+        self.regs[4]
+    }
+    fn arg5(&self) -> u32 {
+        self.regs[5]
+    }
+    fn arg6(&self) -> u32 {
+        self.regs[6]
+    }
+
+    fn syscall_descriptor(&self) -> u32 {
+        self.descriptor
+    }
+
+    /// Writes syscall return argument 0.
+    fn ret0(&mut self, x: u32) {
+        self.regs[0] = x
+    }
+    fn ret1(&mut self, x: u32) {
+        self.regs[1] = x
+    }
+    fn ret2(&mut self, x: u32) {
+        self.regs[2] = x
+    }
+    fn ret3(&mut self, x: u32) {
+        self.regs[3] = x
+    }
+    fn ret4(&mut self, x: u32) {
+        self.regs[4] = x
+    }
+    fn ret5(&mut self, x: u32) {
+        self.regs[5] = x
+    }
+    fn ret6(&mut self, x: u32) {
+        self.regs[6] = x
+    }
+}
+
+/// Records `tasks` as the system-wide task table.
+///
+/// If a task table has already been set, panics.
+///
+/// # Safety
+///
+/// This stashes a copy of `tasks` without revoking your right to access it,
+/// which is a potential aliasing violation if you call `with_task_table`. So
+/// don't do that. The normal kernel entry sequences avoid this issue.
+pub unsafe fn set_task_table(tasks: &mut [task::Task]) {
+    let prev_task_table = core::mem::replace(
+        &mut TASK_TABLE_BASE,
+        Some(NonNull::from(&mut tasks[0])),
+    );
+    // Catch double-uses of this function.
+    uassert_eq!(prev_task_table, None);
+    // Record length as well.
+    TASK_TABLE_SIZE = tasks.len();
+}
+
+pub unsafe fn set_irq_table(irqs: &[abi::Interrupt]) {
+    let prev_table = core::mem::replace(
+        &mut IRQ_TABLE_BASE,
+        Some(NonNull::new_unchecked(irqs.as_ptr() as *mut abi::Interrupt)),
+    );
+    // Catch double-uses of this function.
+    uassert_eq!(prev_table, None);
+    // Record length as well.
+    IRQ_TABLE_SIZE = irqs.len();
+}
+
+/// Returns the most stack that the task has used, which we can't tell here:
+/// task code runs on host threads, whose stacks aren't the ones described in
+/// the task table.
+pub fn stack_usage(_task: &task::Task) -> Option<u32> {
+    None
+}
+
+pub fn reinitialize(task: &mut task::Task) {
+    let descriptor = task.descriptor();
+    // Keep the same stack alignment rules as hardware, so that images that
+    // work here also work there.
+    uassert!(descriptor.initial_stack & 0x7 == 0);
+
+    // Any previous thread for this task is abandoned where it stands: it's
+    // either waiting for a CPU that will now never come, or it's the thread
+    // executing this very code, in which case it'll discover that it's no
+    // longer current on its way out of the kernel.
+    let thread = Box::leak(Box::new(HostThread {
+        task: NonNull::from(&mut *task),
+        wake: UnsafeCell::new(unsafe { core::mem::zeroed() }),
+        in_kernel: AtomicBool::new(true),
+        kernel_sp: 0,
+        entry_point: descriptor.entry_point,
+        initial_stack: descriptor.initial_stack,
+        pthread: 0,
+    }));
+
+    unsafe {
+        uassert_eq!(libc::sem_init(thread.wake.get(), 0, 0), 0);
+
+        let mut attr: libc::pthread_attr_t = core::mem::zeroed();
+        uassert_eq!(libc::pthread_attr_init(&mut attr), 0);
+        uassert_eq!(
+            libc::pthread_attr_setstacksize(&mut attr, HOST_STACK_SIZE),
+            0
+        );
+        uassert_eq!(
+            libc::pthread_attr_setdetachstate(
+                &mut attr,
+                libc::PTHREAD_CREATE_DETACHED
+            ),
+            0
+        );
+        let thread_ptr: *mut HostThread = thread;
+        uassert_eq!(
+            libc::pthread_create(
+                &mut (*thread_ptr).pthread,
+                &attr,
+                task_thread_main,
+                thread_ptr as *mut libc::c_void,
+            ),
+            0
+        );
+        libc::pthread_attr_destroy(&mut attr);
+    }
+
+    *task.save_mut() = SavedState {
+        thread: Some(NonNull::from(thread)),
+        ..SavedState::default()
+    };
+
+    // If we just replaced the current task's thread, the CPU belongs to the
+    // new one now.
+    unsafe {
+        if CURRENT_TASK_PTR == Some(NonNull::from(&mut *task)) {
+            CURRENT_THREAD.store(thread_of(task), Ordering::SeqCst);
+        }
+    }
+}
+
+fn thread_of(task: &task::Task) -> *mut HostThread {
+    task.save()
+        .thread
+        .map(NonNull::as_ptr)
+        .unwrap_or(core::ptr::null_mut())
+}
+
+/// Body of each task thread: wait to be scheduled, then jump into the task.
+extern "C" fn task_thread_main(arg: *mut libc::c_void) -> *mut libc::c_void {
+    let me = arg as *mut HostThread;
+    unsafe {
+        THIS_THREAD.with(|t| t.set(me));
+
+        // Signal handlers get a stack of their own, so they keep working when
+        // the task stack is small or blown.
+        let signal_stack =
+            Box::leak(vec![0u8; SIGNAL_STACK_SIZE].into_boxed_slice());
+        let ss = libc::stack_t {
+            ss_sp: signal_stack.as_mut_ptr() as *mut libc::c_void,
+            ss_flags: 0,
+            ss_size: signal_stack.len(),
+        };
+        uassert_eq!(libc::sigaltstack(&ss, core::ptr::null_mut()), 0);
+
+        // Asynchronous signals meant for the process should be handled by
+        // someone other than a task.
+        let mut mask: libc::sigset_t = core::mem::zeroed();
+        libc::sigfillset(&mut mask);
+        for &sig in &[
+            PREEMPT_SIGNAL,
+            libc::SIGSEGV,
+            libc::SIGBUS,
+            libc::SIGILL,
+            libc::SIGFPE,
+        ] {
+            libc::sigdelset(&mut mask, sig);
+        }
+        libc::pthread_sigmask(libc::SIG_SETMASK, &mask, core::ptr::null_mut());
+
+        leave_kernel(&*me);
+
+        let entry = (*me).entry_point;
+        // The i386 ABI wants 16-byte alignment at call sites, which we keep by
+        // padding below the one argument we pass.
+        let sp = (*me).initial_stack & !0xF;
+        asm!(
+            "mov [{ksp}], esp",
+            "mov esp, {sp}",
+            "sub esp, 12",
+            "push {syscall}",
+            "call {entry}",
+            "ud2",
+            ksp = in(reg) &mut (*me).kernel_sp,
+            sp = in(reg) sp,
+            syscall = in(reg) hubris_hosted_syscall as usize,
+            entry = in(reg) entry,
+            options(noreturn),
+        )
+    }
+}
+
+/// Blocks the calling thread until it's the current task's thread, passing
+/// the CPU along to the rightful owner if need be.
+///
+/// The caller must hold the CPU (or be waiting for it for the first time), and
+/// must have set `in_kernel`.
+unsafe fn wait_for_cpu(me: &HostThread) {
+    loop {
+        let current = CURRENT_THREAD.load(Ordering::SeqCst);
+        if current as *const HostThread == me as *const HostThread {
+            return;
+        }
+        if !current.is_null() {
+            libc::sem_post((*current).wake.get());
+        }
+        // Retry on EINTR; we'll recheck who's current either way.
+        while libc::sem_wait(me.wake.get()) != 0 {}
+    }
+}
+
+/// Waits for the CPU and returns to task code, taking care of any preemption
+/// requests that were ignored while we were in the kernel.
+unsafe fn leave_kernel(me: &HostThread) {
+    loop {
+        wait_for_cpu(me);
+        me.in_kernel.store(false, Ordering::SeqCst);
+        if CURRENT_THREAD.load(Ordering::SeqCst) as *const HostThread
+            == me as *const HostThread
+        {
+            return;
+        }
+        me.in_kernel.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Software stand-in for the MPU: checks whether `task`'s region table grants
+/// `atts` for the `len` bytes starting at `addr`.
+fn task_can_access(
+    task: &task::Task,
+    addr: u32,
+    len: u32,
+    atts: app::RegionAttributes,
+) -> bool {
+    task.region_table().iter().any(|region| {
+        region.attributes.contains(atts)
+            && addr >= region.base
+            && (addr - region.base)
+                .checked_add(len)
+                .map_or(false, |end| end <= region.size)
+    })
+}
+
+/// Hosted tasks have no hardware to program: accesses are checked against the
+/// region table directly (see `task_can_access`), so there's nothing to do
+/// here.
+pub fn apply_memory_protection(_task: &task::Task) {}
+
+pub fn start_first_task(tick_divisor: u32, task: &task::Task) -> ! {
+    // A kernel panic on hardware stops the world. Make sure it does here, too,
+    // rather than quietly unwinding one thread.
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        std::process::abort();
+    }));
+
+    unsafe {
+        install_handler(PREEMPT_SIGNAL, preempt_handler as usize);
+        for &sig in &[libc::SIGSEGV, libc::SIGBUS, libc::SIGILL, libc::SIGFPE] {
+            install_handler(sig, fault_handler as usize);
+        }
+
+        CLOCK_FREQ_KHZ = tick_divisor;
+        CURRENT_TASK_PTR = Some(NonNull::from(task));
+    }
+    crate::accounting::context_switch(None, task_index(NonNull::from(task)));
+    let first = thread_of(task);
+    CURRENT_THREAD.store(first, Ordering::SeqCst);
+
+    std::thread::Builder::new()
+        .name("systick".into())
+        .spawn(sys_tick_thread)
+        .expect("can't start tick thread");
+
+    // Hand the CPU to the first task. This thread, like the boot context on
+    // hardware, has nothing further to do.
+    unsafe {
+        libc::sem_post((*first).wake.get());
+    }
+    loop {
+        std::thread::park();
+    }
+}
+
+unsafe fn install_handler(sig: libc::c_int, handler: usize) {
+    let mut action: libc::sigaction = core::mem::zeroed();
+    action.sa_sigaction = handler;
+    action.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK;
+    libc::sigemptyset(&mut action.sa_mask);
+    uassert_eq!(libc::sigaction(sig, &action, core::ptr::null_mut()), 0);
+}
+
+/// Entry point for syscalls from hosted tasks; stands in for the `SVC`
+/// instruction. Tasks are passed its address when they start.
+///
+/// `regs` holds the seven syscall argument registers followed by the syscall
+/// number. On return, it holds the response registers.
+///
+/// This switches to the thread's host stack (recording the task stack pointer
+/// for the Rust side) before entering the kernel proper.
+#[naked]
+pub unsafe extern "C" fn hubris_hosted_syscall(_regs: *mut [u32; 8]) {
+    asm!("
+        push ebp
+        mov ebp, esp            // ebp = task stack pointer, [ebp + 8] = regs
+        and esp, -16
+        call {kstack}           // find our host stack...
+        mov esp, eax            // ...and switch to it
+        sub esp, 8              // keep the stack 16-byte aligned
+        push dword ptr [ebp + 8]
+        push ebp
+        call {entry}
+        mov esp, ebp            // back to the task stack
+        pop ebp
+        ret
+        ",
+        kstack = sym kernel_stack_top,
+        entry = sym hosted_syscall_entry,
+        options(noreturn),
+    )
+}
+
+/// Returns the top of the calling thread's kernel stack.
+unsafe extern "C" fn kernel_stack_top() -> u32 {
+    ((*this_thread()).kernel_sp & !0xF) - 16
+}
+
+/// The Rust side of `hubris_hosted_syscall`, running on the host stack.
+unsafe extern "C" fn hosted_syscall_entry(task_sp: u32, regs: *mut [u32; 8]) {
+    let me = &*this_thread();
+    let task = me.task.as_ptr();
+    me.in_kernel.store(true, Ordering::SeqCst);
+
+    {
+        let _kernel = KernelGuard::acquire();
+        let save = (*task).save_mut();
+        save.regs.copy_from_slice(&(*regs)[..7]);
+        save.descriptor = (*regs)[7];
+        save.sp = task_sp;
+
+        let stack_ok = task_can_access(
+            &*task,
+            task_sp,
+            4,
+            app::RegionAttributes::READ | app::RegionAttributes::WRITE,
+        );
+        if stack_ok {
+            crate::syscalls::syscall_entry(save.descriptor, task);
+        } else {
+            fault_task(task, FaultInfo::StackOverflow { address: task_sp });
+        }
+    }
+
+    // If we blocked or faulted, this is where we wait. If we were restarted,
+    // we wait here forever.
+    leave_kernel(me);
+
+    let _kernel = KernelGuard::acquire();
+    (*regs)[..7].copy_from_slice(&(*task).save().regs);
+}
+
+/// Forces a fault on `task` and switches away from it.
+unsafe fn fault_task(task: *mut task::Task, fault: FaultInfo) {
+    with_task_table(|tasks| {
+        let idx = (task as usize - tasks.as_ptr() as usize)
+            / core::mem::size_of::<task::Task>();
+
+        let next = match task::force_fault(tasks, idx, fault) {
+            task::NextTask::Specific(i) => i,
+            task::NextTask::Other => task::select(idx, tasks),
+            task::NextTask::Same => idx,
+        };
+
+        if next == idx {
+            panic!("attempt to return to Task #{} after fault", idx);
+        }
+
+        let next = &mut tasks[next];
+        apply_memory_protection(next);
+        set_current_task(next);
+    });
+}
+
+/// Handler for `PREEMPT_SIGNAL`: gives up the CPU if we're no longer current.
+/// This is the hosted equivalent of PendSV.
+extern "C" fn preempt_handler(
+    _sig: libc::c_int,
+    _info: *mut libc::siginfo_t,
+    _context: *mut libc::c_void,
+) {
+    let me = this_thread();
+    if me.is_null() {
+        return;
+    }
+    unsafe {
+        let me = &*me;
+        if me.in_kernel.swap(true, Ordering::SeqCst) {
+            // We'll notice on our way out of the kernel.
+            return;
+        }
+        leave_kernel(me);
+    }
+}
+
+/// Handler for synchronous faults, which become task faults if they occur in
+/// task code.
+extern "C" fn fault_handler(
+    sig: libc::c_int,
+    info: *mut libc::siginfo_t,
+    _context: *mut libc::c_void,
+) {
+    let me = this_thread();
+    unsafe {
+        if me.is_null() || (*me).in_kernel.load(Ordering::SeqCst) {
+            // This fault originates from the kernel (or the simulator itself).
+            // Restore the default disposition and return, so that the faulting
+            // instruction re-executes and takes the process down with a
+            // useful core.
+            libc::signal(sig, libc::SIG_DFL);
+            return;
+        }
+        let me = &*me;
+        me.in_kernel.store(true, Ordering::SeqCst);
+
+        let address = Some((*info).si_addr() as u32);
+        let fault = match sig {
+            libc::SIGSEGV => FaultInfo::MemoryAccess {
+                address,
+                source: FaultSource::User,
+            },
+            libc::SIGBUS => FaultInfo::BusError {
+                address,
+                source: FaultSource::User,
+            },
+            libc::SIGFPE => FaultInfo::DivideByZero,
+            _ => FaultInfo::IllegalInstruction,
+        };
+
+        {
+            let _kernel = KernelGuard::acquire();
+            fault_task(me.task.as_ptr(), fault);
+        }
+
+        // A faulted task is only ever resumed by restarting it, which gets it
+        // a new thread. So this never returns.
+        leave_kernel(me);
+        panic!("faulted task resumed");
+    }
+}
+
+/// Manufacture a mutable/exclusive reference to the task table from thin air
+/// and hand it to `body`. This bypasses borrow checking and should only be used
+/// at kernel entry points, then passed around.
+///
+/// Because the lifetime of the reference passed into `body` is anonymous, the
+/// reference can't easily be stored, which is deliberate.
+///
+/// # Safety
+///
+/// You can use this safely at kernel entry points, exactly once, to create a
+/// reference to the task table. Hosted, kernel entry points must also hold the
+/// kernel lock.
+pub unsafe fn with_task_table<R>(
+    body: impl FnOnce(&mut [task::Task]) -> R,
+) -> R {
+    let tasks = core::slice::from_raw_parts_mut(
+        TASK_TABLE_BASE.expect("kernel not started").as_mut(),
+        TASK_TABLE_SIZE,
+    );
+    body(tasks)
+}
+
+/// Manufacture a shared reference to the interrupt action table from thin air
+/// and hand it to `body`. This bypasses borrow checking and should only be used
+/// at kernel entry points, then passed around.
+///
+/// Because the lifetime of the reference passed into `body` is anonymous, the
+/// reference can't easily be stored, which is deliberate.
+pub fn with_irq_table<R>(body: impl FnOnce(&[abi::Interrupt]) -> R) -> R {
+    // Safety: as long as a legit pointer was stored in IRQ_TABLE_BASE, or no
+    // pointer has been stored, we can do this safely.
+    let table = unsafe {
+        core::slice::from_raw_parts(
+            IRQ_TABLE_BASE.expect("kernel not started").as_ptr(),
+            IRQ_TABLE_SIZE,
+        )
+    };
+    body(table)
+}
+
+/// Records the address of `task` as the current user task.
+///
+/// # Safety
+///
+/// This records a pointer that aliases `task`. As long as you don't read that
+/// pointer except at kernel entry, you'll be okay.
+pub unsafe fn set_current_task(task: &mut task::Task) {
+    let next = NonNull::from(&mut *task);
+    if cfg!(feature = "trace") && CURRENT_TASK_PTR != Some(next) {
+        crate::trace::context_switch(
+            CURRENT_TASK_PTR.map(task_index),
+            task_index(next),
+        );
+    }
+    crate::accounting::context_switch(
+        CURRENT_TASK_PTR.map(task_index),
+        task_index(next),
+    );
+    crate::irq_stats::switched_to(task_index(next));
+    CURRENT_THREAD.store(thread_of(task), Ordering::SeqCst);
+    CURRENT_TASK_PTR = Some(NonNull::from(task));
+}
+
+/// Works out the index of `task` within the task table.
+fn task_index(task: NonNull<task::Task>) -> usize {
+    // Safety: this only reads the base pointer, which doesn't change once the
+    // kernel has started.
+    let base = unsafe { TASK_TABLE_BASE.expect("kernel not started") };
+    (task.as_ptr() as usize - base.as_ptr() as usize)
+        / core::mem::size_of::<task::Task>()
+}
+
+/// Resets the system. There's no resetting a process, so we just exit.
+pub fn reset() -> ! {
+    klog!("system reset requested");
+    std::process::exit(0)
+}
+
+/// Reads the kernel clock.
+pub fn now() -> Timestamp {
+    Timestamp::from_ticks(unsafe { TICKS })
+}
+
+/// Notes when the next timer is due. The simulator always ticks at a fixed
+/// rate, so this does nothing: the `tickless` feature only saves power on real
+/// hardware.
+#[cfg(feature = "tickless")]
+pub fn set_tick_deadline(_deadline: Option<Timestamp>) {}
+
+/// Reads a free-running counter for accounting task CPU time. We have no
+/// cycle counter, so this counts nanoseconds of the host's monotonic clock.
+pub fn cycle_count() -> u32 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // Safety: this only writes to `ts`.
+    unsafe {
+        libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts);
+    }
+    (ts.tv_sec as u32)
+        .wrapping_mul(1_000_000_000)
+        .wrapping_add(ts.tv_nsec as u32)
+}
+
+/// Kernel global for tracking the current timestamp, measured in ticks. Only
+/// accessed with the kernel lock held.
+static mut TICKS: u64 = 0;
+
+/// Body of the thread that plays the part of SysTick.
+fn sys_tick_thread() {
+    loop {
+        std::thread::sleep(TICK);
+
+        let _kernel = KernelGuard::acquire();
+        unsafe {
+            let ticks = &mut TICKS;
+            crate::accounting::tick(CURRENT_TASK_PTR.map(task_index));
+            with_task_table(|tasks| safe_sys_tick_handler(ticks, tasks));
+
+            if let Some(finish) = FINISH {
+                if TICKS >= finish.ticks {
+                    with_task_table(|tasks| end_run(finish, tasks));
+                }
+            }
+        }
+    }
+}
+
+/// The meat of the tick handler, after we do the unsafe things.
+fn safe_sys_tick_handler(ticks: &mut u64, tasks: &mut [task::Task]) {
+    // Advance the kernel's notion of time. See the ARM version for why this
+    // isn't a wrapping add.
+    *ticks += 1;
+    let now = Timestamp::from_ticks(*ticks);
+
+    // Process any timers, and any interrupts that arrived since last time.
+    let switch =
+        task::process_timers(tasks, now).combine(deliver_pending_irqs(tasks));
+
+    if switch != task::NextTask::Same {
+        preempt(tasks);
+    }
+}
+
+/// Picks a new task to run and, if that's not the one currently running, asks
+/// the running thread to get out of the way. Roughly `PendSV`.
+fn preempt(tasks: &mut [task::Task]) {
+    let running = CURRENT_THREAD.load(Ordering::SeqCst);
+    let current = unsafe { CURRENT_TASK_PTR }
+        .expect("tick before kernel started?")
+        .as_ptr();
+    let idx = (current as usize - tasks.as_ptr() as usize)
+        / core::mem::size_of::<task::Task>();
+
+    let next = task::select(idx, tasks);
+    let next = &mut tasks[next];
+    apply_memory_protection(next);
+    unsafe {
+        set_current_task(next);
+    }
+
+    if !running.is_null() && CURRENT_THREAD.load(Ordering::SeqCst) != running {
+        unsafe {
+            libc::pthread_kill((*running).pthread, PREEMPT_SIGNAL);
+        }
+    }
+}
+
+/// When to end a hosted run, and how to judge it. Without one, the simulation
+/// runs until the process is killed.
+#[derive(Copy, Clone)]
+pub struct Finish {
+    /// How many ticks to run for.
+    pub ticks: u64,
+    /// Decides, from the task table at the end, whether the application did
+    /// what was expected of it.
+    pub check: fn(&[task::Task]) -> bool,
+}
+
+/// Set by `boot`, before the tick thread starts.
+static mut FINISH: Option<Finish> = None;
+
+/// Ends a run: reports on every task, and exits with status 0 if
+/// `finish.check` is happy with them, or 1 if not.
+fn end_run(finish: Finish, tasks: &[task::Task]) -> ! {
+    for (i, task) in tasks.iter().enumerate() {
+        klog!(
+            "task {}: {:?}, generation {:?}",
+            i,
+            task.state(),
+            task.generation()
+        );
+    }
+    let ok = (finish.check)(tasks);
+    klog!(
+        "stopped after {} ticks: {}",
+        finish.ticks,
+        if ok { "as expected" } else { "NOT as expected" }
+    );
+    std::process::exit(if ok { 0 } else { 1 })
+}
+
+/// Loads the image that `cargo xtask dist` built into `dir`, and starts the
+/// kernel; this stands in for both programming flash and reset.
+///
+/// The image is the application descriptor in `app.bin`, and the tasks in
+/// `combined.srec`. Task memory is mapped at the addresses `dist` allocated,
+/// so they must be free in this process: a hosted `app.toml` should put its
+/// outputs well clear of where Linux puts programs and their heaps.
+pub fn boot(dir: &Path, finish: Option<Finish>) -> ! {
+    let read = |name: &str| {
+        let path = dir.join(name);
+        std::fs::read(&path)
+            .unwrap_or_else(|e| panic!("can't read {}: {}", path.display(), e))
+    };
+
+    // The kernel keeps pointers into the descriptor for as long as it runs,
+    // and expects it to be aligned like the words it's made of.
+    let app_words = read("app.bin")
+        .chunks_exact(4)
+        .map(|w| u32::from_le_bytes([w[0], w[1], w[2], w[3]]))
+        .collect::<Vec<_>>();
+    let app_words: &'static [u32] = Box::leak(app_words.into_boxed_slice());
+    let app_header = app_words.as_ptr() as *const app::App;
+
+    // Safety: start_kernel is about to trust this descriptor with a lot more
+    // than this, and will check it as it goes.
+    unsafe {
+        map_task_memory(&*app_header);
+    }
+    load_srec(&String::from_utf8_lossy(&read("combined.srec")));
+
+    let scratch = Box::leak(vec![0u8; KERNEL_SCRATCH_SIZE].into_boxed_slice());
+    unsafe {
+        FINISH = finish;
+        crate::startup::start_kernel(
+            app_header,
+            scratch.as_mut_ptr(),
+            scratch.len(),
+            CYCLES_PER_TICK,
+        )
+    }
+}
+
+/// Maps host memory for every region in the application that tasks can use,
+/// apart from peripherals (which we don't have).
+///
+/// # Safety
+///
+/// `app_header` must be followed by its regions, as in an application image.
+unsafe fn map_task_memory(app_header: &app::App) {
+    let regions = core::slice::from_raw_parts(
+        (app_header as *const app::App).offset(1) as *const app::RegionDesc,
+        app_header.region_count as usize,
+    );
+
+    // Regions are allocated from a few big outputs, so we map those, or at
+    // least the parts in use, rounded out to whole pages.
+    let page = libc::sysconf(libc::_SC_PAGESIZE) as u32;
+    let mut spans = regions
+        .iter()
+        .filter(|r| {
+            !r.attributes.is_empty()
+                && !r.attributes.contains(app::RegionAttributes::DEVICE)
+        })
+        .map(|r| {
+            let start = r.base & !(page - 1);
+            let end = (r.base + r.size + page - 1) & !(page - 1);
+            (start, end)
+        })
+        .collect::<Vec<_>>();
+    spans.sort_unstable();
+
+    let mut merged: Vec<(u32, u32)> = vec![];
+    for (start, end) in spans {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+
+    for (start, end) in merged {
+        // Tasks can execute from wherever the MPU would let them, which we
+        // check in software; see `task_can_access`.
+        let p = libc::mmap(
+            start as *mut libc::c_void,
+            (end - start) as usize,
+            libc::PROT_READ | libc::PROT_WRITE | libc::PROT_EXEC,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_FIXED_NOREPLACE,
+            -1,
+            0,
+        );
+        if p == libc::MAP_FAILED || p as u32 != start {
+            panic!(
+                "can't map task memory at {:#x}..{:#x}; is something else \
+                 there?",
+                start, end
+            );
+        }
+    }
+}
+
+/// Copies the data in the S-records in `srec` to memory, which must already be
+/// mapped.
+fn load_srec(srec: &str) {
+    for line in srec.lines() {
+        // Only S3 records, with 32-bit addresses, carry data that we need.
+        let hex = match line.trim_end().strip_prefix("S3") {
+            Some(hex) => hex,
+            None => continue,
+        };
+        let bytes = (0..hex.len() / 2)
+            .map(|i| u8::from_str_radix(&hex[2 * i..2 * i + 2], 16))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap_or_else(|_| panic!("bad S-record: {}", line));
+        // The bytes are a count, a big-endian address, the data, and a
+        // checksum.
+        if bytes.len() < 6 || bytes[0] as usize != bytes.len() - 1 {
+            panic!("bad S-record: {}", line);
+        }
+        let addr = u32::from_be_bytes([bytes[1], bytes[2], bytes[3], bytes[4]]);
+        let data = &bytes[5..bytes.len() - 1];
+        // Safety: `map_task_memory` mapped everything that `dist` put in the
+        // image.
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                data.as_ptr(),
+                addr as *mut u8,
+                data.len(),
+            );
+        }
+    }
+}
+
+/// Enough interrupt state for 512 IRQs, the most an NVIC can have.
+const IRQ_WORDS: usize = 16;
+#[allow(clippy::declare_interior_mutable_const)]
+const IRQ_WORD_INIT: AtomicU32 = AtomicU32::new(0);
+static IRQ_ENABLED: [AtomicU32; IRQ_WORDS] = [IRQ_WORD_INIT; IRQ_WORDS];
+static IRQ_PENDING: [AtomicU32; IRQ_WORDS] = [IRQ_WORD_INIT; IRQ_WORDS];
+
+/// Marks simulated interrupt `n` pending. It will be delivered to its task at
+/// the next tick, once enabled.
+pub fn raise_irq(n: u32) {
+    IRQ_PENDING[(n / 32) as usize].fetch_or(1 << (n % 32), Ordering::SeqCst);
+}
+
+/// Posts notifications for any pending, enabled interrupts, returning a
+/// scheduling hint.
+fn deliver_pending_irqs(tasks: &mut [task::Task]) -> task::NextTask {
+    with_irq_table(|irqs| {
+        let mut switch = task::NextTask::Same;
+        for (i, entry) in irqs.iter().enumerate() {
+            let (word, bit) =
+                ((entry.irq / 32) as usize, 1 << (entry.irq % 32));
+            let enabled = IRQ_ENABLED[word].load(Ordering::SeqCst) & bit != 0;
+            let pending = IRQ_PENDING[word].load(Ordering::SeqCst) & bit != 0;
+            if enabled && pending {
+                IRQ_PENDING[word].fetch_and(!bit, Ordering::SeqCst);
+                // As on hardware, the interrupt stays disabled until the task
+                // re-enables it.
+                disable_irq(entry.irq);
+                // Safety: we're in kernel context, so nothing else is looking
+                // at the current task pointer.
+                let current = unsafe { CURRENT_TASK_PTR.map(task_index) };
+                crate::irq_stats::fired(i, current);
+
+                let n = task::NotificationSet(entry.notification);
+                crate::trace::irq(
+                    entry.irq,
+                    entry.task as usize,
+                    entry.notification,
+                );
+                if tasks[entry.task as usize].post(n) {
+                    switch = task::NextTask::Other;
+                }
+            }
+        }
+        switch
+    })
+}
+
+pub fn disable_irq(n: u32) {
+    IRQ_ENABLED[(n / 32) as usize]
+        .fetch_and(!(1 << (n % 32)), Ordering::SeqCst);
+}
+
+pub fn enable_irq(n: u32) {
+    IRQ_ENABLED[(n / 32) as usize].fetch_or(1 << (n % 32), Ordering::SeqCst);
+}
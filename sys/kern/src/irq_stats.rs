@@ -0,0 +1,131 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Per-interrupt statistics.
+//!
+//! When the kernel is built with the `irq-stats` feature, it keeps an
+//! `abi::IrqStats` for each entry in the interrupt table: how many times the
+//! interrupt has fired, how long it then took for its task to be switched to,
+//! and how long the interrupt stayed disabled until the task re-enabled it
+//! with `IRQ_CONTROL`. These can be read with the `read_irq_stats` kernel IPC.
+//!
+//! If the interrupt fires while its task is running, the task is considered to
+//! have woken straight away.
+//!
+//! Times are measured with the architecture's cycle counter, like CPU time in
+//! `accounting`. The counter is only 32 bits wide, so a single wait of more
+//! than 2^32 cycles will be undercounted.
+//!
+//! Without the feature, the functions in this module do nothing.
+//!
+//! All of these functions must be called from kernel context, which can't be
+//! preempted by other kernel entry points, so the table needs no locking.
+
+use abi::{Interrupt, IrqStats};
+
+/// Run-time state of one interrupt table entry.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct IrqState {
+    stats: IrqStats,
+    /// Value of the cycle counter when the interrupt last fired.
+    fired_at: u32,
+    /// Whether the interrupt has fired since its task was last switched to.
+    waking: bool,
+    /// Whether the interrupt has fired since its task last re-enabled it.
+    masked: bool,
+}
+
+/// The application's interrupt table.
+static mut IRQS: &[Interrupt] = &[];
+
+/// State of each entry in the interrupt table, by index.
+static mut STATES: Option<&'static mut [IrqState]> = None;
+
+/// Hands us the application's interrupt table, and a table to keep statistics
+/// in. The second table should have one entry per interrupt if we're keeping
+/// statistics, and none if we're not.
+///
+/// # Safety
+///
+/// This must be called only once, at startup, before any of the other
+/// functions in this module.
+pub unsafe fn set_stats_table(
+    irqs: &'static [Interrupt],
+    table: &'static mut [IrqState],
+) {
+    uassert!(table.is_empty() || table.len() == irqs.len());
+    IRQS = irqs;
+    let prev = core::mem::replace(&mut STATES, Some(table));
+    uassert!(prev.is_none());
+}
+
+/// Notes that the interrupt in table entry `entry` has fired and been disabled,
+/// while task `current` (if any) was running.
+pub fn fired(entry: usize, current: Option<usize>) {
+    if let Some(state) = state_mut(entry) {
+        state.stats.count = state.stats.count.wrapping_add(1);
+        state.fired_at = crate::arch::cycle_count();
+        state.masked = true;
+        // Safety: we're only called from kernel context, which is never
+        // reentered.
+        let owner = unsafe { IRQS[entry].task as usize };
+        state.waking = current != Some(owner);
+    }
+}
+
+/// Notes that task `task` is being switched to, which wakes it for any of its
+/// interrupts that have fired.
+pub fn switched_to(task: usize) {
+    if !cfg!(feature = "irq-stats") {
+        return;
+    }
+
+    let now = crate::arch::cycle_count();
+    // Safety: we're only called from kernel context, which is never
+    // reentered.
+    let irqs = unsafe { IRQS };
+    for (entry, irq) in irqs.iter().enumerate() {
+        if irq.task as usize != task {
+            continue;
+        }
+        if let Some(state) = state_mut(entry) {
+            if state.waking {
+                state.waking = false;
+                let elapsed = now.wrapping_sub(state.fired_at);
+                state.stats.max_wake = state.stats.max_wake.max(elapsed);
+                state.stats.total_wake =
+                    state.stats.total_wake.wrapping_add(u64::from(elapsed));
+            }
+        }
+    }
+}
+
+/// Notes that the task has re-enabled the interrupt in table entry `entry`.
+pub fn enabled(entry: usize) {
+    if let Some(state) = state_mut(entry) {
+        if state.masked {
+            state.masked = false;
+            let elapsed =
+                crate::arch::cycle_count().wrapping_sub(state.fired_at);
+            state.stats.max_masked = state.stats.max_masked.max(elapsed);
+            state.stats.total_masked =
+                state.stats.total_masked.wrapping_add(u64::from(elapsed));
+        }
+    }
+}
+
+/// Returns the statistics for the interrupt in table entry `entry`, or `None`
+/// if we're not keeping any.
+pub fn stats(entry: usize) -> Option<IrqStats> {
+    state_mut(entry).map(|s| s.stats)
+}
+
+fn state_mut(entry: usize) -> Option<&'static mut IrqState> {
+    if !cfg!(feature = "irq-stats") {
+        return None;
+    }
+    // Safety: we're only called from kernel context, which is never
+    // reentered, and we never hand out more than one of these at a time.
+    unsafe { STATES.as_mut().and_then(|table| table.get_mut(entry)) }
+}
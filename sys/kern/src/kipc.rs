@@ -5,9 +5,12 @@
 //! Implementation of IPC operations on the virtual kernel task.
 
 use abi::{FaultInfo, SchedState, TaskState, UsageError};
+use zerocopy::AsBytes;
 
 use crate::err::UserError;
-use crate::task::{current_id, ArchState, NextTask, Task};
+use crate::task::{
+    current_id, ArchState, AsyncSend, AsyncState, NextTask, Task,
+};
 use crate::umem::USlice;
 
 /// Message dispatcher.
@@ -27,6 +30,13 @@ pub fn handle_kernel_message(
         1 => read_task_status(tasks, caller, maybe_message?, maybe_response?),
         2 => restart_task(tasks, caller, maybe_message?),
         3 => fault_task(tasks, caller, maybe_message?),
+        4 => reset(caller),
+        5 => read_stack_usage(tasks, caller, maybe_message?, maybe_response?),
+        6 => read_fault_detail(tasks, caller, maybe_message?, maybe_response?),
+        7 => read_cpu_usage(tasks, caller, maybe_message?, maybe_response?),
+        8 => read_irq_stats(tasks, caller, maybe_message?, maybe_response?),
+        9 => start_task(tasks, caller, maybe_message?),
+        11 => software_irq(tasks, caller, maybe_message?),
         _ => {
             // Task has sent an unknown message to the kernel. That's bad.
             return Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
@@ -80,11 +90,108 @@ fn read_task_status(
             UsageError::TaskOutOfRange,
         )));
     }
-    // cache other state before taking out a mutable borrow on tasks
-    let other_state = *tasks[index as usize].state();
+    // cache other state before taking out a mutable borrow on tasks. The
+    // priority is the task's current one, which differs from its base
+    // priority if it's inheriting another task's.
+    let other = &tasks[index as usize];
+    let other_status = (*other.state(), other.priority().0, other.is_ready());
 
     let response_len =
-        serialize_response(&mut tasks[caller], response, &other_state)?;
+        serialize_response(&mut tasks[caller], response, &other_status)?;
+    tasks[caller]
+        .save_mut()
+        .set_send_response_and_length(0, response_len);
+    Ok(NextTask::Same)
+}
+
+fn read_fault_detail(
+    tasks: &mut [Task],
+    caller: usize,
+    message: USlice<u8>,
+    mut response: USlice<u8>,
+) -> Result<NextTask, UserError> {
+    let index: u32 = deserialize_message(&tasks[caller], message)?;
+    if index as usize >= tasks.len() {
+        return Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
+            UsageError::TaskOutOfRange,
+        )));
+    }
+    // `FaultDetail` is too big for serde, so it goes back as raw bytes.
+    let detail = *tasks[index as usize].fault_detail();
+    let bytes = detail.as_bytes();
+    let buf = tasks[caller].try_write(&mut response)?;
+    // As with `serialize_response`, a buffer that's too small gets nothing,
+    // along with the size of one that would have worked.
+    if let Some(buf) = buf.get_mut(..bytes.len()) {
+        buf.copy_from_slice(bytes);
+    }
+    tasks[caller]
+        .save_mut()
+        .set_send_response_and_length(0, bytes.len());
+    Ok(NextTask::Same)
+}
+
+fn read_cpu_usage(
+    tasks: &mut [Task],
+    caller: usize,
+    message: USlice<u8>,
+    response: USlice<u8>,
+) -> Result<NextTask, UserError> {
+    let index: u32 = deserialize_message(&tasks[caller], message)?;
+    if index as usize >= tasks.len() {
+        return Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
+            UsageError::TaskOutOfRange,
+        )));
+    }
+    let usage = crate::accounting::usage(index as usize);
+
+    let response_len =
+        serialize_response(&mut tasks[caller], response, &usage)?;
+    tasks[caller]
+        .save_mut()
+        .set_send_response_and_length(0, response_len);
+    Ok(NextTask::Same)
+}
+
+fn read_irq_stats(
+    tasks: &mut [Task],
+    caller: usize,
+    message: USlice<u8>,
+    response: USlice<u8>,
+) -> Result<NextTask, UserError> {
+    let irq: u32 = deserialize_message(&tasks[caller], message)?;
+    let entry = crate::arch::with_irq_table(|irqs| {
+        irqs.iter().position(|entry| entry.irq == irq)
+    })
+    .ok_or(UserError::Unrecoverable(FaultInfo::SyscallUsage(
+        UsageError::NoIrq,
+    )))?;
+    let stats = crate::irq_stats::stats(entry);
+
+    let response_len =
+        serialize_response(&mut tasks[caller], response, &stats)?;
+    tasks[caller]
+        .save_mut()
+        .set_send_response_and_length(0, response_len);
+    Ok(NextTask::Same)
+}
+
+fn read_stack_usage(
+    tasks: &mut [Task],
+    caller: usize,
+    message: USlice<u8>,
+    response: USlice<u8>,
+) -> Result<NextTask, UserError> {
+    let index: u32 = deserialize_message(&tasks[caller], message)?;
+    if index as usize >= tasks.len() {
+        return Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
+            UsageError::TaskOutOfRange,
+        )));
+    }
+    let usage = crate::arch::stack_usage(&tasks[index as usize]);
+
+    let response_len =
+        serialize_response(&mut tasks[caller], response, &usage)?;
     tasks[caller]
         .save_mut()
         .set_send_response_and_length(0, response_len);
@@ -106,6 +213,8 @@ fn restart_task(
     }
     let old_id = current_id(tasks, index);
     tasks[index].reinitialize();
+    // Any locks the task held are up for grabs.
+    let mut next_task = crate::lock::release_all(tasks, index);
     if start {
         tasks[index].set_healthy_state(SchedState::Runnable);
     }
@@ -115,6 +224,30 @@ fn restart_task(
     // example. So, make a pass over the task table and unblock anyone who was
     // expecting useful work from the now-defunct task.
     for (i, task) in tasks.iter_mut().enumerate() {
+        // The defunct task won't be replying to anything it was sent, late or
+        // otherwise.
+        task.clear_abandoned_send(index);
+
+        // Asynchronous sends to the defunct task get the same treatment as
+        // the synchronous kind below, except that we notify the sender, who
+        // may not even be blocked.
+        for slot in 0..task.async_sends().len() {
+            match task.async_sends()[slot].state {
+                AsyncState::Queued(peer) | AsyncState::Delivered(peer)
+                    if peer == old_id =>
+                {
+                    let code = abi::dead_response_code(peer.generation());
+                    if task.complete_async_send(slot, code, 0) {
+                        next_task = NextTask::Other;
+                    }
+                }
+                AsyncState::Abandoned(peer) if peer == old_id => {
+                    task.async_sends_mut()[slot] = AsyncSend::free();
+                }
+                _ => (),
+            }
+        }
+
         // Just to make this a little easier to think about, don't check either
         // of the tasks involved in the restart operation. Neither should be
         // affected anyway.
@@ -153,6 +286,86 @@ fn restart_task(
     } else {
         tasks[caller].save_mut().set_send_response_and_length(0, 0);
     }
+    Ok(next_task)
+}
+
+/// Starts a task that's stopped, without reinitializing it first as
+/// `restart_task` does. A task is only ever stopped if it's freshly
+/// initialized, so there's nothing to undo, and unlike a restart this leaves
+/// its generation -- and so the `TaskId`s others hold for it -- alone. If the
+/// task isn't stopped, this does nothing.
+///
+/// Tasks are left stopped so that the supervisor can start them when it sees
+/// fit, so only the supervisor may start them.
+fn start_task(
+    tasks: &mut [Task],
+    caller: usize,
+    message: USlice<u8>,
+) -> Result<NextTask, UserError> {
+    if caller != 0 {
+        return Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
+            UsageError::IllegalTask,
+        )));
+    }
+    let index: u32 = deserialize_message(&tasks[caller], message)?;
+    let index = index as usize;
+    if index >= tasks.len() {
+        return Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
+            UsageError::TaskOutOfRange,
+        )));
+    }
+
+    tasks[caller].save_mut().set_send_response_and_length(0, 0);
+    if tasks[index].state() == &TaskState::Healthy(SchedState::Stopped) {
+        tasks[index].set_healthy_state(SchedState::Runnable);
+        Ok(NextTask::Other)
+    } else {
+        Ok(NextTask::Same)
+    }
+}
+
+/// Raises, as if the hardware had, every interrupt that the application routes
+/// to task `index` with a notification in `mask`. Each is delivered in the
+/// usual way -- once the task has it enabled -- so this lets the supervisor
+/// (or a test) exercise a task's interrupt handling without the hardware's
+/// help.
+///
+/// Only the supervisor may do this.
+fn software_irq(
+    tasks: &mut [Task],
+    caller: usize,
+    message: USlice<u8>,
+) -> Result<NextTask, UserError> {
+    if caller != 0 {
+        return Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
+            UsageError::IllegalTask,
+        )));
+    }
+    let (index, mask): (u32, u32) =
+        deserialize_message(&tasks[caller], message)?;
+    if index as usize >= tasks.len() {
+        return Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
+            UsageError::TaskOutOfRange,
+        )));
+    }
+
+    let found = crate::arch::with_irq_table(|irqs| {
+        let mut found = false;
+        for irq in irqs {
+            if irq.task == index && irq.notification & mask != 0 {
+                crate::arch::raise_irq(irq.irq);
+                found = true;
+            }
+        }
+        found
+    });
+    if !found {
+        return Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
+            UsageError::NoIrq,
+        )));
+    }
+
+    tasks[caller].save_mut().set_send_response_and_length(0, 0);
     Ok(NextTask::Same)
 }
 
@@ -191,3 +404,17 @@ fn fault_task(
 
     Ok(NextTask::Same)
 }
+
+///
+/// Reset the system.  This is the supervisor's last resort when it can't
+/// recover by restarting tasks, so only the supervisor may ask for it.
+///
+fn reset(caller: usize) -> Result<NextTask, UserError> {
+    if caller != 0 {
+        return Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
+            UsageError::IllegalTask,
+        )));
+    }
+
+    crate::arch::reset()
+}
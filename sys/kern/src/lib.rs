@@ -33,11 +33,15 @@
 #[macro_use]
 pub mod arch;
 
+pub mod accounting;
 pub mod app;
 pub mod err;
+pub mod irq_stats;
 pub mod kipc;
+pub mod lock;
 pub mod startup;
 pub mod syscalls;
 pub mod task;
 pub mod time;
+pub mod trace;
 pub mod umem;
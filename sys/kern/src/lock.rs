@@ -0,0 +1,190 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Application-defined locks.
+//!
+//! The application can declare locks, each usable by a fixed set of tasks,
+//! which tasks take with the `LOCK` syscall and release with `UNLOCK`. This
+//! lets tasks share a resource (such as a bus) directly, rather than
+//! funnelling every use of it through a server.
+//!
+//! A task that tries to take a lock that's held blocks in the `InLock` state
+//! until the lock is handed to it. When a lock is released, it goes to the most
+//! important task waiting for it, so waiting tasks don't need to be queued.
+//! With the `priority-inheritance` feature, the owner of a lock also runs at
+//! the priority of the most important task waiting for it.
+//!
+//! If the owner of a lock faults or is restarted, the lock is released on its
+//! behalf, and the next owner is told that this happened, since the owner may
+//! have left the resource in a strange state.
+//!
+//! As with `accounting`, all of these functions must be called from kernel
+//! context, so the table needs no locking of its own.
+
+use abi::{FaultInfo, LockDesc, SchedState, TaskState, UsageError};
+
+use crate::task::{self, ArchState, NextTask, Task};
+
+/// Run-time state of one lock.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct LockState {
+    /// Index of the task holding the lock, if any.
+    owner: Option<usize>,
+    /// Whether the lock was last released because its owner faulted or was
+    /// restarted. Cleared when it's next taken.
+    owner_died: bool,
+}
+
+/// Descriptions of the locks, from the application.
+static mut DESCS: &[LockDesc] = &[];
+
+/// State of each lock, by index.
+static mut LOCKS: Option<&'static mut [LockState]> = None;
+
+/// Hands us the application's lock descriptions, and a table to keep their
+/// state in, which should have one entry per description.
+///
+/// # Safety
+///
+/// This must be called only once, at startup, before any of the other
+/// functions in this module.
+pub unsafe fn set_lock_table(
+    descs: &'static [LockDesc],
+    table: &'static mut [LockState],
+) {
+    uassert_eq!(descs.len(), table.len());
+    DESCS = descs;
+    let prev = core::mem::replace(&mut LOCKS, Some(table));
+    uassert!(prev.is_none());
+}
+
+/// Returns the index of the task that holds lock `index`, if it exists and is
+/// held.
+pub fn owner(index: usize) -> Option<usize> {
+    locks().get(index).and_then(|l| l.owner)
+}
+
+/// Tries to take lock `index` on behalf of task `caller`.
+///
+/// If the lock is free, the caller takes it and can carry on. If not, the
+/// caller either blocks until it's handed the lock, or (if `try_only` is set)
+/// gets `LOCK_BUSY`. Either way, the caller is eventually given `0` or
+/// `LOCK_OWNER_DIED` once it holds the lock.
+pub fn lock(
+    tasks: &mut [Task],
+    caller: usize,
+    index: u32,
+    try_only: bool,
+) -> Result<NextTask, FaultInfo> {
+    let lock = checked_lock(index, caller)?;
+    match lock.owner {
+        None => {
+            let code = take(lock, caller);
+            tasks[caller].save_mut().set_error_response(code);
+            Ok(NextTask::Same)
+        }
+        Some(owner) if owner == caller => {
+            Err(FaultInfo::SyscallUsage(UsageError::LockAlreadyHeld))
+        }
+        Some(_) if try_only => {
+            tasks[caller].save_mut().set_error_response(abi::LOCK_BUSY);
+            Ok(NextTask::Same)
+        }
+        Some(_) => {
+            tasks[caller].set_healthy_state(SchedState::InLock(index));
+            Ok(NextTask::Other)
+        }
+    }
+}
+
+/// Releases lock `index`, which must be held by task `caller`, and hands it on
+/// to the most important task waiting for it, if any.
+pub fn unlock(
+    tasks: &mut [Task],
+    caller: usize,
+    index: u32,
+) -> Result<NextTask, FaultInfo> {
+    let lock = checked_lock(index, caller)?;
+    if lock.owner != Some(caller) {
+        return Err(FaultInfo::SyscallUsage(UsageError::LockNotHeld));
+    }
+    tasks[caller].save_mut().set_error_response(0);
+
+    let next = hand_on(tasks, index as usize, caller);
+
+    // As with POST, only switch if the new owner is more important than us.
+    match next {
+        Some(next)
+            if tasks[next]
+                .priority()
+                .is_more_important_than(tasks[caller].priority()) =>
+        {
+            Ok(NextTask::Specific(next))
+        }
+        _ => Ok(NextTask::Same),
+    }
+}
+
+/// Releases every lock held by task `index`, which is faulting or being
+/// restarted, handing each on to the most important task waiting for it.
+pub fn release_all(tasks: &mut [Task], index: usize) -> NextTask {
+    let mut next_task = NextTask::Same;
+    for i in 0..locks().len() {
+        if locks()[i].owner == Some(index) {
+            locks()[i].owner_died = true;
+            if hand_on(tasks, i, index).is_some() {
+                next_task = NextTask::Other;
+            }
+        }
+    }
+    next_task
+}
+
+/// Looks up lock `index` for use by task `caller`, faulting if it doesn't exist
+/// or the caller isn't allowed to use it.
+fn checked_lock(
+    index: u32,
+    caller: usize,
+) -> Result<&'static mut LockState, FaultInfo> {
+    let index = index as usize;
+    // Safety: we're only called from kernel context, which is never
+    // reentered.
+    let desc = unsafe { DESCS.get(index) };
+    match (desc, locks().get_mut(index)) {
+        (Some(desc), Some(lock)) if desc.may_be_used_by(caller) => Ok(lock),
+        _ => Err(FaultInfo::SyscallUsage(UsageError::LockOutOfRange)),
+    }
+}
+
+/// Gives the free lock `lock` to task `task`, returning the response code it
+/// should be given.
+fn take(lock: &mut LockState, task: usize) -> u32 {
+    lock.owner = Some(task);
+    if core::mem::replace(&mut lock.owner_died, false) {
+        abi::LOCK_OWNER_DIED
+    } else {
+        0
+    }
+}
+
+/// Takes lock `index` away from task `previous`, and gives it to the most
+/// important task waiting for it, if any, returning that task's index.
+fn hand_on(tasks: &mut [Task], index: usize, previous: usize) -> Option<usize> {
+    let lock = &mut locks()[index];
+    lock.owner = None;
+
+    let waiting = TaskState::Healthy(SchedState::InLock(index as u32));
+    let next = task::priority_scan(previous, tasks, |t| t.state() == &waiting)?;
+    let code = take(lock, next);
+    tasks[next].save_mut().set_error_response(code);
+    tasks[next].set_healthy_state(SchedState::Runnable);
+    Some(next)
+}
+
+fn locks() -> &'static mut [LockState] {
+    // Safety: we're only called from kernel context, which is never
+    // reentered, and callers don't hold on to these across calls that could
+    // produce another.
+    unsafe { LOCKS.as_deref_mut().unwrap_or(&mut []) }
+}
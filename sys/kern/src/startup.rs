@@ -4,7 +4,11 @@
 
 //! Kernel startup.
 
+use abi::CpuUsage;
+
 use crate::app;
+use crate::irq_stats::IrqState;
+use crate::lock::LockState;
 use crate::task::{self, Task};
 
 /// The main kernel entry point.
@@ -53,7 +57,7 @@ pub unsafe fn start_kernel(
     uassert!(app_header.region_count < 256);
 
     // Check that no mysterious data appears in the reserved space.
-    uassert_eq!(app_header.zeroed_expansion_space, [0; 12]);
+    uassert_eq!(app_header.zeroed_expansion_space, [0; 8]);
 
     // Derive the addresses of the other regions from the app header.
     // Regions come first.
@@ -68,12 +72,19 @@ pub unsafe fn start_kernel(
     let tasks =
         core::slice::from_raw_parts(tasks_ptr, app_header.task_count as usize);
 
+    let interrupts_ptr = tasks_ptr.offset(app_header.task_count as isize)
+        as *const app::Interrupt;
     let interrupts = core::slice::from_raw_parts(
-        tasks_ptr.offset(app_header.task_count as isize)
-            as *const app::Interrupt,
+        interrupts_ptr,
         app_header.irq_count as usize,
     );
 
+    let locks = core::slice::from_raw_parts(
+        interrupts_ptr.offset(app_header.irq_count as isize)
+            as *const app::LockDesc,
+        app_header.lock_count as usize,
+    );
+
     // Validate regions first, since tasks will use them.
     for region in regions {
         // Check for use of reserved attributes.
@@ -85,13 +96,24 @@ pub unsafe fn start_kernel(
         // Check for suspicious use of reserved word
         uassert_eq!(region.reserved_zero, 0);
 
+        // On ARMv7-M, a region is a power of two, or the bottom few eighths
+        // of one, which only regions of 256 bytes or more can have.
         #[cfg(armv7m)]
-        uassert!(region.size.is_power_of_two());
+        uassert!(
+            region.size.is_power_of_two()
+                || (region.size.next_power_of_two() >= 256
+                    && region.size % (region.size.next_power_of_two() / 8)
+                        == 0)
+        );
     }
 
-    // Validate tasks next.
+    // Validate tasks next. Tables of tasks by index, like `ipc_allow`, only
+    // have room for so many.
+    uassert!(app_header.task_count as usize <= 32 * app::IPC_ALLOW_WORDS);
     for task in tasks {
         uassert!(!task.flags.intersects(app::TaskFlags::RESERVED));
+        // Check that the IPC allow-list names only tasks that exist.
+        check_task_bitmap(&task.ipc_allow, app_header.task_count);
 
         let mut entry_pt_found = false;
         let mut stack_ptr_found = false;
@@ -125,22 +147,41 @@ pub unsafe fn start_kernel(
         uassert!(irq.task < tasks.len() as u32);
     }
 
+    // And locks, which must only name tasks that exist.
+    for lock in locks {
+        check_task_bitmap(&lock.users, app_header.task_count);
+    }
+
     // Okay, we're pretty sure this is all legitimate.
     safe_start_kernel(
         app_header,
         tasks,
         regions,
         interrupts,
+        locks,
         alloc,
         tick_divisor,
     )
 }
 
+/// Checks that a bitmap of tasks by index, like `TaskDesc::ipc_allow`, names
+/// only tasks that exist.
+fn check_task_bitmap(bitmap: &[u32], task_count: u32) {
+    for (i, &word) in bitmap.iter().enumerate() {
+        let first = i as u32 * 32;
+        let count = task_count.saturating_sub(first);
+        if count < 32 {
+            uassert_eq!(word >> count, 0);
+        }
+    }
+}
+
 fn safe_start_kernel(
     app_header: &'static app::App,
     task_descs: &'static [app::TaskDesc],
     region_descs: &'static [app::RegionDesc],
     interrupts: &'static [app::Interrupt],
+    locks: &'static [app::LockDesc],
     mut alloc: BumpPointer,
     tick_divisor: u32,
 ) -> ! {
@@ -175,6 +216,23 @@ fn safe_start_kernel(
 
     uassert!(tasks.len() != 0); // tasks must exist for this to work.
 
+    // And a place to keep track of how much CPU each task uses.
+    let cpu_usage =
+        alloc.gimme_n(app_header.task_count as usize, |_| CpuUsage::default());
+
+    // And the state of each lock.
+    let lock_states = alloc.gimme_n(locks.len(), |_| LockState::default());
+
+    // And, if we're keeping them, statistics for each interrupt.
+    let irq_stats = alloc.gimme_n(
+        if cfg!(feature = "irq-stats") {
+            interrupts.len()
+        } else {
+            0
+        },
+        |_| IrqState::default(),
+    );
+
     // With that done, set up initial register state etc.
     for task in tasks.iter_mut() {
         crate::arch::reinitialize(task);
@@ -192,6 +250,9 @@ fn safe_start_kernel(
     unsafe {
         crate::arch::set_task_table(tasks);
         crate::arch::set_irq_table(interrupts);
+        crate::accounting::set_usage_table(cpu_usage);
+        crate::lock::set_lock_table(locks, lock_states);
+        crate::irq_stats::set_stats_table(interrupts, irq_stats);
     }
     task::set_fault_notification(app_header.fault_notification);
 
@@ -30,13 +30,15 @@
 use core::convert::TryFrom;
 
 use abi::{
-    FaultInfo, LeaseAttributes, SchedState, Sysnum, TaskId, TaskState,
-    UsageError,
+    AsyncOutcome, FaultInfo, LeaseAttributes, SchedState, Sysnum, TaskId,
+    TaskState, UsageError,
 };
 
 use crate::arch;
 use crate::err::{InteractFault, UserError};
-use crate::task::{self, current_id, ArchState, NextTask, Task};
+use crate::task::{
+    self, current_id, ArchState, AsyncSend, AsyncState, NextTask, Task,
+};
 use crate::time::Timestamp;
 use crate::umem::{safe_copy, ULease, USlice};
 
@@ -78,11 +80,17 @@ pub unsafe extern "C" fn syscall_entry(nr: u32, task: *mut Task) {
 /// Factored out of `syscall_entry` to encapsulate the bits that don't need
 /// unsafe.
 fn safe_syscall_entry(nr: u32, current: usize, tasks: &mut [Task]) -> NextTask {
-    let res = match Sysnum::try_from(nr) {
-        Ok(Sysnum::Send) => send(tasks, current),
+    let peer = crate::trace::syscall_peer(nr, &tasks[current]);
+    let sysnum = Sysnum::try_from(nr);
+    let res = match sysnum {
+        Ok(Sysnum::Send) => send(tasks, current, false),
         Ok(Sysnum::Recv) => recv(tasks, current).map_err(UserError::from),
         Ok(Sysnum::Reply) => reply(tasks, current).map_err(UserError::from),
-        Ok(Sysnum::SetTimer) => Ok(set_timer(&mut tasks[current], arch::now())),
+        Ok(Sysnum::SetTimer) => {
+            let next = set_timer(&mut tasks[current], arch::now());
+            task::reschedule_tick(tasks);
+            Ok(next)
+        }
         Ok(Sysnum::BorrowRead) => borrow_read(tasks, current),
         Ok(Sysnum::BorrowWrite) => borrow_write(tasks, current),
         Ok(Sysnum::BorrowInfo) => borrow_info(tasks, current),
@@ -91,46 +99,121 @@ fn safe_syscall_entry(nr: u32, current: usize, tasks: &mut [Task]) -> NextTask {
         Ok(Sysnum::GetTimer) => Ok(get_timer(&mut tasks[current], arch::now())),
         Ok(Sysnum::RefreshTaskId) => refresh_task_id(tasks, current),
         Ok(Sysnum::Post) => post(tasks, current),
+        Ok(Sysnum::SendAsync) => send_async(tasks, current),
+        Ok(Sysnum::AsyncStatus) => async_status(tasks, current),
+        Ok(Sysnum::SendTimeout) => send_timeout(tasks, current),
+        Ok(Sysnum::Lock) => lock(tasks, current).map_err(UserError::from),
+        Ok(Sysnum::Unlock) => unlock(tasks, current).map_err(UserError::from),
         Err(_) => {
             // Bogus syscall number! That's a fault.
             Err(FaultInfo::SyscallUsage(UsageError::BadSyscallNumber).into())
         }
     };
-    match res {
+    crate::trace::syscall(
+        nr,
+        current,
+        peer,
+        &res,
+        tasks[current].is_runnable(),
+    );
+    let next = match res {
         Ok(nt) => nt,
         Err(UserError::Recoverable(code, hint)) => {
             tasks[current].save_mut().set_error_response(code);
             hint
         }
         Err(UserError::Unrecoverable(fault)) => {
-            task::force_fault(tasks, current, fault)
+            // This recomputes priorities itself.
+            return task::force_fault(tasks, current, fault);
         }
+    };
+
+    // Only these can change who's waiting on whom (a SEND to the kernel can
+    // restart or fault a task), and so shift priorities under the hint we've
+    // got; none of the others can.
+    let waits_changed = matches!(
+        sysnum,
+        Ok(Sysnum::Send
+            | Sysnum::SendTimeout
+            | Sysnum::Recv
+            | Sysnum::Reply
+            | Sysnum::Lock
+            | Sysnum::Unlock)
+    );
+    if waits_changed && task::update_priorities(tasks) {
+        NextTask::Other
+    } else {
+        next
     }
 }
 
+/// Checks that the application allows `caller` to send to, or post to, `peer`,
+/// faulting it if not.
+///
+/// This is checked before the generation of `peer`, so that a task can't use
+/// its dead-task responses to learn about tasks it has no business with.
+/// Out-of-range task IDs are left for `check_task_id_against_table` to fault.
+fn check_ipc_allowed(
+    tasks: &[Task],
+    caller: usize,
+    peer: TaskId,
+) -> Result<(), UserError> {
+    let index = peer.index();
+    if index < tasks.len()
+        && index != caller
+        && !tasks[caller].descriptor().may_send_to(index)
+    {
+        return Err(FaultInfo::SyscallUsage(UsageError::IpcNotPermitted).into());
+    }
+    Ok(())
+}
+
 /// Implementation of the SEND IPC primitive.
 ///
+/// If `times_out` is true, the caller's timer will abandon the send if it
+/// fires before the reply arrives; see `send_timeout`.
+///
 /// `caller` is a valid task index (i.e. not directly from user code).
 ///
 /// # Panics
 ///
 /// If `caller` is out of range for `tasks`.
-fn send(tasks: &mut [Task], caller: usize) -> Result<NextTask, UserError> {
+fn send(
+    tasks: &mut [Task],
+    caller: usize,
+    times_out: bool,
+) -> Result<NextTask, UserError> {
     // Extract callee.
     let callee_id = tasks[caller].save().as_send_args().callee();
 
-    // Check IPC filter - TODO
-    // Open question: should out-of-range task IDs be handled by faulting below,
-    // or by failing the IPC filter? Either condition will fault...
+    tasks[caller].set_timer_cancels_send(times_out);
 
-    // Route kernel messages.
+    // Route kernel messages. Every task may talk to the kernel.
     if callee_id == TaskId::KERNEL {
         return crate::kipc::handle_kernel_message(tasks, caller);
     }
 
     // Verify the given callee ID, converting it into a table index on success.
+    check_ipc_allowed(tasks, caller, callee_id)?;
     let callee = task::check_task_id_against_table(tasks, callee_id)?;
 
+    // If we already have an asynchronous send outstanding to the callee, we
+    // wouldn't be able to tell which message its REPLY was meant for.
+    if tasks[caller].has_async_send_to(callee_id) {
+        return Err(
+            FaultInfo::SyscallUsage(UsageError::AsyncSendOutstanding).into()
+        );
+    }
+    // The same goes for a message that we gave up waiting on, but which the
+    // callee still has. Unlike the above, the caller can't be expected to
+    // keep track of that, so we treat it as another timeout.
+    if tasks[caller].has_abandoned_send_to(callee) {
+        return Err(UserError::Recoverable(
+            abi::SEND_TIMED_OUT,
+            NextTask::Same,
+        ));
+    }
+
     // Check for ready peer.
     let mut next_task = NextTask::Same;
     let caller_id = current_id(tasks, caller);
@@ -165,6 +248,179 @@ fn send(tasks: &mut [Task], caller: usize) -> Result<NextTask, UserError> {
     return Ok(NextTask::Other.combine(next_task));
 }
 
+/// Implementation of the SEND_TIMEOUT IPC primitive.
+///
+/// This takes the same arguments as SEND, but uses the caller's timer (as set
+/// by SET_TIMER) as a deadline: if the timer fires before the callee replies,
+/// the send is abandoned and the caller resumes with `abi::SEND_TIMED_OUT`.
+/// The timer's notification bits, if any, are posted as usual.
+///
+/// If the caller's timer isn't set, we take that to mean the deadline has
+/// already passed, and fail immediately.
+///
+/// If the timer fires after the callee has received the message, the callee
+/// may still REPLY to it (or borrow from its leases) later. So that can't be
+/// confused with a later message, the caller may not send to the callee again
+/// -- by any means -- until it has replied: attempts fail with
+/// `abi::SEND_TIMED_OUT` (or `abi::ASYNC_PEER_BUSY`) straight away. The late
+/// reply itself is discarded.
+///
+/// `caller` is a valid task index (i.e. not directly from user code).
+///
+/// # Panics
+///
+/// If `caller` is out of range for `tasks`.
+fn send_timeout(
+    tasks: &mut [Task],
+    caller: usize,
+) -> Result<NextTask, UserError> {
+    if tasks[caller].timer().0.is_none() {
+        return Err(UserError::Recoverable(
+            abi::SEND_TIMED_OUT,
+            NextTask::Same,
+        ));
+    }
+    send(tasks, caller, true)
+}
+
+/// Implementation of the SEND_ASYNC IPC primitive.
+///
+/// This queues a message for the callee without blocking the caller. The
+/// message is delivered when the callee next receives, just as if the caller
+/// were blocked in SEND, and the callee's REPLY is written into the caller's
+/// response buffer, after which the caller is notified. The caller collects
+/// the outcome with ASYNC_STATUS.
+///
+/// Because the caller keeps running, it must leave its message and response
+/// buffers alone until the send completes or is cancelled.
+///
+/// `caller` is a valid task index (i.e. not directly from user code).
+///
+/// # Panics
+///
+/// If `caller` is out of range for `tasks`.
+fn send_async(
+    tasks: &mut [Task],
+    caller: usize,
+) -> Result<NextTask, UserError> {
+    let args = tasks[caller].save().as_send_async_args();
+    let callee_id = args.callee();
+    let operation = args.operation();
+    let message = args.message()?;
+    let response = args.response_buffer()?;
+    let notification = args.notification();
+    drop(args);
+
+    // The kernel only speaks synchronously, and sending to yourself would
+    // never be received.
+    if callee_id == TaskId::KERNEL || callee_id.index() == caller {
+        return Err(FaultInfo::SyscallUsage(UsageError::IllegalTask).into());
+    }
+
+    check_ipc_allowed(tasks, caller, callee_id)?;
+    let callee = task::check_task_id_against_table(tasks, callee_id)?;
+
+    if tasks[caller].has_async_send_to(callee_id)
+        || tasks[caller].has_abandoned_send_to(callee)
+    {
+        return Err(UserError::Recoverable(
+            abi::ASYNC_PEER_BUSY,
+            NextTask::Same,
+        ));
+    }
+
+    let slot = tasks[caller]
+        .async_sends()
+        .iter()
+        .position(|a| a.state == AsyncState::Free)
+        .ok_or(UserError::Recoverable(
+            abi::ASYNC_QUEUE_FULL,
+            NextTask::Same,
+        ))?;
+
+    tasks[caller].async_sends_mut()[slot] = AsyncSend {
+        state: AsyncState::Queued(callee_id),
+        operation,
+        message,
+        response,
+        notification,
+    };
+    tasks[caller]
+        .save_mut()
+        .set_send_response_and_length(0, slot);
+
+    // If the callee is already waiting, we can deliver right away.
+    let caller_id = current_id(tasks, caller);
+    if tasks[callee].state().can_accept_message_from(caller_id) {
+        match deliver_async(tasks, caller, slot, callee) {
+            Ok(_) => {
+                // Unlike SEND, we stay runnable, so only switch if the callee
+                // is more important.
+                let caller_p = tasks[caller].priority();
+                let callee_p = tasks[callee].priority();
+                if callee_p.is_more_important_than(caller_p) {
+                    return Ok(NextTask::Specific(callee));
+                }
+            }
+            Err(interact) => {
+                // The message stays queued if only the callee was at fault.
+                return Ok(interact.apply_to_dst(tasks, callee)?);
+            }
+        }
+    }
+
+    Ok(NextTask::Same)
+}
+
+/// Implementation of the ASYNC_STATUS syscall, which collects the outcome of an
+/// asynchronous send, or cancels it.
+///
+/// If the send has completed, this returns the response code and length of
+/// the reply and frees the slot, whether or not cancellation was requested.
+/// Otherwise, a cancelled send is withdrawn -- or, if the callee has already
+/// received it, its eventual reply is discarded.
+fn async_status(
+    tasks: &mut [Task],
+    caller: usize,
+) -> Result<NextTask, UserError> {
+    let args = tasks[caller].save().as_async_status_args();
+    let slot = args.slot();
+    let cancel = args.cancel();
+    drop(args);
+
+    let task = &mut tasks[caller];
+    let send = task
+        .async_sends_mut()
+        .get_mut(slot)
+        .ok_or(FaultInfo::SyscallUsage(UsageError::BadAsyncSlot))?;
+
+    let (outcome, code, len) = match send.state {
+        AsyncState::Free | AsyncState::Abandoned(_) => {
+            return Err(
+                FaultInfo::SyscallUsage(UsageError::BadAsyncSlot).into()
+            );
+        }
+        AsyncState::Complete { code, len } => {
+            send.state = AsyncState::Free;
+            (AsyncOutcome::Complete, code, len)
+        }
+        AsyncState::Queued(_) if cancel => {
+            send.state = AsyncState::Free;
+            (AsyncOutcome::Cancelled, 0, 0)
+        }
+        AsyncState::Delivered(peer) if cancel => {
+            send.state = AsyncState::Abandoned(peer);
+            (AsyncOutcome::Cancelled, 0, 0)
+        }
+        AsyncState::Queued(_) | AsyncState::Delivered(_) => {
+            (AsyncOutcome::Pending, 0, 0)
+        }
+    };
+
+    task.save_mut().set_async_status_result(outcome, code, len);
+    Ok(NextTask::Same)
+}
+
 /// Implementation of the RECV IPC primitive.
 ///
 /// `caller` is a valid task index (i.e. not directly from user code).
@@ -173,6 +429,16 @@ fn send(tasks: &mut [Task], caller: usize) -> Result<NextTask, UserError> {
 ///
 /// If `caller` is out of range for `tasks`.
 fn recv(tasks: &mut [Task], caller: usize) -> Result<NextTask, UserError> {
+    let specific_sender = tasks[caller].save().as_recv_args().specific_sender();
+
+    // An open RECV is taken to mean that the caller is ready to serve, which
+    // the supervisor may be waiting to hear, even if it doesn't block here.
+    let mut next_task = if specific_sender.is_none() {
+        task::note_open_recv(tasks, caller)
+    } else {
+        NextTask::Same
+    };
+
     // We allow tasks to atomically replace their notification mask at each
     // receive. We simultaneously find out if there are notifications pending.
     if let Some(firing) = tasks[caller].take_notifications() {
@@ -184,15 +450,11 @@ fn recv(tasks: &mut [Task], caller: usize) -> Result<NextTask, UserError> {
             0,
             0,
         );
-        return Ok(NextTask::Same);
+        return Ok(next_task);
     }
 
     let caller_id = current_id(tasks, caller);
 
-    let specific_sender = tasks[caller].save().as_recv_args().specific_sender();
-
-    let mut next_task = NextTask::Same; // update if we wake tasks
-
     if specific_sender == Some(TaskId::KERNEL) {
         // We've already checked for notifications, which is the only kind of
         // message the kernel emits. No need to check further; we'll fall
@@ -206,9 +468,9 @@ fn recv(tasks: &mut [Task], caller: usize) -> Result<NextTask, UserError> {
         // First possibility: that task you're asking about is DEAD.
         let sender_idx = task::check_task_id_against_table(tasks, sender_id)?;
         // Second possibility: task has a message for us.
-        if tasks[sender_idx].state().is_sending_to(caller_id) {
+        if has_message_for(&tasks[sender_idx], caller_id) {
             // Oh hello sender!
-            match deliver(tasks, sender_idx, caller) {
+            match deliver_any(tasks, sender_idx, caller) {
                 Ok(_) => {
                     // Delivery succeeded! Sender is now blocked in reply. Go ahead
                     // and let the caller resume.
@@ -242,11 +504,11 @@ fn recv(tasks: &mut [Task], caller: usize) -> Result<NextTask, UserError> {
         let mut last = caller; // keep track of scan position.
 
         // Is anyone blocked waiting to send to us?
-        while let Some(sender) = task::priority_scan(last, tasks, |t| {
-            t.state().is_sending_to(caller_id)
-        }) {
+        while let Some(sender) =
+            task::priority_scan(last, tasks, |t| has_message_for(t, caller_id))
+        {
             // Oh hello sender!
-            match deliver(tasks, sender, caller) {
+            match deliver_any(tasks, sender, caller) {
                 Ok(_) => {
                     // Delivery succeeded! Sender is now blocked in reply. Go ahead
                     // and let the caller resume.
@@ -295,9 +557,21 @@ fn reply(tasks: &mut [Task], caller: usize) -> Result<NextTask, FaultInfo> {
         Ok(x) => x,
     };
 
+    // This may be the late reply to a send that timed out, which we drop;
+    // that frees the callee to send to us again.
+    if tasks[callee].has_abandoned_send_to(caller) {
+        tasks[callee].clear_abandoned_send(caller);
+        return Ok(NextTask::Same);
+    }
+
     if tasks[callee].state()
         != &TaskState::Healthy(SchedState::InReply(caller_id))
     {
+        // This may be the reply to an asynchronous send instead.
+        if let Some(slot) = tasks[callee].async_send_awaiting(caller_id) {
+            return reply_async(tasks, caller, callee, slot);
+        }
+
         // Huh. The target task is off doing something else. This can happen if
         // application-specific supervisory logic unblocks it before we've had a
         // chance to reply (e.g. to implement timeouts).
@@ -372,6 +646,49 @@ fn reply(tasks: &mut [Task], caller: usize) -> Result<NextTask, FaultInfo> {
     return Ok(NextTask::Same);
 }
 
+/// Delivers a REPLY from `caller` to the asynchronous send in `slot` of task
+/// `callee`.
+fn reply_async(
+    tasks: &mut [Task],
+    caller: usize,
+    callee: usize,
+    slot: usize,
+) -> Result<NextTask, FaultInfo> {
+    let send = tasks[callee].async_sends()[slot];
+    if let AsyncState::Abandoned(_) = send.state {
+        // Nobody wants this reply anymore; its arrival just frees the slot.
+        tasks[callee].async_sends_mut()[slot] = AsyncSend::free();
+        return Ok(NextTask::Same);
+    }
+
+    let reply_args = tasks[caller].save().as_reply_args();
+    let src_slice = reply_args.message().map_err(FaultInfo::SyscallUsage)?;
+    let code = reply_args.response_code();
+    drop(reply_args);
+
+    let amount_copied =
+        match safe_copy(tasks, caller, src_slice, callee, send.response) {
+            Ok(n) => n,
+            Err(interact) => {
+                // As with REPLY, apply the fault to whoever deserves it; a
+                // sender whose buffer is bad doesn't get its reply.
+                return interact.apply_to_dst(tasks, callee);
+            }
+        };
+
+    let woke = tasks[callee].complete_async_send(slot, code, amount_copied);
+
+    // Async senders are expected to be important tasks talking to less
+    // important ones, so this reply may well call for a switch.
+    let caller_p = tasks[caller].priority();
+    let callee_p = tasks[callee].priority();
+    if woke && callee_p.is_more_important_than(caller_p) {
+        Ok(NextTask::Specific(callee))
+    } else {
+        Ok(NextTask::Same)
+    }
+}
+
 /// Implementation of the `SET_TIMER` syscall.
 fn set_timer(task: &mut Task, now: Timestamp) -> NextTask {
     let args = task.save().as_set_timer_args();
@@ -672,6 +989,67 @@ fn deliver(
     Ok(())
 }
 
+/// Checks whether `sender` has a message, synchronous or asynchronous, waiting
+/// for `callee`.
+fn has_message_for(sender: &Task, callee: TaskId) -> bool {
+    sender.state().is_sending_to(callee)
+        || sender.async_send_queued_for(callee).is_some()
+}
+
+/// Delivers whichever kind of message `sender` has waiting for `callee`, as
+/// established by `has_message_for`.
+fn deliver_any(
+    tasks: &mut [Task],
+    sender: usize,
+    callee: usize,
+) -> Result<(), InteractFault> {
+    let callee_id = current_id(tasks, callee);
+    if tasks[sender].state().is_sending_to(callee_id) {
+        deliver(tasks, sender, callee)
+    } else {
+        // A task blocked in SEND can't also be making asynchronous sends, so
+        // this one must be queued.
+        let slot = tasks[sender].async_send_queued_for(callee_id).unwrap();
+        deliver_async(tasks, sender, slot, callee)
+    }
+}
+
+/// Transfers the asynchronous message in `slot` of task `caller` into
+/// `callee`, which must be receiving. This is the asynchronous counterpart of
+/// `deliver`, and fails in the same ways.
+///
+/// Asynchronous messages can't carry leases, so the callee is always told
+/// there are none.
+fn deliver_async(
+    tasks: &mut [Task],
+    caller: usize,
+    slot: usize,
+    callee: usize,
+) -> Result<(), InteractFault> {
+    let send = tasks[caller].async_sends()[slot];
+    let caller_id = current_id(tasks, caller);
+
+    let recv_args = tasks[callee].save().as_recv_args();
+    let dest_slice = recv_args.buffer().map_err(InteractFault::in_dst)?;
+    drop(recv_args);
+
+    let amount_copied =
+        safe_copy(tasks, caller, send.message, callee, dest_slice)?;
+    tasks[callee].save_mut().set_recv_result(
+        caller_id,
+        u32::from(send.operation),
+        amount_copied,
+        send.response.len(),
+        0,
+    );
+
+    let callee_id = current_id(tasks, callee);
+    tasks[caller].async_sends_mut()[slot].state =
+        AsyncState::Delivered(callee_id);
+    tasks[callee].set_healthy_state(SchedState::Runnable);
+    Ok(())
+}
+
 fn irq_control(
     tasks: &mut [Task],
     caller: usize,
@@ -681,9 +1059,12 @@ fn irq_control(
     let control = args.control();
     drop(args);
 
-    let operation = match control {
-        0 => crate::arch::disable_irq,
-        1 => crate::arch::enable_irq,
+    let operation: fn(usize, u32) = match control {
+        0 => |_, irq| crate::arch::disable_irq(irq),
+        1 => |entry, irq| {
+            crate::irq_stats::enabled(entry);
+            crate::arch::enable_irq(irq);
+        },
         _ => {
             return Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
                 UsageError::NoIrq,
@@ -694,9 +1075,9 @@ fn irq_control(
     if crate::arch::with_irq_table(|irqs| {
         let mut found = false;
 
-        for irq in irqs {
+        for (i, irq) in irqs.iter().enumerate() {
             if irq.task == caller as u32 && irq.notification == bitmask {
-                operation(irq.irq);
+                operation(i, irq.irq);
                 found = true;
             }
         }
@@ -715,13 +1096,16 @@ fn explicit_panic(
     tasks: &mut [Task],
     caller: usize,
 ) -> Result<NextTask, UserError> {
-    // Make an attempt at printing the message.
+    // Make an attempt at printing the message, and keep as much of it as we
+    // can for post-mortem tooling.
     let args = tasks[caller].save().as_panic_args();
     let message = args.message();
     drop(args);
 
+    let mut detail = abi::FaultDetail::EMPTY;
     if let Ok(uslice) = message {
         if let Ok(slice) = tasks[caller].try_read(&uslice) {
+            detail = abi::FaultDetail::with_message(slice);
             // Plausible.
             if slice.iter().all(|&c| c < 0x80) {
                 klog!("task @{} panicked: {}", caller, unsafe {
@@ -733,7 +1117,12 @@ fn explicit_panic(
         }
     }
 
-    Ok(task::force_fault(tasks, caller, FaultInfo::Panic))
+    Ok(task::force_fault_with_detail(
+        tasks,
+        caller,
+        FaultInfo::Panic,
+        detail,
+    ))
 }
 
 fn refresh_task_id(
@@ -761,6 +1150,7 @@ fn post(tasks: &mut [Task], caller: usize) -> Result<NextTask, UserError> {
     let bits = args.notification_bits();
     drop(args);
 
+    check_ipc_allowed(tasks, caller, peer_id)?;
     let peer_idx = task::check_task_id_against_table(tasks, peer_id)?;
 
     let woke = tasks[peer_idx].post(bits);
@@ -782,3 +1172,20 @@ fn post(tasks: &mut [Task], caller: usize) -> Result<NextTask, UserError> {
         Ok(NextTask::Same)
     }
 }
+
+/// Implementation of the LOCK syscall, which takes one of the application's
+/// locks; see the `lock` module.
+fn lock(tasks: &mut [Task], caller: usize) -> Result<NextTask, FaultInfo> {
+    let args = tasks[caller].save().as_lock_args();
+    let index = args.index();
+    let try_only = args.try_only();
+    drop(args);
+
+    crate::lock::lock(tasks, caller, index, try_only)
+}
+
+/// Implementation of the UNLOCK syscall.
+fn unlock(tasks: &mut [Task], caller: usize) -> Result<NextTask, FaultInfo> {
+    let index = tasks[caller].save().as_lock_args().index();
+    crate::lock::unlock(tasks, caller, index)
+}
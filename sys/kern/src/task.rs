@@ -7,8 +7,8 @@
 use core::sync::atomic::{AtomicU32, Ordering};
 
 use abi::{
-    FaultInfo, FaultSource, Generation, Priority, SchedState, TaskId,
-    TaskState, UsageError,
+    AsyncOutcome, FaultDetail, FaultInfo, FaultSource, Generation, Priority,
+    SchedState, TaskId, TaskState, UsageError,
 };
 use zerocopy::FromBytes;
 
@@ -20,7 +20,8 @@ use crate::time::Timestamp;
 use crate::umem::{ULease, USlice};
 
 /// This global holds the fault notification that will be sent to the supervisor
-/// of another task faults. It gets configured at application startup by a call
+/// of another task faults (or first becomes ready; see `note_open_recv`). It
+/// gets configured at application startup by a call
 /// to `set_fault_notification` and then remains untouched.
 #[no_mangle]
 static FAULT_NOTIFICATION: AtomicU32 = AtomicU32::new(0);
@@ -44,8 +45,13 @@ pub struct Task {
     /// Saved machine state of the user program.
     save: crate::arch::SavedState,
     // NOTE: it is critical that the above field appear first!
-    /// Current priority of the task.
+    /// Current priority of the task. This is the priority from its
+    /// descriptor, unless it's inheriting a more important one; see
+    /// `update_priorities`.
     priority: Priority,
+    /// Scratch space for `update_priorities`.
+    #[cfg(feature = "priority-inheritance")]
+    next_priority: Priority,
     /// State used to make status and scheduling decisions.
     state: TaskState,
     /// State for tracking the task's timer.
@@ -60,6 +66,24 @@ pub struct Task {
     /// Notification status.
     notifications: u32,
 
+    /// What we know about the task's most recent fault, beyond what's in its
+    /// state. Unlike the state, this survives the task being restarted.
+    fault_detail: FaultDetail,
+
+    /// Asynchronous sends made by this task that have not yet been collected.
+    async_sends: [AsyncSend; abi::ASYNC_SENDS_PER_TASK],
+
+    /// Tasks, by index, holding a message that this task gave up waiting on
+    /// with `SEND_TIMEOUT`, that haven't replied yet. Until one does, its
+    /// REPLY can't be told apart from a reply to any new message, so we don't
+    /// let this task send it one. (This has room for every task, since the
+    /// kernel only accepts task tables that `TaskDesc::ipc_allow` can cover.)
+    abandoned_sends: [u32; abi::IPC_ALLOW_WORDS],
+
+    /// Whether the task has made an open RECV since it was last started; see
+    /// `note_open_recv`.
+    ready: bool,
+
     /// Pointer to the ROM descriptor used to create this task, so it can be
     /// restarted.
     descriptor: &'static TaskDesc,
@@ -74,6 +98,8 @@ impl Task {
     ) -> Self {
         Task {
             priority: abi::Priority(descriptor.priority as u8),
+            #[cfg(feature = "priority-inheritance")]
+            next_priority: abi::Priority(descriptor.priority as u8),
             state: if descriptor.flags.contains(TaskFlags::START_AT_BOOT) {
                 TaskState::Healthy(SchedState::Runnable)
             } else {
@@ -85,6 +111,10 @@ impl Task {
 
             generation: 0,
             notifications: 0,
+            fault_detail: FaultDetail::EMPTY,
+            async_sends: [AsyncSend::free(); abi::ASYNC_SENDS_PER_TASK],
+            abandoned_sends: [0; abi::IPC_ALLOW_WORDS],
+            ready: false,
             save: crate::arch::SavedState::default(),
             timer: crate::task::TimerState::default(),
         }
@@ -267,6 +297,13 @@ impl Task {
     ) {
         self.timer.deadline = deadline;
         self.timer.to_post = notifications;
+        self.timer.cancels_send = false;
+    }
+
+    /// Records whether this task's timer should cut short the SEND it's about
+    /// to make, as for `SEND_TIMEOUT`.
+    pub fn set_timer_cancels_send(&mut self, cancels_send: bool) {
+        self.timer.cancels_send = cancels_send;
     }
 
     /// Reads out the state of this task's timer, as previously set by
@@ -289,6 +326,9 @@ impl Task {
         self.generation = self.generation.wrapping_add(1);
         self.timer = TimerState::default();
         self.notifications = 0;
+        self.async_sends = [AsyncSend::free(); abi::ASYNC_SENDS_PER_TASK];
+        self.abandoned_sends = [0; abi::IPC_ALLOW_WORDS];
+        self.ready = false;
         self.state = TaskState::default();
 
         crate::arch::reinitialize(self);
@@ -311,11 +351,30 @@ impl Task {
         Generation::from(self.generation as u8 & MASK)
     }
 
-    /// Returns this task's priority.
+    /// Returns whether this task has made an open RECV since it was last
+    /// started, which the supervisor takes to mean that it's ready to serve.
+    pub fn is_ready(&self) -> bool {
+        self.ready
+    }
+
+    /// Returns this task's priority, which may be inherited from a more
+    /// important task; see `update_priorities`.
     pub fn priority(&self) -> Priority {
         self.priority
     }
 
+    /// Returns the priority this task was given in the application, which is
+    /// the one it runs at when it's not inheriting another.
+    pub fn base_priority(&self) -> Priority {
+        abi::Priority(self.descriptor.priority as u8)
+    }
+
+    /// Returns what we know about this task's most recent fault, beyond its
+    /// `FaultInfo`.
+    pub fn fault_detail(&self) -> &FaultDetail {
+        &self.fault_detail
+    }
+
     /// Returns a reference to this task's current state, for inspection.
     pub fn state(&self) -> &TaskState {
         &self.state
@@ -340,6 +399,75 @@ impl Task {
         }
     }
 
+    /// Returns this task's table of asynchronous sends.
+    pub fn async_sends(&self) -> &[AsyncSend] {
+        &self.async_sends
+    }
+
+    /// Returns this task's table of asynchronous sends, mutably.
+    pub fn async_sends_mut(&mut self) -> &mut [AsyncSend] {
+        &mut self.async_sends
+    }
+
+    /// Finds an asynchronous send from this task that is waiting to be
+    /// delivered to `target`, returning its slot number.
+    ///
+    /// Faulted tasks don't get their messages delivered, so this always
+    /// returns `None` for them.
+    pub fn async_send_queued_for(&self, target: TaskId) -> Option<usize> {
+        if let TaskState::Faulted { .. } = self.state {
+            return None;
+        }
+        self.async_sends
+            .iter()
+            .position(|a| a.state == AsyncState::Queued(target))
+    }
+
+    /// Finds an asynchronous send from this task that `peer` has received but
+    /// not yet replied to, returning its slot number.
+    pub fn async_send_awaiting(&self, peer: TaskId) -> Option<usize> {
+        self.async_sends.iter().position(|a| match a.state {
+            AsyncState::Delivered(p) | AsyncState::Abandoned(p) => p == peer,
+            _ => false,
+        })
+    }
+
+    /// Checks whether the task at index `peer` still holds a message that this
+    /// task timed out waiting on; see `process_timers`.
+    pub fn has_abandoned_send_to(&self, peer: usize) -> bool {
+        self.abandoned_sends[peer / 32] & 1 << (peer % 32) != 0
+    }
+
+    /// Forgets that this task timed out waiting on the task at index `peer`,
+    /// now that `peer` has replied to it or been restarted.
+    pub fn clear_abandoned_send(&mut self, peer: usize) {
+        self.abandoned_sends[peer / 32] &= !(1 << (peer % 32));
+    }
+
+    /// Checks whether this task has an asynchronous send to `peer` that hasn't
+    /// been answered yet.
+    pub fn has_async_send_to(&self, peer: TaskId) -> bool {
+        self.async_sends
+            .iter()
+            .any(|a| a.state.peer() == Some(peer))
+    }
+
+    /// Records the completion of the asynchronous send in `slot` with response
+    /// `code` and a reply of `len` bytes, and posts its notification. Returns
+    /// `true` if this woke the task, as with `post`.
+    #[must_use]
+    pub fn complete_async_send(
+        &mut self,
+        slot: usize,
+        code: u32,
+        len: usize,
+    ) -> bool {
+        let send = &mut self.async_sends[slot];
+        send.state = AsyncState::Complete { code, len };
+        let n = send.notification;
+        self.post(n)
+    }
+
     /// Returns a reference to the saved machine state for the task.
     pub fn save(&self) -> &crate::arch::SavedState {
         &self.save
@@ -392,6 +520,8 @@ pub trait ArchState: Default {
     fn ret4(&mut self, _: u32);
     /// Writes syscall return argument 5.
     fn ret5(&mut self, _: u32);
+    /// Writes syscall return argument 6.
+    fn ret6(&mut self, _: u32);
 
     /// Returns a proxied reference that assigns names and types to the syscall
     /// arguments for SEND.
@@ -447,6 +577,24 @@ pub trait ArchState: Default {
         AsPostArgs(self)
     }
 
+    /// Returns a proxied reference that assigns names and types to the syscall
+    /// arguments for SEND_ASYNC.
+    fn as_send_async_args(&self) -> AsSendAsyncArgs<&Self> {
+        AsSendAsyncArgs(self)
+    }
+
+    /// Returns a proxied reference that assigns names and types to the syscall
+    /// arguments for ASYNC_STATUS.
+    fn as_async_status_args(&self) -> AsAsyncStatusArgs<&Self> {
+        AsAsyncStatusArgs(self)
+    }
+
+    /// Returns a proxied reference that assigns names and types to the syscall
+    /// arguments for LOCK and UNLOCK.
+    fn as_lock_args(&self) -> AsLockArgs<&Self> {
+        AsLockArgs(self)
+    }
+
     /// Sets a recoverable error code using the generic ABI.
     fn set_error_response(&mut self, resp: u32) {
         self.ret0(resp);
@@ -490,14 +638,19 @@ pub trait ArchState: Default {
     }
 
     /// Sets the results of READ_TIMER.
+    ///
+    /// Times are returned in whole ticks, for compatibility, with the
+    /// microseconds past each tick packed into the last register: the current
+    /// time's in the low half, and the deadline's in the high half.
     fn set_time_result(
         &mut self,
         now: Timestamp,
         dl: Option<Timestamp>,
         not: NotificationSet,
     ) {
-        let now_u64 = u64::from(now);
-        let dl_u64 = dl.map(u64::from).unwrap_or(0);
+        let now_u64 = now.ticks();
+        let dl_u64 = dl.map(Timestamp::ticks).unwrap_or(0);
+        let dl_micros = dl.map(Timestamp::sub_tick_micros).unwrap_or(0);
 
         self.ret0(now_u64 as u32);
         self.ret1((now_u64 >> 32) as u32);
@@ -505,12 +658,25 @@ pub trait ArchState: Default {
         self.ret3(dl_u64 as u32);
         self.ret4((dl_u64 >> 32) as u32);
         self.ret5(not.0);
+        self.ret6(now.sub_tick_micros() | dl_micros << 16);
     }
 
     /// Sets the results of REFRESH_TASK_ID
     fn set_refresh_task_id_result(&mut self, id: TaskId) {
         self.ret0(id.0 as u32);
     }
+
+    /// Sets the results returned from an ASYNC_STATUS call.
+    fn set_async_status_result(
+        &mut self,
+        outcome: AsyncOutcome,
+        code: u32,
+        len: usize,
+    ) {
+        self.ret0(outcome as u32);
+        self.ret1(code);
+        self.ret2(len as u32);
+    }
 }
 
 /// Reference proxy for send argument registers.
@@ -552,6 +718,74 @@ impl<'a, T: ArchState> AsSendArgs<&'a T> {
     }
 }
 
+/// Reference proxy for SEND_ASYNC argument registers. These are laid out like
+/// SEND's, except that there is no lease table.
+pub struct AsSendAsyncArgs<T>(T);
+
+impl<'a, T: ArchState> AsSendAsyncArgs<&'a T> {
+    /// Extracts the task ID the caller wishes to send to.
+    pub fn callee(&self) -> TaskId {
+        TaskId((self.0.arg0() >> 16) as u16)
+    }
+
+    /// Extracts the operation code the caller is using.
+    pub fn operation(&self) -> u16 {
+        self.0.arg0() as u16
+    }
+
+    /// Extracts the bounds of the caller's message as a `USlice`.
+    ///
+    /// If the caller passed a slice that overlaps the end of the address space,
+    /// returns `Err`.
+    pub fn message(&self) -> Result<USlice<u8>, UsageError> {
+        USlice::from_raw(self.0.arg1() as usize, self.0.arg2() as usize)
+    }
+
+    /// Extracts the bounds of the caller's response buffer as a `USlice`.
+    ///
+    /// If the caller passed a slice that overlaps the end of the address space,
+    /// returns `Err`.
+    pub fn response_buffer(&self) -> Result<USlice<u8>, UsageError> {
+        USlice::from_raw(self.0.arg3() as usize, self.0.arg4() as usize)
+    }
+
+    /// Extracts the notification set to post when the reply arrives.
+    pub fn notification(&self) -> NotificationSet {
+        NotificationSet(self.0.arg5())
+    }
+}
+
+/// Reference proxy for ASYNC_STATUS argument registers.
+pub struct AsAsyncStatusArgs<T>(T);
+
+impl<'a, T: ArchState> AsAsyncStatusArgs<&'a T> {
+    /// Extracts the asynchronous send slot being asked about.
+    pub fn slot(&self) -> usize {
+        self.0.arg0() as usize
+    }
+
+    /// Checks whether the caller wants to cancel the send.
+    pub fn cancel(&self) -> bool {
+        self.0.arg1() != 0
+    }
+}
+
+/// Reference proxy for LOCK and UNLOCK argument registers.
+pub struct AsLockArgs<T>(T);
+
+impl<'a, T: ArchState> AsLockArgs<&'a T> {
+    /// Extracts the index of the lock being taken or released.
+    pub fn index(&self) -> u32 {
+        self.0.arg0()
+    }
+
+    /// Checks whether the caller would rather not wait for the lock. (Only
+    /// meaningful for LOCK.)
+    pub fn try_only(&self) -> bool {
+        self.0.arg1() & abi::LOCK_TRY != 0
+    }
+}
+
 /// Reference proxy for receive argument registers.
 pub struct AsRecvArgs<T>(T);
 
@@ -608,12 +842,17 @@ impl<'a, T: ArchState> AsReplyArgs<&'a T> {
 pub struct AsSetTimerArgs<T>(T);
 
 impl<'a, T: ArchState> AsSetTimerArgs<&'a T> {
-    /// Extracts the deadline.
+    /// Extracts the deadline, which is given in ticks unless the caller set
+    /// `TIMER_MICROS`.
     pub fn deadline(&self) -> Option<Timestamp> {
-        if self.0.arg0() != 0 {
-            Some(Timestamp::from(
-                u64::from(self.0.arg2()) << 32 | u64::from(self.0.arg1()),
-            ))
+        let flags = self.0.arg0();
+        if flags & abi::TIMER_ENABLE != 0 {
+            let raw = u64::from(self.0.arg2()) << 32 | u64::from(self.0.arg1());
+            Some(if flags & abi::TIMER_MICROS != 0 {
+                Timestamp::from_micros(raw)
+            } else {
+                Timestamp::from_ticks(raw)
+            })
         } else {
             None
         }
@@ -707,6 +946,71 @@ pub struct TimerState {
     /// Set of notification bits to post to the owning task when this timer
     /// fires.
     to_post: NotificationSet,
+    /// If `true`, the task's most recent SEND was a `SEND_TIMEOUT`, and when
+    /// this timer fires it should give up on that send if it's still blocked.
+    cancels_send: bool,
+}
+
+/// Record of an asynchronous send made by a task with `SEND_ASYNC`.
+///
+/// Unlike a SEND, which keeps its arguments in the sender's saved registers
+/// while it's blocked, an asynchronous send has to remember them here, because
+/// the sender keeps running.
+#[derive(Copy, Clone, Debug)]
+pub struct AsyncSend {
+    /// Progress of the send.
+    pub state: AsyncState,
+    /// Operation code for the message.
+    pub operation: u16,
+    /// Message to deliver, in the sender's memory.
+    pub message: USlice<u8>,
+    /// Buffer for the reply, in the sender's memory.
+    pub response: USlice<u8>,
+    /// Notification bits to post to the sender once the reply arrives.
+    pub notification: NotificationSet,
+}
+
+impl AsyncSend {
+    /// Returns an unused slot.
+    pub fn free() -> Self {
+        Self {
+            state: AsyncState::Free,
+            operation: 0,
+            message: USlice::empty(),
+            response: USlice::empty(),
+            notification: NotificationSet::default(),
+        }
+    }
+}
+
+/// Progress of an asynchronous send.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum AsyncState {
+    /// The slot is unused.
+    Free,
+    /// The message is waiting for the given task to receive it.
+    Queued(TaskId),
+    /// The given task has received the message, and owes a reply.
+    Delivered(TaskId),
+    /// The sender cancelled the send after the given task received it. The
+    /// slot stays occupied until the task replies, so that the reply can be
+    /// discarded rather than confused with a later message.
+    Abandoned(TaskId),
+    /// The reply has arrived (or the peer died), and is waiting for the sender
+    /// to collect it.
+    Complete { code: u32, len: usize },
+}
+
+impl AsyncState {
+    /// Returns the task this send is waiting on, if any.
+    pub fn peer(&self) -> Option<TaskId> {
+        match self {
+            Self::Queued(p) | Self::Delivered(p) | Self::Abandoned(p) => {
+                Some(*p)
+            }
+            Self::Free | Self::Complete { .. } => None,
+        }
+    }
 }
 
 /// Collection of bits that may be posted to a task's notification word.
@@ -754,11 +1058,39 @@ impl NextTask {
 /// any that have expired by `current_time` (and disabling them atomically).
 pub fn process_timers(tasks: &mut [Task], current_time: Timestamp) -> NextTask {
     let mut sched_hint = NextTask::Same;
+    let mut timed_out_any = false;
     for (index, task) in tasks.iter_mut().enumerate() {
         if let Some(deadline) = task.timer.deadline {
             if deadline <= current_time {
                 task.timer.deadline = None;
-                let task_hint = if task.post(task.timer.to_post) {
+                crate::trace::timer(index, task.timer.to_post.0);
+                let timed_out = task.timer.cancels_send
+                    && matches!(
+                        task.state,
+                        TaskState::Healthy(
+                            SchedState::InSend(_) | SchedState::InReply(_)
+                        )
+                    );
+                task.timer.cancels_send = false;
+                if timed_out {
+                    // Abandon the send. If the callee has already received
+                    // the message, its eventual REPLY will find us no longer
+                    // waiting and be discarded, and any attempt to use our
+                    // leases in the meantime will fail. We remember who it
+                    // was, so that we don't send them another message until
+                    // then: the REPLY could be mistaken for an answer to it.
+                    if let TaskState::Healthy(SchedState::InReply(callee)) =
+                        task.state
+                    {
+                        let peer = callee.index();
+                        task.abandoned_sends[peer / 32] |= 1 << (peer % 32);
+                    }
+                    task.save_mut().set_error_response(abi::SEND_TIMED_OUT);
+                    task.set_healthy_state(SchedState::Runnable);
+                    timed_out_any = true;
+                }
+                let woke = task.post(task.timer.to_post);
+                let task_hint = if timed_out || woke {
                     NextTask::Specific(index)
                 } else {
                     NextTask::Same
@@ -767,9 +1099,34 @@ pub fn process_timers(tasks: &mut [Task], current_time: Timestamp) -> NextTask {
             }
         }
     }
+    if timed_out_any && update_priorities(tasks) {
+        sched_hint = NextTask::Other;
+    }
     sched_hint
 }
 
+/// Returns the earliest deadline among all enabled timers in the task table, or
+/// `None` if no timers are enabled.
+pub fn next_deadline(tasks: &[Task]) -> Option<Timestamp> {
+    tasks.iter().filter_map(|task| task.timer.deadline).min()
+}
+
+/// Tells the architecture when the next timer is due.
+///
+/// With the `tickless` feature, the architecture stretches the interval between
+/// timer interrupts to reach the next deadline, rather than interrupting at a
+/// fixed rate, so this needs to be called after anything that could bring a
+/// deadline forward -- setting a timer, or processing the ones that expired.
+#[cfg(feature = "tickless")]
+pub fn reschedule_tick(tasks: &[Task]) {
+    crate::arch::set_tick_deadline(next_deadline(tasks));
+}
+
+/// Without the `tickless` feature, the timer interrupt arrives every tick
+/// regardless.
+#[cfg(not(feature = "tickless"))]
+pub fn reschedule_tick(_tasks: &[Task]) {}
+
 /// Checks a user-provided `TaskId` for validity against `table`.
 ///
 /// On success, returns an index that can be used to dereference `table` without
@@ -804,6 +1161,73 @@ pub fn select(previous: usize, tasks: &[Task]) -> usize {
         .expect("no tasks runnable")
 }
 
+/// Recomputes the priority of every task, returning `true` if any changed.
+///
+/// With the `priority-inheritance` feature, a task that owes a reply to a more
+/// important task -- that is, one blocked in `InReply` waiting on it -- runs at
+/// that task's priority until it replies. Otherwise, tasks of intermediate
+/// priority could starve the caller by starving the server it's waiting on.
+/// Likewise, the owner of a lock runs at the priority of the most important
+/// task waiting for it. This is transitive: if the server is itself waiting on
+/// a reply from a third task, the third task inherits the caller's priority
+/// too.
+///
+/// This needs to be called after anything that could change who's waiting on
+/// whom, before the next scheduling decision: the SEND, RECV, REPLY, LOCK and
+/// UNLOCK syscalls, a send timing out, and a task faulting or being restarted.
+/// Nothing else can, so priorities are left alone the rest of the time.
+#[cfg(feature = "priority-inheritance")]
+pub fn update_priorities(tasks: &mut [Task]) -> bool {
+    for task in tasks.iter_mut() {
+        task.next_priority = task.base_priority();
+    }
+    // Each pass pushes priorities at least one more step along every chain of
+    // waiting callers, and no chain can be longer than the task table.
+    for _ in 0..tasks.len() {
+        let mut changed = false;
+        for i in 0..tasks.len() {
+            // Tasks waiting for a lock are similarly at the mercy of its
+            // owner.
+            let holdup = match tasks[i].state {
+                TaskState::Healthy(SchedState::InReply(server)) => {
+                    server.index()
+                }
+                TaskState::Healthy(SchedState::InLock(lock)) => {
+                    match crate::lock::owner(lock as usize) {
+                        Some(owner) => owner,
+                        None => continue,
+                    }
+                }
+                _ => continue,
+            };
+            let p = tasks[i].next_priority;
+            let holdup = &mut tasks[holdup];
+            if p.is_more_important_than(holdup.next_priority) {
+                holdup.next_priority = p;
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    let mut changed = false;
+    for task in tasks.iter_mut() {
+        if task.priority != task.next_priority {
+            task.priority = task.next_priority;
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// Without the `priority-inheritance` feature, priorities never change.
+#[cfg(not(feature = "priority-inheritance"))]
+pub fn update_priorities(_tasks: &mut [Task]) -> bool {
+    false
+}
+
 /// Scans `tasks` for the next task, after `previous`, that satisfies `pred`. If
 /// more than one task satisfies `pred`, returns the most important one. If
 /// multiple tasks with the same priority satisfy `pred`, prefers the first one
@@ -864,7 +1288,25 @@ pub fn force_fault(
     index: usize,
     fault: FaultInfo,
 ) -> NextTask {
+    force_fault_with_detail(tasks, index, fault, FaultDetail::EMPTY)
+}
+
+/// Like `force_fault`, but also records `detail` about the fault (such as a
+/// panic message, or where the task was when it faulted) for the supervisor
+/// and debuggers to find.
+pub fn force_fault_with_detail(
+    tasks: &mut [Task],
+    index: usize,
+    fault: FaultInfo,
+    detail: FaultDetail,
+) -> NextTask {
+    crate::trace::fault(index);
+    // A faulted task can't use whatever its locks protect, so let someone
+    // else have them. We're going to reschedule anyway, so we don't need to
+    // hear about who that woke.
+    let _ = crate::lock::release_all(tasks, index);
     let task = &mut tasks[index];
+    task.fault_detail = detail;
     task.state = match task.state {
         TaskState::Healthy(sched) => TaskState::Faulted {
             original_state: sched,
@@ -879,6 +1321,9 @@ pub fn force_fault(
             }
         }
     };
+    // The task no longer waits on anyone, and anyone waiting on it is stuck
+    // until it's restarted. Either way, they're none of its business.
+    update_priorities(tasks);
     let supervisor_awoken = tasks[0]
         .post(NotificationSet(FAULT_NOTIFICATION.load(Ordering::Relaxed)));
     if supervisor_awoken {
@@ -888,6 +1333,24 @@ pub fn force_fault(
     }
 }
 
+/// Notes that task `index` has made an open RECV. The first time it does after
+/// being started, this posts the supervisor's notification, so that a
+/// supervisor holding back tasks until this one is ready to serve can look
+/// again, rather than having to keep checking.
+pub fn note_open_recv(tasks: &mut [Task], index: usize) -> NextTask {
+    if index == 0 || tasks[index].ready {
+        return NextTask::Same;
+    }
+    tasks[index].ready = true;
+    let supervisor_awoken = tasks[0]
+        .post(NotificationSet(FAULT_NOTIFICATION.load(Ordering::Relaxed)));
+    if supervisor_awoken {
+        NextTask::Specific(0)
+    } else {
+        NextTask::Same
+    }
+}
+
 /// Produces a current `TaskId` (i.e. one with the correct generation) for
 /// `tasks[index]`.
 pub fn current_id(tasks: &[Task], index: usize) -> TaskId {
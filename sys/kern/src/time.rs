@@ -4,21 +4,45 @@
 
 //! Implementation of kernel time.
 
+pub use abi::MICROS_PER_TICK;
+
 /// In-kernel timestamp representation.
 ///
-/// This is currently measured in an arbitrary "tick" unit.
+/// This is measured in microseconds since boot, so that timers can have
+/// deadlines between ticks, although how finely the kernel can actually tell
+/// the time depends on the architecture (and, on ARM, the `tickless` feature).
+///
+/// There are deliberately no conversions to or from bare integers, to avoid
+/// mixing up ticks and microseconds; use the named constructors and
+/// accessors instead.
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
 #[repr(transparent)]
 pub struct Timestamp(u64);
 
-impl From<u64> for Timestamp {
-    fn from(v: u64) -> Self {
-        Timestamp(v)
+impl Timestamp {
+    /// Makes a timestamp from a count of ticks since boot, saturating in the
+    /// far future.
+    pub fn from_ticks(ticks: u64) -> Self {
+        Timestamp(ticks.saturating_mul(MICROS_PER_TICK))
+    }
+
+    /// Makes a timestamp from a count of microseconds since boot.
+    pub fn from_micros(micros: u64) -> Self {
+        Timestamp(micros)
+    }
+
+    /// Returns the number of whole ticks since boot.
+    pub fn ticks(self) -> u64 {
+        self.0 / MICROS_PER_TICK
+    }
+
+    /// Returns the number of microseconds since the last whole tick.
+    pub fn sub_tick_micros(self) -> u32 {
+        (self.0 % MICROS_PER_TICK) as u32
     }
-}
 
-impl From<Timestamp> for u64 {
-    fn from(v: Timestamp) -> Self {
-        v.0
+    /// Returns the number of microseconds since boot.
+    pub fn micros(self) -> u64 {
+        self.0
     }
 }
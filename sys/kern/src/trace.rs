@@ -0,0 +1,188 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Kernel event trace.
+//!
+//! When the kernel is built with the `trace` feature, it records syscalls,
+//! context switches, interrupt dispatch, timer expiry and faults into a ring
+//! of `abi::TraceRecord`s at the well-known symbol `KERNEL_TRACE`, where a
+//! debugger can find it. This is mostly useful for working out who was blocked
+//! on whom after a hang; `cargo xtask trace` decodes a dump of the ring.
+//!
+//! Without the feature, the functions in this module do nothing.
+//!
+//! All of these functions must be called from kernel context, which can't be
+//! preempted by other kernel entry points, so the ring needs no locking.
+
+use core::convert::TryFrom;
+
+use abi::{Sysnum, TaskId, TraceFlags, TraceKind};
+
+use crate::err::UserError;
+use crate::task::{ArchState, NextTask, Task};
+
+/// Number of records kept in the ring. Each costs 24 bytes of kernel RAM.
+///
+/// This must be a power of two, so that the record count in the header can
+/// wrap without disturbing the position in the ring.
+pub const TRACE_ENTRIES: usize = 128;
+
+#[cfg(feature = "trace")]
+#[repr(C)]
+pub struct KernelTrace {
+    header: abi::TraceHeader,
+    records: [abi::TraceRecord; TRACE_ENTRIES],
+}
+
+#[cfg(feature = "trace")]
+#[used]
+#[no_mangle]
+static mut KERNEL_TRACE: KernelTrace = KernelTrace {
+    header: abi::TraceHeader {
+        magic: abi::TRACE_MAGIC,
+        record_size: core::mem::size_of::<abi::TraceRecord>() as u32,
+        capacity: TRACE_ENTRIES as u32,
+        count: 0,
+    },
+    records: [abi::TraceRecord::EMPTY; TRACE_ENTRIES],
+};
+
+#[cfg(feature = "trace")]
+fn record(
+    kind: TraceKind,
+    task: Option<usize>,
+    target: Option<usize>,
+    flags: TraceFlags,
+    arg: u32,
+    value: u32,
+) {
+    // Safety: we're only called from kernel context, which is never
+    // reentered, so nobody else can be looking at the ring.
+    let trace = unsafe { &mut KERNEL_TRACE };
+    let slot = trace.header.count as usize % TRACE_ENTRIES;
+    trace.records[slot] = abi::TraceRecord {
+        timestamp: crate::arch::now().ticks(),
+        kind: kind as u16,
+        task: task_index(task),
+        target: task_index(target),
+        flags: flags.bits(),
+        arg,
+        value,
+    };
+    trace.header.count = trace.header.count.wrapping_add(1);
+}
+
+#[cfg(not(feature = "trace"))]
+#[inline(always)]
+fn record(
+    _kind: TraceKind,
+    _task: Option<usize>,
+    _target: Option<usize>,
+    _flags: TraceFlags,
+    _arg: u32,
+    _value: u32,
+) {
+}
+
+#[cfg(feature = "trace")]
+fn task_index(index: Option<usize>) -> u16 {
+    index.map(|i| i as u16).unwrap_or(abi::TRACE_NO_TASK)
+}
+
+/// Works out which task, if any, syscall `nr` from `task` names as its peer.
+///
+/// This has to be called before the syscall is processed, since processing it
+/// overwrites the argument registers with the results.
+pub fn syscall_peer(nr: u32, task: &Task) -> Option<usize> {
+    if !cfg!(feature = "trace") {
+        return None;
+    }
+
+    let args = task.save();
+    let id = match Sysnum::try_from(nr) {
+        Ok(Sysnum::Send) | Ok(Sysnum::SendTimeout) => {
+            args.as_send_args().callee()
+        }
+        Ok(Sysnum::SendAsync) => args.as_send_async_args().callee(),
+        Ok(Sysnum::Recv) => args.as_recv_args().specific_sender()?,
+        Ok(Sysnum::Reply) => args.as_reply_args().callee(),
+        Ok(Sysnum::BorrowRead)
+        | Ok(Sysnum::BorrowWrite)
+        | Ok(Sysnum::BorrowInfo) => args.as_borrow_args().lender(),
+        Ok(Sysnum::RefreshTaskId) => args.as_refresh_task_id_args().task_id(),
+        Ok(Sysnum::Post) => args.as_post_args().task_id(),
+        _ => return None,
+    };
+    if id == TaskId::KERNEL {
+        None
+    } else {
+        Some(id.index())
+    }
+}
+
+/// Records the outcome `res` of syscall `nr` from task `caller`, naming `peer`
+/// (from `syscall_peer`). `runnable` is whether the caller can still run.
+pub fn syscall(
+    nr: u32,
+    caller: usize,
+    peer: Option<usize>,
+    res: &Result<NextTask, UserError>,
+    runnable: bool,
+) {
+    let (flags, code) = match res {
+        Ok(_) if !runnable => (TraceFlags::BLOCKED, 0),
+        Ok(_) => (TraceFlags::empty(), 0),
+        Err(UserError::Recoverable(code, _)) => (TraceFlags::empty(), *code),
+        Err(UserError::Unrecoverable(_)) => (TraceFlags::FAULTED, 0),
+    };
+    record(TraceKind::Syscall, Some(caller), peer, flags, nr, code);
+}
+
+/// Records a switch from task `from` (if one was running) to task `to`.
+pub fn context_switch(from: Option<usize>, to: usize) {
+    record(
+        TraceKind::ContextSwitch,
+        from,
+        Some(to),
+        TraceFlags::empty(),
+        0,
+        0,
+    );
+}
+
+/// Records interrupt `irq` being dispatched to `task` as notification `bits`.
+pub fn irq(irq: u32, task: usize, bits: u32) {
+    record(
+        TraceKind::Irq,
+        Some(task),
+        None,
+        TraceFlags::empty(),
+        irq,
+        bits,
+    );
+}
+
+/// Records the expiry of `task`'s timer, posting notification `bits`.
+pub fn timer(task: usize, bits: u32) {
+    record(
+        TraceKind::Timer,
+        Some(task),
+        None,
+        TraceFlags::empty(),
+        0,
+        bits,
+    );
+}
+
+/// Records `task` being marked as faulted.
+pub fn fault(task: usize) {
+    record(
+        TraceKind::Fault,
+        Some(task),
+        None,
+        TraceFlags::empty(),
+        0,
+        0,
+    );
+}
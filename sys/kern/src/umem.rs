@@ -221,6 +221,8 @@ impl<T> Clone for USlice<T> {
     }
 }
 
+impl<T> Copy for USlice<T> {}
+
 /// Can't `derive(Debug)` for `USlice` because that puts a `Debug` requirement
 /// on `T`, and that's silly.
 impl<T> core::fmt::Debug for USlice<T> {
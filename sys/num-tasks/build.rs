@@ -35,5 +35,27 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     writeln!(task_file, "pub const NUM_TASKS: usize = {};", task_count)
         .unwrap();
 
+    // Locks are optional, so unlike tasks, there may be none at all.
+    println!("cargo:rerun-if-env-changed=HUBRIS_LOCKS");
+    let lock_names = env::var("HUBRIS_LOCKS").unwrap_or_default();
+    println!("HUBRIS_LOCKS = {}", lock_names);
+    let lock_names: Vec<_> =
+        lock_names.split(",").filter(|n| !n.is_empty()).collect();
+
+    if env::var_os("CARGO_FEATURE_LOCK_ENUM").is_some() {
+        writeln!(task_file, "#[allow(non_camel_case_types)]").unwrap();
+        writeln!(task_file, "pub enum Lock {{").unwrap();
+        for (i, name) in lock_names.iter().enumerate() {
+            writeln!(task_file, "    {} = {},", name, i).unwrap();
+        }
+        writeln!(task_file, "}}").unwrap();
+    }
+    writeln!(
+        task_file,
+        "pub const NUM_LOCKS: usize = {};",
+        lock_names.len()
+    )
+    .unwrap();
+
     Ok(())
 }
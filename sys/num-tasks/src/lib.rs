@@ -8,6 +8,10 @@
 //! `num_tasks::NUM_TASKS` is a `const` `usize` giving the total task count.
 //! This can be used to size tables, which in turn lets tasks effectively "add a
 //! field" to all tasks in the system, outside the kernel.
+//!
+//! Similarly, `num_tasks::NUM_LOCKS` gives the number of locks declared in the
+//! application's `[locks]` table, and with the `lock-enum` feature, the `Lock`
+//! enum gives the index of each by name, for use with `userlib::sys_lock`.
 
 #![no_std]
 
@@ -5,8 +5,12 @@
 use std::env;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Do an architecture check.
-    if env::var("CARGO_CFG_TARGET_OS").unwrap() != "none" {
+    // Do an architecture check. Building for 32-bit x86 Linux is allowed, for
+    // use with the kernel's hosted backend.
+    let target_os = env::var("CARGO_CFG_TARGET_OS").unwrap();
+    let target_arch = env::var("CARGO_CFG_TARGET_ARCH").unwrap();
+    let hosted = target_os == "linux" && target_arch == "x86";
+    if target_os != "none" && !hosted {
         eprintln!("***********************************************");
         eprintln!("Hi!");
         eprintln!("You appear to be building this natively,");
@@ -14,8 +14,9 @@ use zerocopy::{AsBytes, FromBytes, LayoutVerified};
 
 use crate::{
     sys_borrow_info, sys_borrow_read, sys_borrow_write, sys_get_timer,
-    sys_recv, sys_recv_closed, sys_recv_open, sys_reply, sys_send,
-    sys_set_timer, BorrowInfo, ClosedRecvError, FromPrimitive,
+    sys_lock, sys_recv, sys_recv_closed, sys_recv_open, sys_reply, sys_send,
+    sys_send_with_deadline, sys_set_timer, sys_set_timer_micros, sys_try_lock,
+    sys_unlock, BorrowInfo, ClosedRecvError, FromPrimitive, LockAcquired,
 };
 
 const INTERNAL_TIMER_NOTIFICATION: u32 = 1 << 31;
@@ -525,6 +526,56 @@ where
     }
 }
 
+/// Typed version of `sys_send_with_deadline`, which works like `send` but gives
+/// up if `target` hasn't replied by `deadline` (in kernel timer ticks).
+///
+/// If the deadline passes, the kernel's `abi::SEND_TIMED_OUT` response code is
+/// passed to `M::Err`'s impl of `From<u32>` and returned in `Err`, like any
+/// other failure.
+///
+/// # Panics
+///
+/// If the server sends back a successful response that is the wrong size for
+/// `M::Response`, as for `send`.
+pub fn send_with_deadline<M>(
+    target: TaskId,
+    message: &M,
+    deadline: u64,
+) -> Result<M::Response, M::Err>
+where
+    M: Call,
+{
+    use core::mem::MaybeUninit;
+
+    // See `send` for why this is OK.
+    let mut response: MaybeUninit<M::Response> = MaybeUninit::uninit();
+    let rslice = unsafe {
+        core::slice::from_raw_parts_mut(
+            response.as_mut_ptr() as *mut u8,
+            core::mem::size_of_val(&response),
+        )
+    };
+
+    let (code, rlen) = sys_send_with_deadline(
+        target,
+        M::OP,
+        message.as_bytes(),
+        rslice,
+        &[],
+        deadline,
+    );
+
+    if code == 0 {
+        if rlen == core::mem::size_of_val(&response) {
+            Ok(unsafe { response.assume_init() })
+        } else {
+            panic!();
+        }
+    } else {
+        Err(M::Err::from(code))
+    }
+}
+
 /// Typed version of `sys_send` that sends a value to another task and collects
 /// a response, retrying automatically if that task has restarted. This is a
 /// variant on `send` for operations that are idempotent (because the server may
@@ -635,3 +686,85 @@ pub fn sleep_until(time: u64) {
 pub fn sleep_for(ticks: u64) {
     sleep_until(sys_get_timer().now + ticks)
 }
+
+/// Suspends the calling task until the kernel time is `>= time`, in
+/// microseconds since boot.
+///
+/// This is for delays too short to measure in ticks, which would otherwise
+/// have to be busy-waits. How close to `time` the task wakes depends on the
+/// kernel: without the `tickless` feature, it's not until the next tick, up to
+/// a millisecond late. See `sys_set_timer_micros`.
+pub fn sleep_until_micros(time: u64) {
+    sys_set_timer_micros(Some(time), INTERNAL_TIMER_NOTIFICATION);
+    loop {
+        let _ = sys_recv_closed(
+            &mut [],
+            INTERNAL_TIMER_NOTIFICATION,
+            TaskId::KERNEL,
+        );
+        // As in `sleep_until`, check for spurious wakeups.
+        if sys_get_timer().now_micros >= time {
+            break;
+        }
+    }
+}
+
+/// Suspends the calling task until the kernel time has increased by `micros`
+/// microseconds.
+pub fn sleep_for_micros(micros: u64) {
+    sleep_until_micros(sys_get_timer().now_micros + micros)
+}
+
+/// Operation code of the message that `heartbeat` sends to the supervisor,
+/// which is the `heartbeat` operation of its Idol interface (`idl/jefe.idol`).
+pub const HEARTBEAT_OP: u16 = 1;
+
+/// Checks in with the supervisor, to tell it that this task is still making
+/// progress.
+///
+/// If the application marks this task as critical, the supervisor stops
+/// kicking the hardware watchdog -- and so lets the system reset -- when this
+/// task hasn't checked in recently enough. Critical tasks should call this
+/// from their main loop. For other tasks, it's harmless.
+pub fn heartbeat() {
+    // The supervisor replies immediately, and there's nothing useful we could
+    // do if it didn't, so we ignore the result.
+    let _ = sys_send(TaskId::SUPERVISOR, HEARTBEAT_OP, &[], &mut [], &[]);
+}
+
+/// Takes the application lock with index `lock`, waiting for it if necessary,
+/// and returns a guard that releases it when dropped.
+///
+/// See `sys_lock` for details.
+pub fn lock(lock: usize) -> LockGuard {
+    let acquired = sys_lock(lock);
+    LockGuard { lock, acquired }
+}
+
+/// Takes the application lock with index `lock` if it's free, returning a
+/// guard that releases it when dropped, or `None` if somebody else holds it.
+pub fn try_lock(lock: usize) -> Option<LockGuard> {
+    let acquired = sys_try_lock(lock)?;
+    Some(LockGuard { lock, acquired })
+}
+
+/// Holds an application lock taken with `lock` or `try_lock`, and releases it
+/// when dropped.
+pub struct LockGuard {
+    lock: usize,
+    acquired: LockAcquired,
+}
+
+impl LockGuard {
+    /// Checks whether the lock's previous owner died while holding it, in
+    /// which case whatever it protects may need resetting.
+    pub fn owner_died(&self) -> bool {
+        self.acquired == LockAcquired::OwnerDied
+    }
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        sys_unlock(self.lock);
+    }
+}
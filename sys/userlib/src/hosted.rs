@@ -0,0 +1,402 @@
+//! Syscall stubs and startup for tasks running under the kernel's hosted
+//! backend.
+//!
+//! Rather than loading registers and executing `SVC`, the stubs pack their
+//! arguments into an array standing in for `r4`-`r11` and call the kernel's
+//! syscall entry point, whose address the kernel hands to `_start`. (Tasks are
+//! linked separately from the kernel, so they can't refer to it by name.) The
+//! register assignments are the same as in the ARM stubs.
+//!
+//! Tasks are linked without the C library, so this also supplies the handful
+//! of memory routines that the compiler expects to find there.
+
+use super::*;
+
+/// The kernel's syscall entry point, as passed to `_start`.
+type SyscallEntry = unsafe extern "C" fn(regs: *mut [u32; 8]);
+
+/// Set by `_start`, before anything can make a syscall.
+static mut SYSCALL_ENTRY: Option<SyscallEntry> = None;
+
+/// Makes syscall `nr` with `args` in the argument registers, returning the
+/// contents of the response registers.
+unsafe fn syscall(nr: Sysnum, args: [u32; 7]) -> [u32; 7] {
+    let mut regs = [0; 8];
+    regs[..7].copy_from_slice(&args);
+    regs[7] = nr as u32;
+    match SYSCALL_ENTRY {
+        Some(entry) => entry(&mut regs),
+        // Only possible before `_start` has run, in which case we can't so
+        // much as panic. Trap instead.
+        None => asm!("ud2", options(noreturn)),
+    }
+    let mut rets = [0; 7];
+    rets.copy_from_slice(&regs[..7]);
+    rets
+}
+
+/// This is the entry point for the kernel, which calls it on the task's
+/// stack with the address of the syscall entry point. Like its ARM
+/// counterpart, its job is to set up our memory before jumping to
+/// user-defined `main`.
+#[doc(hidden)]
+#[no_mangle]
+#[link_section = ".text.start"]
+pub unsafe extern "C" fn _start(syscall_entry: SyscallEntry) -> ! {
+    // Provided by the user program:
+    extern "Rust" {
+        fn main() -> !;
+    }
+    // Provided by the linker script.
+    extern "C" {
+        static __sidata: u32;
+        static mut __sdata: u32;
+        static mut __edata: u32;
+        static mut __sbss: u32;
+        static mut __ebss: u32;
+    }
+
+    // Copy data initialization image into data section. As on ARM, this
+    // assumes that both are 32-bit aligned and padded to 4-byte boundaries.
+    // The volatile accesses keep the compiler from turning these loops into
+    // calls to `memcpy` and `memset`, which aren't safe to call until data
+    // is initialized.
+    let mut src: *const u32 = &__sidata;
+    let mut dest: *mut u32 = &mut __sdata;
+    while dest < &mut __edata as *mut u32 {
+        dest.write_volatile(src.read_volatile());
+        src = src.add(1);
+        dest = dest.add(1);
+    }
+
+    // Zero BSS section.
+    let mut dest: *mut u32 = &mut __sbss;
+    while dest < &mut __ebss as *mut u32 {
+        dest.write_volatile(0);
+        dest = dest.add(1);
+    }
+
+    SYSCALL_ENTRY = Some(syscall_entry);
+    main()
+}
+
+// The compiler generates calls to these for copies and comparisons, and
+// without a C library, nobody else provides them. They're written in assembly
+// so that the compiler can't "helpfully" turn their bodies back into calls to
+// themselves.
+global_asm!(
+    "
+    .pushsection .text.memcpy,\"ax\"
+    .globl memcpy
+    .type memcpy,@function
+memcpy:
+    push edi
+    push esi
+    mov edi, [esp + 12]
+    mov esi, [esp + 16]
+    mov ecx, [esp + 20]
+    mov eax, edi
+    rep movsb
+    pop esi
+    pop edi
+    ret
+    .size memcpy, . - memcpy
+    .popsection
+
+    .pushsection .text.memmove,\"ax\"
+    .globl memmove
+    .type memmove,@function
+memmove:
+    push edi
+    push esi
+    mov edi, [esp + 12]
+    mov esi, [esp + 16]
+    mov ecx, [esp + 20]
+    mov eax, edi
+    cmp edi, esi
+    jbe 2f
+    // The destination is above the source, so copy backwards in case they
+    // overlap.
+    lea esi, [esi + ecx - 1]
+    lea edi, [edi + ecx - 1]
+    std
+    rep movsb
+    cld
+    jmp 3f
+2:  rep movsb
+3:  pop esi
+    pop edi
+    ret
+    .size memmove, . - memmove
+    .popsection
+
+    .pushsection .text.memset,\"ax\"
+    .globl memset
+    .type memset,@function
+memset:
+    push edi
+    mov edi, [esp + 8]
+    mov eax, [esp + 12]
+    mov ecx, [esp + 16]
+    mov edx, edi
+    rep stosb
+    mov eax, edx
+    pop edi
+    ret
+    .size memset, . - memset
+    .popsection
+
+    .pushsection .text.memcmp,\"ax\"
+    .globl memcmp
+    .globl bcmp
+    .type memcmp,@function
+    .type bcmp,@function
+memcmp:
+bcmp:
+    push esi
+    push edi
+    mov esi, [esp + 12]
+    mov edi, [esp + 16]
+    mov ecx, [esp + 20]
+    xor eax, eax
+2:  test ecx, ecx
+    jz 3f
+    movzx eax, byte ptr [esi]
+    movzx edx, byte ptr [edi]
+    sub eax, edx
+    jnz 3f
+    inc esi
+    inc edi
+    dec ecx
+    jmp 2b
+3:  pop edi
+    pop esi
+    ret
+    .size memcmp, . - memcmp
+    .popsection
+    "
+);
+
+fn rc_len(rets: [u32; 7]) -> RcLen {
+    RcLen(u64::from(rets[0]) | u64::from(rets[1]) << 32)
+}
+
+pub(crate) unsafe fn sys_send_stub(args: &mut SendArgs<'_>) -> RcLen {
+    rc_len(syscall(
+        Sysnum::Send,
+        [
+            args.packed_target_operation,
+            args.outgoing_ptr as u32,
+            args.outgoing_len as u32,
+            args.incoming_ptr as u32,
+            args.incoming_len as u32,
+            args.lease_ptr as u32,
+            args.lease_len as u32,
+        ],
+    ))
+}
+
+pub(crate) unsafe fn sys_send_timeout_stub(args: &mut SendArgs<'_>) -> RcLen {
+    rc_len(syscall(
+        Sysnum::SendTimeout,
+        [
+            args.packed_target_operation,
+            args.outgoing_ptr as u32,
+            args.outgoing_len as u32,
+            args.incoming_ptr as u32,
+            args.incoming_len as u32,
+            args.lease_ptr as u32,
+            args.lease_len as u32,
+        ],
+    ))
+}
+
+pub(crate) unsafe fn sys_recv_stub(
+    buffer_ptr: *mut u8,
+    buffer_len: usize,
+    notification_mask: u32,
+    specific_sender: u32,
+    out: *mut RawRecvMessage,
+) -> u32 {
+    let rets = syscall(
+        Sysnum::Recv,
+        [
+            buffer_ptr as u32,
+            buffer_len as u32,
+            notification_mask,
+            specific_sender,
+            0,
+            0,
+            0,
+        ],
+    );
+    out.write(RawRecvMessage {
+        sender: rets[1],
+        operation: rets[2],
+        message_len: rets[3] as usize,
+        response_capacity: rets[4] as usize,
+        lease_count: rets[5] as usize,
+    });
+    rets[0]
+}
+
+pub(crate) unsafe fn sys_reply_stub(
+    peer: u32,
+    code: u32,
+    message_ptr: *const u8,
+    message_len: usize,
+) {
+    syscall(
+        Sysnum::Reply,
+        [peer, code, message_ptr as u32, message_len as u32, 0, 0, 0],
+    );
+}
+
+pub(crate) unsafe fn sys_set_timer_stub(
+    set_timer: u32,
+    deadline_lo: u32,
+    deadline_hi: u32,
+    notification: u32,
+) {
+    syscall(
+        Sysnum::SetTimer,
+        [set_timer, deadline_lo, deadline_hi, notification, 0, 0, 0],
+    );
+}
+
+pub(crate) unsafe fn sys_borrow_read_stub(args: *mut BorrowReadArgs) -> RcLen {
+    let args = &*args;
+    rc_len(syscall(
+        Sysnum::BorrowRead,
+        [
+            args.lender,
+            args.index as u32,
+            args.offset as u32,
+            args.dest as u32,
+            args.dest_len as u32,
+            0,
+            0,
+        ],
+    ))
+}
+
+pub(crate) unsafe fn sys_borrow_write_stub(
+    args: *mut BorrowWriteArgs,
+) -> RcLen {
+    let args = &*args;
+    rc_len(syscall(
+        Sysnum::BorrowWrite,
+        [
+            args.lender,
+            args.index as u32,
+            args.offset as u32,
+            args.src as u32,
+            args.src_len as u32,
+            0,
+            0,
+        ],
+    ))
+}
+
+pub(crate) unsafe fn sys_borrow_info_stub(
+    lender: u32,
+    index: usize,
+    out: *mut RawBorrowInfo,
+) {
+    let rets =
+        syscall(Sysnum::BorrowInfo, [lender, index as u32, 0, 0, 0, 0, 0]);
+    out.write(RawBorrowInfo {
+        rc: rets[0],
+        atts: rets[1],
+        length: rets[2] as usize,
+    });
+}
+
+pub(crate) unsafe fn sys_irq_control_stub(mask: u32, enable: u32) {
+    syscall(Sysnum::IrqControl, [mask, enable, 0, 0, 0, 0, 0]);
+}
+
+pub(crate) unsafe fn sys_panic_stub(msg: *const u8, len: usize) -> ! {
+    syscall(Sysnum::Panic, [msg as u32, len as u32, 0, 0, 0, 0, 0]);
+    // The kernel doesn't resume panicked tasks.
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+pub(crate) unsafe fn sys_get_timer_stub(out: *mut RawTimerState) {
+    let rets = syscall(Sysnum::GetTimer, [0; 7]);
+    out.write(RawTimerState {
+        now_lo: rets[0],
+        now_hi: rets[1],
+        set: rets[2],
+        dl_lo: rets[3],
+        dl_hi: rets[4],
+        on_dl: rets[5],
+        micros: rets[6],
+    });
+}
+
+pub(crate) unsafe fn sys_refresh_task_id_stub(tid: u32) -> u32 {
+    syscall(Sysnum::RefreshTaskId, [tid, 0, 0, 0, 0, 0, 0])[0]
+}
+
+pub(crate) unsafe fn sys_post_stub(tid: u32, mask: u32) -> u32 {
+    syscall(Sysnum::Post, [tid, mask, 0, 0, 0, 0, 0])[0]
+}
+
+pub(crate) unsafe fn sys_send_async_stub(args: &mut SendAsyncArgs) -> RcLen {
+    rc_len(syscall(
+        Sysnum::SendAsync,
+        [
+            args.packed_target_operation,
+            args.outgoing_ptr as u32,
+            args.outgoing_len as u32,
+            args.incoming_ptr as u32,
+            args.incoming_len as u32,
+            args.notification,
+            0,
+        ],
+    ))
+}
+
+pub(crate) unsafe fn sys_async_status_stub(
+    slot: usize,
+    cancel: u32,
+    out: *mut RawAsyncStatus,
+) {
+    let rets =
+        syscall(Sysnum::AsyncStatus, [slot as u32, cancel, 0, 0, 0, 0, 0]);
+    out.write(RawAsyncStatus {
+        outcome: rets[0],
+        code: rets[1],
+        length: rets[2] as usize,
+    });
+}
+
+pub(crate) unsafe fn sys_lock_stub(lock: u32, flags: u32) -> u32 {
+    syscall(Sysnum::Lock, [lock, flags, 0, 0, 0, 0, 0])[0]
+}
+
+pub(crate) unsafe fn sys_unlock_stub(lock: u32) {
+    syscall(Sysnum::Unlock, [lock, 0, 0, 0, 0, 0, 0]);
+}
+
+/// There's no processor to pause; the idle task just waits to be preempted.
+pub fn wait_for_interrupt() {
+    core::hint::spin_loop();
+}
+
+/// Divides by zero, which the kernel sees as `SIGFPE`.
+#[inline(never)]
+pub fn divide_by_zero() {
+    unsafe {
+        let q: u32 = 0;
+        asm!(
+            "div {q}",
+            q = in(reg) q,
+            inout("eax") 123u32 => _,
+            inout("edx") 0u32 => _,
+        );
+    }
+}
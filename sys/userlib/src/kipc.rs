@@ -9,9 +9,32 @@ use zerocopy::AsBytes;
 use crate::*;
 
 pub fn read_task_status(task: usize) -> abi::TaskState {
+    read_task_status_raw(task).0
+}
+
+/// Like `read_task_status`, but also returns the priority that `task` is
+/// currently running at. This is the priority it was given in the application,
+/// unless the kernel has `priority-inheritance` and `task` owes a reply to a
+/// more important task, whose priority it then inherits.
+pub fn read_task_status_and_priority(
+    task: usize,
+) -> (abi::TaskState, abi::Priority) {
+    let (state, priority, _) = read_task_status_raw(task);
+    (state, abi::Priority(priority))
+}
+
+/// Returns whether `task` has made an open RECV since it was last started,
+/// which we take to mean that it's ready to serve. The first time it does, the
+/// kernel posts the supervisor's notification, so the supervisor can wait for
+/// this rather than having to keep checking.
+pub fn read_task_ready(task: usize) -> bool {
+    read_task_status_raw(task).2
+}
+
+fn read_task_status_raw(task: usize) -> (abi::TaskState, u8, bool) {
     // Coerce `task` to a known size (Rust doesn't assume that usize == u32)
     let task = task as u32;
-    let mut response = [0; core::mem::size_of::<abi::TaskState>()];
+    let mut response = [0; core::mem::size_of::<(abi::TaskState, u8, bool)>()];
     let (rc, len) =
         sys_send(TaskId::KERNEL, 1, task.as_bytes(), &mut response, &[]);
     assert_eq!(rc, 0);
@@ -21,6 +44,67 @@ pub fn read_task_status(task: usize) -> abi::TaskState {
         .0
 }
 
+/// Returns the most stack, in bytes, that `task` has used since it was last
+/// started, or `None` if the kernel can't tell.
+pub fn read_stack_usage(task: usize) -> Option<u32> {
+    // Coerce `task` to a known size (Rust doesn't assume that usize == u32)
+    let task = task as u32;
+    let mut response = [0; core::mem::size_of::<Option<u32>>()];
+    let (rc, len) =
+        sys_send(TaskId::KERNEL, 5, task.as_bytes(), &mut response, &[]);
+    assert_eq!(rc, 0);
+    ssmarshal::deserialize(&response[..len])
+        .map_err(|_| ())
+        .unwrap()
+        .0
+}
+
+/// Returns how much CPU `task` has used since boot.
+pub fn read_cpu_usage(task: usize) -> abi::CpuUsage {
+    // Coerce `task` to a known size (Rust doesn't assume that usize == u32)
+    let task = task as u32;
+    let mut response = [0; core::mem::size_of::<abi::CpuUsage>()];
+    let (rc, len) =
+        sys_send(TaskId::KERNEL, 7, task.as_bytes(), &mut response, &[]);
+    assert_eq!(rc, 0);
+    ssmarshal::deserialize(&response[..len])
+        .map_err(|_| ())
+        .unwrap()
+        .0
+}
+
+/// Returns what the kernel has seen of interrupt `irq` since boot, or `None` if
+/// it isn't keeping track (because it was built without `irq-stats`). The
+/// interrupt must be routed to some task.
+pub fn read_irq_stats(irq: u32) -> Option<abi::IrqStats> {
+    let mut response = [0; core::mem::size_of::<Option<abi::IrqStats>>()];
+    let (rc, len) =
+        sys_send(TaskId::KERNEL, 8, irq.as_bytes(), &mut response, &[]);
+    assert_eq!(rc, 0);
+    ssmarshal::deserialize(&response[..len])
+        .map_err(|_| ())
+        .unwrap()
+        .0
+}
+
+/// Returns what the kernel knows about `task`'s most recent fault beyond its
+/// `FaultInfo`: a panic message, or where it was when it faulted.
+pub fn read_fault_detail(task: usize) -> abi::FaultDetail {
+    // Coerce `task` to a known size (Rust doesn't assume that usize == u32)
+    let task = task as u32;
+    let mut response = abi::FaultDetail::EMPTY;
+    let (rc, len) = sys_send(
+        TaskId::KERNEL,
+        6,
+        task.as_bytes(),
+        response.as_bytes_mut(),
+        &[],
+    );
+    assert_eq!(rc, 0);
+    assert_eq!(len, core::mem::size_of::<abi::FaultDetail>());
+    response
+}
+
 pub fn restart_task(task: usize, start: bool) {
     // Coerce `task` to a known size (Rust doesn't assume that usize == u32)
     let msg = (task as u32, start);
@@ -32,9 +116,41 @@ pub fn restart_task(task: usize, start: bool) {
     assert_eq!(rc, 0);
 }
 
+/// Starts `task` if it's stopped, without restarting it: its generation is
+/// unchanged, so IPCs already sent to it will be delivered. Does nothing if
+/// `task` isn't stopped. Only the supervisor may do this; any other task that
+/// tries will be faulted.
+pub fn start_task(task: usize) {
+    // Coerce `task` to a known size (Rust doesn't assume that usize == u32)
+    let task = task as u32;
+    let (rc, _len) = sys_send(TaskId::KERNEL, 9, task.as_bytes(), &mut [], &[]);
+    assert_eq!(rc, 0);
+}
+
+/// Raises the interrupts routed to `task` with notifications in `mask`, as if
+/// the hardware had. There must be at least one. Only the supervisor may do
+/// this; any other task that tries will be faulted.
+pub fn software_irq(task: usize, mask: u32) {
+    // Coerce `task` to a known size (Rust doesn't assume that usize == u32)
+    let msg = (task as u32, mask);
+    let mut buf = [0; core::mem::size_of::<(u32, u32)>()];
+    ssmarshal::serialize(&mut buf, &msg)
+        .map_err(|_| ())
+        .unwrap();
+    let (rc, _len) = sys_send(TaskId::KERNEL, 11, &buf, &mut [], &[]);
+    assert_eq!(rc, 0);
+}
+
 pub fn fault_task(task: usize) {
     // Coerce `task` to a known size (Rust doesn't assume that usize == u32)
     let task = task as u32;
     let (rc, _len) = sys_send(TaskId::KERNEL, 3, task.as_bytes(), &mut [], &[]);
     assert_eq!(rc, 0);
 }
+
+/// Resets the system. Only the supervisor may do this; any other task that
+/// tries will be faulted.
+pub fn reset() -> ! {
+    sys_send(TaskId::KERNEL, 4, &[], &mut [], &[]);
+    unreachable!()
+}
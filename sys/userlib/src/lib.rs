@@ -23,10 +23,14 @@
 //! all registers.
 //!
 //! See: https://github.com/rust-lang/rust/issues/73450#issuecomment-650463347
+//!
+//! When building for the kernel's hosted (simulation) backend, the stubs and
+//! `_start` come from the `hosted` module instead.
 
 #![no_std]
 #![feature(asm)]
 #![feature(naked_functions)]
+#![cfg_attr(target_arch = "x86", feature(global_asm))]
 
 #[macro_use]
 pub mod macros;
@@ -43,6 +47,13 @@ pub mod task_slot;
 pub mod units;
 pub mod util;
 
+#[cfg(all(target_arch = "x86", target_os = "linux"))]
+mod hosted;
+#[cfg(all(target_arch = "x86", target_os = "linux"))]
+use hosted::*;
+#[cfg(all(target_arch = "x86", target_os = "linux"))]
+pub use hosted::{divide_by_zero, wait_for_interrupt};
+
 #[derive(Debug)]
 #[repr(transparent)]
 pub struct Lease<'a> {
@@ -149,6 +160,7 @@ struct SendArgs<'a> {
 /// Core implementation of the SEND syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(target_arch = "arm")]
 #[naked]
 unsafe extern "C" fn sys_send_stub(_args: &mut SendArgs<'_>) -> RcLen {
     asm!("
@@ -175,6 +187,77 @@ unsafe extern "C" fn sys_send_stub(_args: &mut SendArgs<'_>) -> RcLen {
     )
 }
 
+/// Like `sys_send`, but gives up if `target` hasn't replied by `deadline` (in
+/// kernel timer ticks, as for `sys_set_timer`), returning
+/// `(abi::SEND_TIMED_OUT, 0)`.
+///
+/// If `target` had already received the message when the deadline passed, it
+/// may still act on it; its reply will be discarded, and its attempts to use
+/// `leases` will fail. Until it does reply, any further send to `target` fails
+/// at once with `abi::SEND_TIMED_OUT`, so that its reply can't be taken for an
+/// answer to something else.
+///
+/// This borrows the task's timer for the duration of the send. Any timer the
+/// task had already set is restored afterwards -- if its deadline passed in
+/// the meantime, its notification is posted when it's restored.
+#[inline(always)]
+pub fn sys_send_with_deadline(
+    target: TaskId,
+    operation: u16,
+    outgoing: &[u8],
+    incoming: &mut [u8],
+    leases: &[Lease<'_>],
+    deadline: u64,
+) -> (u32, usize) {
+    let saved = sys_get_timer();
+    sys_set_timer(Some(deadline), 0);
+
+    let mut args = SendArgs {
+        packed_target_operation: u32::from(target.0) << 16
+            | u32::from(operation),
+        outgoing_ptr: outgoing.as_ptr(),
+        outgoing_len: outgoing.len(),
+        incoming_ptr: incoming.as_mut_ptr(),
+        incoming_len: incoming.len(),
+        lease_ptr: leases.as_ptr(),
+        lease_len: leases.len(),
+    };
+    let result = unsafe { sys_send_timeout_stub(&mut args).into() };
+
+    sys_set_timer_micros(saved.deadline_micros, saved.on_dl);
+    result
+}
+
+/// Core implementation of the SEND_TIMEOUT syscall.
+///
+/// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(target_arch = "arm")]
+#[naked]
+unsafe extern "C" fn sys_send_timeout_stub(_args: &mut SendArgs<'_>) -> RcLen {
+    asm!("
+        @ Spill the registers we're about to use to pass stuff.
+        push {{r4-r11}}
+        @ Load in args from the struct.
+        ldm r0, {{r4-r10}}
+        @ Load the constant syscall number.
+        mov r11, {sysnum}
+
+        @ To the kernel!
+        svc #0
+
+        @ Move the two results back into their return positions.
+        mov r0, r4
+        mov r1, r5
+        @ Restore the registers we used.
+        pop {{r4-r11}}
+        @ Fin.
+        bx lr
+        ",
+        sysnum = const Sysnum::SendTimeout as u32,
+        options(noreturn),
+    )
+}
+
 /// Performs an "open" RECV that will accept messages from any task or
 /// notifications from the kernel.
 ///
@@ -283,6 +366,7 @@ pub struct RecvMessage {
 /// Core implementation of the RECV syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(target_arch = "arm")]
 #[naked]
 #[must_use]
 unsafe extern "C" fn sys_recv_stub(
@@ -348,6 +432,7 @@ pub fn sys_reply(peer: TaskId, code: u32, message: &[u8]) {
 /// Core implementation of the REPLY syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(target_arch = "arm")]
 #[naked]
 unsafe extern "C" fn sys_reply_stub(
     _peer: u32,
@@ -397,10 +482,32 @@ unsafe extern "C" fn sys_reply_stub(
 /// enabled.
 #[inline(always)]
 pub fn sys_set_timer(deadline: Option<u64>, notifications: u32) {
-    let raw_deadline = deadline.unwrap_or(0);
+    set_timer(deadline, 0, notifications)
+}
+
+/// Sets this task's timer, like `sys_set_timer`, but with the deadline in
+/// microseconds since boot rather than in ticks.
+///
+/// How close to the deadline the timer actually fires depends on the kernel:
+/// built with the `tickless` feature on ARM, it's within a microsecond or so.
+/// Otherwise, the timer can't fire between ticks, and fires at the first tick
+/// after the deadline -- up to a whole tick (a millisecond) late, which is no
+/// better than `sys_set_timer`. Tasks that need short delays to be short need
+/// a tickless kernel.
+#[inline(always)]
+pub fn sys_set_timer_micros(deadline: Option<u64>, notifications: u32) {
+    set_timer(deadline, abi::TIMER_MICROS, notifications)
+}
+
+#[inline(always)]
+fn set_timer(deadline: Option<u64>, flags: u32, notifications: u32) {
+    let (flags, raw_deadline) = match deadline {
+        Some(d) => (flags | abi::TIMER_ENABLE, d),
+        None => (0, 0),
+    };
     unsafe {
         sys_set_timer_stub(
-            deadline.is_some() as u32,
+            flags,
             raw_deadline as u32,
             (raw_deadline >> 32) as u32,
             notifications,
@@ -411,6 +518,7 @@ pub fn sys_set_timer(deadline: Option<u64>, notifications: u32) {
 /// Core implementation of the SET_TIMER syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(target_arch = "arm")]
 #[naked]
 unsafe extern "C" fn sys_set_timer_stub(
     _set_timer: u32,
@@ -467,6 +575,7 @@ pub fn sys_borrow_read(
 /// Core implementation of the BORROW_READ syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(target_arch = "arm")]
 #[naked]
 unsafe extern "C" fn sys_borrow_read_stub(_args: *mut BorrowReadArgs) -> RcLen {
     asm!("
@@ -525,6 +634,7 @@ pub fn sys_borrow_write(
 /// Core implementation of the BORROW_WRITE syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(target_arch = "arm")]
 #[naked]
 unsafe extern "C" fn sys_borrow_write_stub(
     _args: *mut BorrowWriteArgs,
@@ -604,6 +714,7 @@ pub struct BorrowInfo {
 /// Core implementation of the BORROW_INFO syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(target_arch = "arm")]
 #[naked]
 unsafe extern "C" fn sys_borrow_info_stub(
     _lender: u32,
@@ -647,6 +758,7 @@ pub fn sys_irq_control(mask: u32, enable: bool) {
 /// Core implementation of the IRQ_CONTROL syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(target_arch = "arm")]
 #[naked]
 unsafe extern "C" fn sys_irq_control_stub(_mask: u32, _enable: u32) {
     asm!("
@@ -682,6 +794,7 @@ pub fn sys_panic(msg: &[u8]) -> ! {
 /// Core implementation of the PANIC syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(target_arch = "arm")]
 #[naked]
 unsafe extern "C" fn sys_panic_stub(_msg: *const u8, _len: usize) -> ! {
     asm!("
@@ -730,14 +843,23 @@ pub fn sys_get_timer() -> TimerState {
     // Safety: stub fully initializes output struct.
     let out = unsafe { out.assume_init() };
 
+    let now = u64::from(out.now_lo) | u64::from(out.now_hi) << 32;
+    let deadline = if out.set != 0 {
+        Some(u64::from(out.dl_lo) | u64::from(out.dl_hi) << 32)
+    } else {
+        None
+    };
+    // The kernel gives us times in ticks, with the microseconds past each
+    // tick packed into the last word.
+    let micros = |ticks: u64, extra: u32| {
+        ticks * abi::MICROS_PER_TICK + u64::from(extra & 0xffff)
+    };
     TimerState {
-        now: u64::from(out.now_lo) | u64::from(out.now_hi) << 32,
-        deadline: if out.set != 0 {
-            Some(u64::from(out.dl_lo) | u64::from(out.dl_hi) << 32)
-        } else {
-            None
-        },
+        now,
+        deadline,
         on_dl: out.on_dl,
+        now_micros: micros(now, out.micros),
+        deadline_micros: deadline.map(|d| micros(d, out.micros >> 16)),
     }
 }
 
@@ -749,6 +871,12 @@ pub struct TimerState {
     pub deadline: Option<u64>,
     /// Notifications to be delivered if the deadline is reached.
     pub on_dl: u32,
+    /// `now`, in microseconds. How precise this is depends on the kernel; see
+    /// `sys_set_timer_micros`.
+    pub now_micros: u64,
+    /// `deadline`, in microseconds, which is exact even if `deadline` has been
+    /// rounded down to a tick.
+    pub deadline_micros: Option<u64>,
 }
 
 #[repr(C)] // loaded from assembly, field order must not change
@@ -759,11 +887,13 @@ struct RawTimerState {
     dl_lo: u32,
     dl_hi: u32,
     on_dl: u32,
+    micros: u32,
 }
 
 /// Core implementation of the GET_TIMER syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(target_arch = "arm")]
 #[naked]
 unsafe extern "C" fn sys_get_timer_stub(_out: *mut RawTimerState) {
     asm!("
@@ -776,7 +906,7 @@ unsafe extern "C" fn sys_get_timer_stub(_out: *mut RawTimerState) {
         svc #0
 
         @ Write all the results out into the raw output buffer.
-        stm r0, {{r4-r9}}
+        stm r0, {{r4-r10}}
         @ Restore the registers we used.
         pop {{r4-r11}}
         @ Fin.
@@ -792,6 +922,7 @@ unsafe extern "C" fn sys_get_timer_stub(_out: *mut RawTimerState) {
 #[doc(hidden)]
 #[no_mangle]
 #[link_section = ".text.start"]
+#[cfg(target_arch = "arm")]
 #[naked]
 pub unsafe extern "C" fn _start() -> ! {
     // Provided by the user program:
@@ -882,11 +1013,9 @@ fn panic(info: &core::panic::PanicInfo) -> ! {
 
     let mut pw = PrefixWrite([0; 128], 0);
     write!(pw, "{}", info).ok();
-    sys_panic(if pw.1 < pw.0.len() {
-        pw.0.split_at(pw.1).0
-    } else {
-        &[]
-    });
+    // `write_str` never takes us past the end of the buffer, so even a
+    // message that didn't fit leaves us a prefix to send.
+    sys_panic(&pw.0[..pw.1]);
 }
 
 #[cfg(not(feature = "panic-messages"))]
@@ -904,6 +1033,7 @@ pub fn sys_refresh_task_id(task_id: TaskId) -> TaskId {
 /// Core implementation of the REFRESH_TASK_ID syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(target_arch = "arm")]
 #[naked]
 unsafe extern "C" fn sys_refresh_task_id_stub(_tid: u32) -> u32 {
     asm!("
@@ -940,6 +1070,7 @@ pub fn sys_post(task_id: TaskId, bits: u32) -> u32 {
 /// Core implementation of the POST syscall.
 ///
 /// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(target_arch = "arm")]
 #[naked]
 unsafe extern "C" fn sys_post_stub(_tid: u32, _mask: u32) -> u32 {
     asm!("
@@ -967,3 +1098,315 @@ unsafe extern "C" fn sys_post_stub(_tid: u32, _mask: u32) -> u32 {
         options(noreturn),
     )
 }
+
+/// Sends a message to `target` without waiting for the reply.
+///
+/// The message is delivered when `target` next receives, as for `sys_send`,
+/// but the caller keeps running. When `target` replies (or dies), the kernel
+/// writes the reply into `incoming` and posts `notification` to the caller,
+/// which can then collect the outcome with `sys_async_status`. Messages sent
+/// this way can't carry leases.
+///
+/// This is intended for high-trust tasks, like the supervisor, that need to
+/// talk to less trustworthy ones without risking being blocked forever.
+///
+/// On success, returns the slot number identifying the send. Each task can
+/// have `abi::ASYNC_SENDS_PER_TASK` sends outstanding, but only one to a given
+/// task; beyond that, this fails with `abi::ASYNC_QUEUE_FULL` or
+/// `abi::ASYNC_PEER_BUSY` respectively. It also fails with a dead code if
+/// `target` has been restarted.
+///
+/// # Safety
+///
+/// The kernel reads `outgoing` and writes `incoming` at some point after this
+/// returns. The caller must keep both valid, and not otherwise use `incoming`,
+/// until `sys_async_status` reports that the send is complete or has been
+/// cancelled.
+#[inline(always)]
+pub unsafe fn sys_send_async(
+    target: TaskId,
+    operation: u16,
+    outgoing: &[u8],
+    incoming: &mut [u8],
+    notification: u32,
+) -> Result<usize, u32> {
+    let mut args = SendAsyncArgs {
+        packed_target_operation: u32::from(target.0) << 16
+            | u32::from(operation),
+        outgoing_ptr: outgoing.as_ptr(),
+        outgoing_len: outgoing.len(),
+        incoming_ptr: incoming.as_mut_ptr(),
+        incoming_len: incoming.len(),
+        notification,
+    };
+    let (rc, slot) = sys_send_async_stub(&mut args).into();
+    if rc == 0 {
+        Ok(slot)
+    } else {
+        Err(rc)
+    }
+}
+
+#[allow(dead_code)] // this gets used from asm
+#[repr(C)] // field order matters
+struct SendAsyncArgs {
+    packed_target_operation: u32,
+    outgoing_ptr: *const u8,
+    outgoing_len: usize,
+    incoming_ptr: *mut u8,
+    incoming_len: usize,
+    notification: u32,
+}
+
+/// Core implementation of the SEND_ASYNC syscall.
+///
+/// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(target_arch = "arm")]
+#[naked]
+unsafe extern "C" fn sys_send_async_stub(_args: &mut SendAsyncArgs) -> RcLen {
+    asm!("
+        @ Spill the registers we're about to use to pass stuff.
+        push {{r4-r11}}
+        @ Load in args from the struct.
+        ldm r0, {{r4-r9}}
+        @ Load the constant syscall number.
+        mov r11, {sysnum}
+
+        @ To the kernel!
+        svc #0
+
+        @ Move the two results back into their return positions.
+        mov r0, r4
+        mov r1, r5
+        @ Restore the registers we used.
+        pop {{r4-r11}}
+        @ Fin.
+        bx lr
+        ",
+        sysnum = const Sysnum::SendAsync as u32,
+        options(noreturn),
+    )
+}
+
+/// Checks on the asynchronous send in `slot`, made with `sys_send_async`.
+///
+/// If the send has completed, returns its response code and the length of the
+/// reply written into its `incoming` buffer, and frees the slot for reuse.
+/// Otherwise returns `None`.
+///
+/// Checking on a slot that isn't in use is a fault.
+#[inline(always)]
+pub fn sys_async_status(slot: usize) -> Option<(u32, usize)> {
+    async_status(slot, false)
+}
+
+/// Cancels the asynchronous send in `slot`, made with `sys_send_async`, and
+/// frees the slot. After this returns, the kernel will not touch the send's
+/// buffers again.
+///
+/// If the send had already completed, returns its outcome as for
+/// `sys_async_status`.
+///
+/// Note that if the recipient had already received the message, the slot
+/// remains occupied until it replies (or is restarted) -- and, until then,
+/// the caller can't send to it again.
+#[inline(always)]
+pub fn sys_async_cancel(slot: usize) -> Option<(u32, usize)> {
+    async_status(slot, true)
+}
+
+#[inline(always)]
+fn async_status(slot: usize, cancel: bool) -> Option<(u32, usize)> {
+    use core::mem::MaybeUninit;
+
+    let mut raw = MaybeUninit::<RawAsyncStatus>::uninit();
+    unsafe {
+        sys_async_status_stub(slot, cancel as u32, raw.as_mut_ptr());
+    }
+    // Safety: stub completely initializes record
+    let raw = unsafe { raw.assume_init() };
+
+    if raw.outcome == AsyncOutcome::Complete as u32 {
+        Some((raw.code, raw.length))
+    } else {
+        None
+    }
+}
+
+#[repr(C)]
+struct RawAsyncStatus {
+    outcome: u32,
+    code: u32,
+    length: usize,
+}
+
+/// Core implementation of the ASYNC_STATUS syscall.
+///
+/// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(target_arch = "arm")]
+#[naked]
+unsafe extern "C" fn sys_async_status_stub(
+    _slot: usize,
+    _cancel: u32,
+    _out: *mut RawAsyncStatus,
+) {
+    asm!("
+        @ Spill the registers we're about to use to pass stuff. Note that we're
+        @ being clever and pushing only the registers we need; this means the
+        @ pop sequence at the end needs to match!
+        push {{r4-r6, r11}}
+
+        @ Move register arguments into place.
+        mov r4, r0
+        mov r5, r1
+        @ Load the constant syscall number.
+        mov r11, {sysnum}
+
+        @ To the kernel!
+        svc #0
+
+        @ Move the results into place.
+        stm r2, {{r4-r6}}
+
+        @ Restore the registers we used and return.
+        pop {{r4-r6, r11}}
+        bx lr
+        ",
+        sysnum = const Sysnum::AsyncStatus as u32,
+        options(noreturn),
+    )
+}
+
+/// How a lock was taken by `sys_lock` or `sys_try_lock`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum LockAcquired {
+    /// The lock was free, or was released by its previous owner.
+    Clean,
+    /// The lock's previous owner faulted or was restarted while holding it,
+    /// so whatever the lock protects may need to be put back into a known
+    /// state.
+    OwnerDied,
+}
+
+/// Takes the application lock with index `lock`, waiting for its current
+/// owner (if any) to release it.
+///
+/// Locks are declared in the `[locks]` table of the application's `app.toml`;
+/// the `lock-enum` feature of the `hubris-num-tasks` crate gives their indices
+/// by name. If several tasks are waiting for a lock when it's released, the
+/// most important one gets it.
+///
+/// Naming a lock that doesn't exist, or that the application doesn't let the
+/// caller use, is a fault, as is taking a lock that the caller already holds.
+#[inline(always)]
+pub fn sys_lock(lock: usize) -> LockAcquired {
+    lock_result(unsafe { sys_lock_stub(lock as u32, 0) })
+        .unwrap_or_else(|| panic!())
+}
+
+/// Takes the application lock with index `lock` if nobody else holds it, as
+/// for `sys_lock`, or returns `None` without waiting if somebody does.
+#[inline(always)]
+pub fn sys_try_lock(lock: usize) -> Option<LockAcquired> {
+    lock_result(unsafe { sys_lock_stub(lock as u32, LOCK_TRY) })
+}
+
+fn lock_result(rc: u32) -> Option<LockAcquired> {
+    match rc {
+        0 => Some(LockAcquired::Clean),
+        LOCK_OWNER_DIED => Some(LockAcquired::OwnerDied),
+        _ => None,
+    }
+}
+
+/// Core implementation of the LOCK syscall.
+///
+/// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(target_arch = "arm")]
+#[naked]
+unsafe extern "C" fn sys_lock_stub(_lock: u32, _flags: u32) -> u32 {
+    asm!("
+        @ Spill the registers we're about to use to pass stuff. Note that we're
+        @ being clever and pushing only the registers we need; this means the
+        @ pop sequence at the end needs to match!
+        push {{r4, r5, r11, lr}}
+
+        @ Move register arguments into place.
+        mov r4, r0
+        mov r5, r1
+        @ Load the constant syscall number.
+        mov r11, {sysnum}
+
+        @ To the kernel!
+        svc #0
+
+        @ Move result into place.
+        mov r0, r4
+
+        @ Restore the registers we used and return.
+        pop {{r4, r5, r11, pc}}
+        ",
+        sysnum = const Sysnum::Lock as u32,
+        options(noreturn),
+    )
+}
+
+/// Pauses the processor until an interrupt arrives, which could wake some
+/// higher-priority task. With a tickless kernel, that's not until the next
+/// timer deadline or hardware interrupt. This is the idle task's whole job.
+#[cfg(target_arch = "arm")]
+#[inline(always)]
+pub fn wait_for_interrupt() {
+    unsafe { asm!("wfi", options(nomem, nostack, preserves_flags)) }
+}
+
+/// Divides by zero, which faults the task (the kernel has the processor trap
+/// it). This is for tasks that demonstrate or test fault handling.
+#[cfg(target_arch = "arm")]
+#[inline(never)]
+pub fn divide_by_zero() {
+    unsafe {
+        let p: u32 = 123;
+        let q: u32 = 0;
+        let _res: u32;
+        asm!("udiv r2, r1, r0", in("r1") p, in("r0") q, out("r2") _res);
+    }
+}
+
+/// Releases the application lock with index `lock`, which the caller must
+/// hold, handing it to the most important task waiting for it, if any.
+#[inline(always)]
+pub fn sys_unlock(lock: usize) {
+    unsafe { sys_unlock_stub(lock as u32) }
+}
+
+/// Core implementation of the UNLOCK syscall.
+///
+/// See the note on syscall stubs at the top of this module for rationale.
+#[cfg(target_arch = "arm")]
+#[naked]
+unsafe extern "C" fn sys_unlock_stub(_lock: u32) {
+    asm!("
+        @ Spill the registers we're about to use to pass stuff. Note that we're
+        @ being clever and pushing only the registers we need (plus one to
+        @ maintain alignment); this means the pop sequence at the end needs to
+        @ match!
+        push {{r4, r5, r11, lr}}
+
+        @ Move register arguments into place.
+        mov r4, r0
+        @ Load the constant syscall number.
+        mov r11, {sysnum}
+
+        @ To the kernel!
+        svc #0
+
+        @ This call returns no results.
+
+        @ Restore the registers we used and return.
+        pop {{r4, r5, r11, pc}}
+        ",
+        sysnum = const Sysnum::Unlock as u32,
+        options(noreturn),
+    )
+}
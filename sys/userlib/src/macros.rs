@@ -5,6 +5,9 @@
 pub use bstringify;
 pub use paste;
 
+#[cfg(feature = "log-semihosting")]
+pub use cortex_m_semihosting;
+
 #[cfg(feature = "log-itm")]
 #[macro_export]
 macro_rules! sys_log {
@@ -26,10 +29,10 @@ macro_rules! sys_log {
 #[macro_export]
 macro_rules! sys_log {
     ($s:expr) => {
-        { let _ = cortex_m_semihosting::hprintln!($s); }
+        { let _ = $crate::macros::cortex_m_semihosting::hprintln!($s); }
     };
     ($s:expr, $($tt:tt)*) => {
-        { let _ = cortex_m_semihosting::hprintln!($s, $($tt)*); }
+        { let _ = $crate::macros::cortex_m_semihosting::hprintln!($s, $($tt)*); }
     };
 }
 
@@ -0,0 +1,177 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Client API for the supervisor (Jefe)
+//!
+//! Most tasks have no business talking to the supervisor, beyond checking in
+//! with `userlib::hl::heartbeat`. This API is for management tasks that report
+//! on and steer the rest of the system: it lets them see how each task is
+//! doing, change what the supervisor does about a task (much as Humility can,
+//! through `task/jefe/src/external.rs`), and read back the supervisor's log of
+//! task faults, which survives a reset (but not a loss of power).
+//!
+//! The supervisor only ever receives and replies, never sending to anyone but
+//! the kernel, so it's always safe to call. It can be found at
+//! `TaskId::SUPERVISOR`:
+//!
+//! ```ignore
+//! let jefe = Jefe::from(TaskId::SUPERVISOR);
+//! jefe.set_disposition(index, Disposition::Hold)?;
+//! ```
+//!
+//! Only tasks named in the supervisor's configuration may change dispositions;
+//! anyone else gets `JefeError::NotPermitted`:
+//!
+//! ```toml
+//! [tasks.jefe.config]
+//! disposition-callers = ["hiffy"]
+//! ```
+
+#![no_std]
+
+use zerocopy::{AsBytes, FromBytes};
+
+use userlib::*;
+
+/// Errors that can be produced from the supervisor API.
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
+pub enum JefeError {
+    /// There is no such task, or the fault log holds fewer entries than that.
+    NoSuchEntry = 1,
+    /// The supervisor can't be asked to do that to itself.
+    IllegalTask = 2,
+    /// Not a valid `Disposition`.
+    BadDisposition = 3,
+    /// The supervisor didn't understand the message.
+    BadMessage = 4,
+    /// The caller isn't allowed to do that; see `disposition-callers` in the
+    /// supervisor's configuration.
+    NotPermitted = 5,
+}
+
+impl From<JefeError> for u16 {
+    fn from(rc: JefeError) -> Self {
+        rc as u16
+    }
+}
+
+impl From<JefeError> for u32 {
+    fn from(rc: JefeError) -> Self {
+        rc as u32
+    }
+}
+
+impl core::convert::TryFrom<u32> for JefeError {
+    type Error = ();
+    fn try_from(rc: u32) -> Result<Self, Self::Error> {
+        Self::from_u32(rc).ok_or(())
+    }
+}
+
+/// What the supervisor does about a task.
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
+#[repr(u8)]
+pub enum Disposition {
+    /// Restart the task whenever it faults, subject to its restart policy.
+    Restart = 0,
+    /// Start the task if it's stopped, and then treat it as `Restart`.
+    Start = 1,
+    /// Leave the task alone if it faults, e.g. for a debugger to look at.
+    Hold = 2,
+    /// Fault the task, and then treat it as `Hold`.
+    Fault = 3,
+}
+
+/// Roughly what a task is doing, as reported in `TaskStatus`.
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
+#[repr(u8)]
+pub enum TaskRunState {
+    Stopped = 0,
+    Runnable = 1,
+    /// Blocked in SEND, REPLY or RECV.
+    Blocked = 2,
+    Faulted = 3,
+}
+
+/// How a task is doing, as reported by `Jefe::get_task_status`.
+#[derive(Copy, Clone, Debug, FromBytes, AsBytes)]
+#[repr(C)]
+pub struct TaskStatus {
+    /// Time at which the task last faulted, if `faults` is nonzero.
+    pub last_fault_time: u64,
+    /// Number of times the supervisor has restarted the task since boot.
+    pub restarts: u32,
+    /// Number of times the task has faulted since boot.
+    pub faults: u32,
+    /// Current generation of the task.
+    pub generation: u16,
+    /// A `TaskRunState`.
+    pub state: u8,
+    /// A `Disposition`.
+    pub disposition: u8,
+    /// The task's most recent fault, if `faults` is nonzero, as encoded by
+    /// `encode_fault`.
+    pub last_fault: [u8; FAULT_RECORD_INFO_SIZE],
+    /// Most stack, in bytes, that the task has used since it last started,
+    /// or `u32::MAX` if that's unknown.
+    pub stack_used: u32,
+}
+
+impl TaskStatus {
+    pub fn state(&self) -> Option<TaskRunState> {
+        TaskRunState::from_u8(self.state)
+    }
+
+    pub fn disposition(&self) -> Option<Disposition> {
+        Disposition::from_u8(self.disposition)
+    }
+
+    pub fn stack_used(&self) -> Option<u32> {
+        if self.stack_used == u32::MAX {
+            None
+        } else {
+            Some(self.stack_used)
+        }
+    }
+
+    /// Decodes the task's most recent fault, if it has faulted since boot.
+    pub fn last_fault(&self) -> Option<FaultInfo> {
+        if self.faults == 0 {
+            None
+        } else {
+            decode(&self.last_fault)
+        }
+    }
+}
+
+impl Jefe {
+    /// Changes what the supervisor does about task `index`.
+    pub fn set_disposition(
+        &self,
+        index: usize,
+        disposition: Disposition,
+    ) -> Result<(), JefeError> {
+        self.set_disposition_raw(index as u32, disposition as u32)
+    }
+}
+
+/// Decodes the fault recorded in `record`, which may fail if the record was
+/// written by a supervisor with a different idea of `FaultInfo`.
+pub fn decode_fault(record: &FaultRecord) -> Option<FaultInfo> {
+    decode(&record.fault)
+}
+
+fn decode(bytes: &[u8]) -> Option<FaultInfo> {
+    ssmarshal::deserialize(bytes).ok().map(|(fault, _)| fault)
+}
+
+/// Serializes `fault` for storage in a `FaultRecord` or `TaskStatus`.
+pub fn encode_fault(fault: &FaultInfo) -> [u8; FAULT_RECORD_INFO_SIZE] {
+    let mut buf = [0; FAULT_RECORD_INFO_SIZE];
+    // Every `FaultInfo` fits with room to spare, so this can't fail.
+    ssmarshal::serialize(&mut buf, fault).unwrap();
+    buf
+}
+
+include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
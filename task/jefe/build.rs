@@ -0,0 +1,254 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Jefe's task-specific configuration, from `[tasks.jefe.config]`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct Config {
+    /// The hardware watchdog, which is left off unless this is present.
+    watchdog: Option<WatchdogConfig>,
+    #[serde(default)]
+    restart: RestartConfig,
+    /// Names of tasks that may change other tasks' dispositions through the
+    /// `Jefe` interface. (Debuggers always can.)
+    #[serde(default)]
+    disposition_callers: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct WatchdogConfig {
+    /// Names of tasks that must check in for the watchdog to be kicked.
+    critical: Vec<String>,
+    /// How recently, in milliseconds, each critical task must have checked in.
+    #[serde(default = "default_heartbeat_ms")]
+    heartbeat_ms: u64,
+    /// Hardware watchdog timeout, in milliseconds.
+    #[serde(default = "default_timeout_ms")]
+    timeout_ms: u32,
+}
+
+fn default_heartbeat_ms() -> u64 {
+    1000
+}
+
+fn default_timeout_ms() -> u32 {
+    1000
+}
+
+/// Restart policies: one that applies to every task by default, and
+/// overrides for particular tasks, keyed by name. Any setting that an
+/// override leaves out is taken from the default.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct RestartConfig {
+    #[serde(default)]
+    default: RestartPolicy,
+    #[serde(default)]
+    task: BTreeMap<String, RestartPolicy>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct RestartPolicy {
+    /// Delay before the second restart in a row; the first is immediate.
+    initial_backoff_ms: Option<u64>,
+    /// Limit on the delay, which doubles with each further restart.
+    max_backoff_ms: Option<u64>,
+    /// Number of restarts in a row after which we escalate, if any.
+    max_restarts: Option<u32>,
+    /// How long a task must run without faulting for its restarts to no
+    /// longer count as being "in a row".
+    window_ms: Option<u64>,
+    /// What to do once `max_restarts` is exceeded.
+    escalation: Option<Escalation>,
+    /// Tasks to restart along with this one, for `restart-dependents`.
+    dependents: Option<Vec<String>>,
+}
+
+#[derive(Copy, Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Escalation {
+    Hold,
+    RestartDependents,
+    Reset,
+}
+
+fn task_index(task_names: &[&str], name: &str) -> Result<usize, String> {
+    task_names
+        .iter()
+        .position(|t| *t == name)
+        .ok_or_else(|| format!("unknown task {:?}", name))
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let config = build_util::task_config::<Config>()?.unwrap_or_default();
+    let watchdog = config.watchdog;
+    let restart = config.restart;
+
+    println!("cargo:rerun-if-env-changed=HUBRIS_TASKS");
+    let task_names = env::var("HUBRIS_TASKS")?;
+    let task_names: Vec<&str> = task_names.split(',').collect();
+
+    let out = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+
+    let mut file = File::create(out.join("watchdog_config.rs"))?;
+    if let Some(watchdog) = watchdog {
+        let critical = watchdog
+            .critical
+            .iter()
+            .map(|name| task_index(&task_names, name))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if watchdog.timeout_ms < 100 {
+            // Jefe only gets around to kicking the watchdog every 100 ms.
+            return Err("watchdog timeout-ms must be at least 100".into());
+        }
+
+        // Without one of these, there's no watchdog to turn on, and the
+        // application would silently go without.
+        let chips = ["H743", "H753", "H7B3", "F4", "LPC55"];
+        if !chips
+            .iter()
+            .any(|c| env::var_os(format!("CARGO_FEATURE_{}", c)).is_some())
+        {
+            return Err("the watchdog needs one of Jefe's chip features, \
+                        to say which one to use"
+                .into());
+        }
+
+        writeln!(file, "pub const ENABLED: bool = true;")?;
+        writeln!(
+            file,
+            "pub const CRITICAL_TASKS: &[usize] = &{:?};",
+            critical
+        )?;
+        writeln!(
+            file,
+            "pub const HEARTBEAT_MS: u64 = {};",
+            watchdog.heartbeat_ms
+        )?;
+        writeln!(file, "pub const TIMEOUT_MS: u32 = {};", watchdog.timeout_ms)?;
+    } else {
+        writeln!(file, "pub const ENABLED: bool = false;")?;
+        writeln!(file, "pub const CRITICAL_TASKS: &[usize] = &[];")?;
+        writeln!(file, "pub const HEARTBEAT_MS: u64 = 0;")?;
+        writeln!(file, "pub const TIMEOUT_MS: u32 = 0;")?;
+    }
+
+    // Which tasks each task has to wait for before it's started, encoded by
+    // `xtask dist` as `task=dep,dep;task=dep`. It's already checked these for
+    // sense, but we still have to find the tasks.
+    println!("cargo:rerun-if-env-changed=HUBRIS_START_AFTER");
+    let start_after = env::var("HUBRIS_START_AFTER").unwrap_or_default();
+    let mut waits = vec![vec![]; task_names.len()];
+    for entry in start_after.split(';').filter(|e| !e.is_empty()) {
+        let (name, deps) = entry.split_once('=').ok_or_else(|| {
+            format!("bad HUBRIS_START_AFTER entry {:?}", entry)
+        })?;
+        let index = task_index(&task_names, name)?;
+        for dep in deps.split(',') {
+            waits[index].push(task_index(&task_names, dep)?);
+        }
+    }
+
+    let mut file = File::create(out.join("start_config.rs"))?;
+    writeln!(
+        file,
+        "pub const START_AFTER: [&[usize]; {}] = [",
+        task_names.len()
+    )?;
+    for (name, deps) in task_names.iter().zip(&waits) {
+        writeln!(file, "    // {}\n    &{:?},", name, deps)?;
+    }
+    writeln!(file, "];")?;
+
+    let disposition_callers = config
+        .disposition_callers
+        .iter()
+        .map(|name| task_index(&task_names, name))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut file = File::create(out.join("ipc_config.rs"))?;
+    writeln!(
+        file,
+        "pub const DISPOSITION_CALLERS: &[usize] = &{:?};",
+        disposition_callers
+    )?;
+
+    for name in restart.task.keys() {
+        task_index(&task_names, name)?;
+    }
+
+    let mut file = File::create(out.join("restart_config.rs"))?;
+    writeln!(
+        file,
+        "pub const RESTART_POLICY: [RestartPolicy; {}] = [",
+        task_names.len()
+    )?;
+    for (i, name) in task_names.iter().enumerate() {
+        let over = restart.task.get(*name).cloned().unwrap_or_default();
+        let default = &restart.default;
+
+        let escalation = match over.escalation.or(default.escalation) {
+            None | Some(Escalation::Hold) => "Escalation::Hold".to_string(),
+            Some(Escalation::Reset) => "Escalation::Reset".to_string(),
+            Some(Escalation::RestartDependents) => {
+                let dependents = over
+                    .dependents
+                    .as_ref()
+                    .or(default.dependents.as_ref())
+                    .ok_or_else(|| {
+                        format!("task {} has no dependents to restart", name)
+                    })?;
+                let mut indices = vec![];
+                for d in dependents {
+                    let index = task_index(&task_names, d)?;
+                    if index == 0 || index == i {
+                        return Err(format!(
+                            "task {} can't list {} as a dependent",
+                            name, d
+                        )
+                        .into());
+                    }
+                    indices.push(index);
+                }
+                format!("Escalation::RestartDependents(&{:?})", indices)
+            }
+        };
+
+        writeln!(
+            file,
+            "    // {}\n    RestartPolicy {{ initial_backoff_ms: {}, \
+             max_backoff_ms: {}, max_restarts: {:?}, window_ms: {}, \
+             escalation: {} }},",
+            name,
+            over.initial_backoff_ms
+                .or(default.initial_backoff_ms)
+                .unwrap_or(1),
+            over.max_backoff_ms
+                .or(default.max_backoff_ms)
+                .unwrap_or(1000),
+            over.max_restarts.or(default.max_restarts),
+            over.window_ms.or(default.window_ms).unwrap_or(10_000),
+            escalation,
+        )?;
+    }
+    writeln!(file, "];")?;
+
+    idol::server::build_server_support(
+        "../../idl/jefe.idol",
+        "server_stub.rs",
+        idol::server::ServerStyle::InOrder,
+    )?;
+
+    Ok(())
+}
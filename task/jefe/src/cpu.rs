@@ -0,0 +1,30 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! CPU usage tracking for Jefe
+//!
+//! The kernel keeps track of how much CPU time each task has used since boot,
+//! and how many times it's been switched to. Whenever we refresh our record of
+//! stack usage, we also copy these into `CPU_USAGE`, an array of
+//! `abi::CpuUsage` indexed by task, where a debugger can find it; `cargo xtask
+//! top` decodes a dump of it, or the difference between two dumps taken a
+//! while apart.
+
+use crate::samples::TaskSamples;
+use userlib::*;
+
+#[used]
+#[no_mangle]
+static CPU_USAGE: TaskSamples<abi::CpuUsage> = TaskSamples::new(
+    [abi::CpuUsage {
+        time: 0,
+        switches: 0,
+        reserved: 0,
+    }; hubris_num_tasks::NUM_TASKS],
+);
+
+/// Brings `CPU_USAGE` up to date.
+pub fn refresh() {
+    CPU_USAGE.refresh(kipc::read_cpu_usage);
+}
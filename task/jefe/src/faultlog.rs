@@ -0,0 +1,113 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Persistent fault log for Jefe
+//!
+//! Each task fault that Jefe notices is recorded, with the task's index and
+//! generation and the time, in a ring of `abi::FaultRecord`s at the symbol
+//! `FAULT_LOG`. The ring lives in a part of Jefe's RAM that isn't initialized
+//! at boot, so it survives a reset (whether by the watchdog, by restart
+//! escalation, or by a debugger) and accumulates faults across boots until
+//! it's cleared; it does not survive a loss of power, as we don't (yet) copy
+//! it to flash.
+//!
+//! Tasks can read and clear the log through the `Jefe` Idol interface. From
+//! the host, `cargo xtask faults` decodes a dump of the `FAULT_LOG` symbol.
+
+use core::mem::MaybeUninit;
+
+use abi::{FaultInfo, FaultLogHeader, FaultRecord};
+use userlib::*;
+use zerocopy::FromBytes;
+
+/// Number of faults we remember; once the ring is full, each new fault
+/// overwrites the oldest.
+const FAULT_LOG_ENTRIES: usize = 16;
+
+#[repr(C)]
+struct Log {
+    header: FaultLogHeader,
+    records: [FaultRecord; FAULT_LOG_ENTRIES],
+}
+
+#[used]
+#[no_mangle]
+#[link_section = ".uninit"]
+static mut FAULT_LOG: MaybeUninit<Log> = MaybeUninit::uninit();
+
+pub struct FaultLog {
+    log: &'static mut Log,
+}
+
+impl FaultLog {
+    /// Takes over the fault log, starting a fresh one if what's in RAM
+    /// doesn't look like one of ours, and counts this boot. This must only
+    /// be called once.
+    pub fn start() -> Self {
+        // Safety: we are called once, and are the only code that touches
+        // `FAULT_LOG`. Every bit pattern is a valid `Log`, even before it's
+        // been written, as it consists entirely of integers.
+        let log = unsafe { &mut *FAULT_LOG.as_mut_ptr() };
+
+        let header = &mut log.header;
+        if header.magic != abi::FAULT_LOG_MAGIC
+            || header.record_size as usize
+                != core::mem::size_of::<FaultRecord>()
+            || header.capacity as usize != FAULT_LOG_ENTRIES
+        {
+            *header = FaultLogHeader {
+                magic: abi::FAULT_LOG_MAGIC,
+                record_size: core::mem::size_of::<FaultRecord>() as u32,
+                capacity: FAULT_LOG_ENTRIES as u32,
+                count: 0,
+                boots: 0,
+                reserved: 0,
+            };
+        } else if header.count != 0 {
+            sys_log!(
+                "Fault log holds {} faults from earlier boots",
+                header.count.min(FAULT_LOG_ENTRIES as u32)
+            );
+        }
+        header.boots = header.boots.wrapping_add(1);
+
+        Self { log }
+    }
+
+    /// Records a fault in task `index`.
+    pub fn record(&mut self, index: usize, fault: &FaultInfo) {
+        let header = &mut self.log.header;
+        let slot = header.count as usize % FAULT_LOG_ENTRIES;
+        self.log.records[slot] = FaultRecord {
+            timestamp: sys_get_timer().now,
+            boot: header.boots,
+            task: index as u16,
+            generation: u8::from(crate::task_generation(index)).into(),
+            fault: task_jefe_api::encode_fault(fault),
+        };
+        header.count = header.count.wrapping_add(1);
+    }
+
+    /// Returns entry `n` of the log, counting from the oldest that we still
+    /// hold.
+    pub fn get(&self, n: u32) -> Option<FaultRecord> {
+        let count = self.log.header.count as usize;
+        let len = count.min(FAULT_LOG_ENTRIES);
+        let n = n as usize;
+        if n >= len {
+            return None;
+        }
+
+        // Until the ring wraps, it starts at slot 0; after that, the oldest
+        // record is the one about to be overwritten.
+        let first = if count <= FAULT_LOG_ENTRIES { 0 } else { count };
+        Some(self.log.records[(first + n) % FAULT_LOG_ENTRIES])
+    }
+
+    /// Forgets every fault recorded so far.
+    pub fn clear(&mut self) {
+        self.log.header.count = 0;
+        self.log.records = [FaultRecord::new_zeroed(); FAULT_LOG_ENTRIES];
+    }
+}
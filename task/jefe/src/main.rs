@@ -7,30 +7,48 @@
 //! The supervisor is responsible for:
 //!
 //! - Maintaining the system console output (currently via semihosting).
-//! - Monitoring tasks for failures and restarting them.
+//! - Monitoring tasks for failures and restarting them (see the `restart`
+//!   module for how often).
+//! - Starting tasks that have to wait for others (see the `start` module).
+//! - Managing the hardware watchdog timer (see the `watchdog` module).
+//! - Keeping a log of task faults that survives reset (see the `faultlog`
+//!   module).
+//! - Keeping track of how much stack each task uses (see the `stacks`
+//!   module), and how much CPU (see the `cpu` module).
+//! - Answering questions about, and taking direction on, the tasks it
+//!   supervises, from other tasks (through the `Jefe` Idol interface, in
+//!   `idl/jefe.idol`) and from debuggers (see the `external` module).
 //!
 //! It will probably become responsible for:
 //!
 //! - Evacuating kernel log information.
 //! - Coordinating certain shared resources, such as the RCC and GPIO muxing.
-//! - Managing a watchdog timer.
 //!
 //! It's unwise for the supervisor to use `SEND`, ever, except to talk to the
 //! kernel. This is because a `SEND` to a misbehaving task could block forever,
-//! taking out the supervisor. The long-term idea is to provide some sort of
-//! asynchronous messaging from the supervisor to less-trusted tasks, but that
-//! doesn't exist yet, so we're mostly using RECV/REPLY and notifications. This
-//! means that hardware drivers required for this task must be built in instead
-//! of running in separate tasks.
+//! taking out the supervisor. To talk to less-trusted tasks, the supervisor
+//! should instead use `sys_send_async`, which returns immediately and posts a
+//! notification when the reply arrives (or the recipient is restarted); the
+//! reply can then be collected with `sys_async_status`, and a send that is
+//! taking too long can be given up on with `sys_async_cancel`.
 
 #![no_std]
 #![no_main]
 
+mod cpu;
 mod external;
+mod faultlog;
+mod restart;
+mod samples;
+mod stacks;
+mod start;
+mod watchdog;
 
+use idol_runtime::RequestError;
+use task_jefe_api::{Disposition, JefeError, TaskRunState, TaskStatus};
 use userlib::*;
 
-fn log_fault(t: usize, fault: &abi::FaultInfo) {
+fn log_fault(t: usize, fault: &abi::FaultInfo, detail: &abi::FaultDetail) {
     match fault {
         abi::FaultInfo::MemoryAccess { address, .. } => match address {
             Some(a) => {
@@ -77,97 +95,367 @@ fn log_fault(t: usize, fault: &abi::FaultInfo) {
         }
 
         abi::FaultInfo::Panic => {
-            sys_log!("Task #{} Panic!", t);
+            match core::str::from_utf8(detail.message()) {
+                Ok(msg) if !msg.is_empty() => {
+                    sys_log!("Task #{} Panic: {}", t, msg);
+                }
+                _ => {
+                    sys_log!("Task #{} Panic!", t);
+                }
+            }
+            let loc = core::str::from_utf8(detail.location()).unwrap_or("");
+            if !loc.is_empty() {
+                sys_log!("Task #{} panicked at {}", t, loc);
+            }
         }
 
         abi::FaultInfo::Injected(who) => {
             sys_log!("Task #{} Fault injected by task #{}", t, who.index());
         }
     }
+
+    if let Some(pc) = detail.pc() {
+        sys_log!("Task #{} faulted at PC 0x{:08x}", t, pc);
+    }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq)]
-pub enum Disposition {
-    Restart,
-    Start,
-    Hold,
-    Fault,
+/// What we know about a task's history since boot.
+#[derive(Copy, Clone, Default)]
+struct TaskHistory {
+    restarts: u32,
+    faults: u32,
+    last_fault: Option<(u64, abi::FaultInfo)>,
 }
 
-#[export_name = "main"]
-fn main() -> ! {
-    sys_log!("viva el jefe");
+/// Returns the current generation of task `index`.
+fn task_generation(index: usize) -> Generation {
+    sys_refresh_task_id(TaskId::for_index_and_gen(index, Generation::default()))
+        .generation()
+}
 
-    let mut disposition: [Disposition; hubris_num_tasks::NUM_TASKS] =
-        [Disposition::Restart; hubris_num_tasks::NUM_TASKS];
-    let mut logged: [bool; hubris_num_tasks::NUM_TASKS] =
-        [false; hubris_num_tasks::NUM_TASKS];
+// We'll have notification 0 wired up to receive information about task
+// faults.
+const FAULT_MASK: u32 = 1 << 0;
 
-    // We'll have notification 0 wired up to receive information about task
-    // faults.
-    let fault_mask = 1;
+// We install a timeout to periodcally check for an external direction of our
+// task disposition (e.g., via Humility).  This timeout should generally be
+// fast for a human but slow for a computer; we pick a value of ~100 ms.  Our
+// timer mask can't conflict with our fault notification, but can otherwise be
+// arbitrary.
+const TIMER_MASK: u32 = 1 << 1;
+const TIMER_INTERVAL: u64 = 100;
 
-    // We install a timeout to periodcally check for an external direction
-    // of our task disposition (e.g., via Humility).  This timeout should
-    // generally be fast for a human but slow for a computer; we pick a
-    // value of ~100 ms.  Our timer mask can't conflict with our fault
-    // notification, but can otherwise be arbitrary.
-    const TIMER_MASK: u32 = 1 << 1;
-    const TIMER_INTERVAL: u64 = 100;
-    let mut deadline = TIMER_INTERVAL;
+struct ServerImpl {
+    disposition: [Disposition; hubris_num_tasks::NUM_TASKS],
+    logged: [bool; hubris_num_tasks::NUM_TASKS],
+    history: [TaskHistory; hubris_num_tasks::NUM_TASKS],
+    restarts: restart::Restarts,
+    starts: start::Starts,
+    faults: faultlog::FaultLog,
+    watchdog: watchdog::Watchdog,
+    /// Time of our next periodic wakeup.
+    deadline: u64,
+    /// Number of periodic wakeups until we next refresh stack usage.
+    stack_refresh: u32,
+}
 
-    sys_set_timer(Some(deadline), TIMER_MASK);
+impl ServerImpl {
+    /// Handles a notification from the kernel, with bits `bits`.
+    fn handle_notification(&mut self, bits: u32) {
+        // Check to see if we have any external requests
+        let changed = external::check(&mut self.disposition);
 
-    external::set_ready();
+        // Our timer serves both our periodic work and any delayed restarts,
+        // so it may have gone off for either.
+        let now = sys_get_timer().now;
+        let mut restart_due = false;
+        if bits & TIMER_MASK != 0 {
+            // If it's time for our periodic work, this is our chance to kick
+            // the watchdog.
+            if now >= self.deadline {
+                self.watchdog.tick(now, &self.disposition);
+                self.deadline += TIMER_INTERVAL;
 
-    loop {
-        let msginfo = sys_recv_open(&mut [], fault_mask | TIMER_MASK);
+                // Less often, we also check on everyone's stack and CPU
+                // usage.
+                if self.stack_refresh == 0 {
+                    stacks::refresh();
+                    cpu::refresh();
+                    self.stack_refresh = stacks::REFRESH_TICKS;
+                }
+                self.stack_refresh -= 1;
+            }
+            restart_due = self.restarts.next_due().map_or(false, |t| now >= t);
+        }
 
-        if msginfo.sender == TaskId::KERNEL {
-            // Check to see if we have any external requests
-            let changed = external::check(&mut disposition);
+        // Until every task is started, the kernel also posts our fault
+        // notification whenever a task becomes ready, which is our chance to
+        // start the ones whose dependencies are.
+        if (bits & FAULT_MASK) != 0 && self.starts.pending() {
+            let watchdog = &mut self.watchdog;
+            self.starts.check(|i| watchdog.started(i, now));
+        }
 
-            // If our timer went off, we need to reestablish it
-            if msginfo.operation & TIMER_MASK != 0 {
-                deadline += TIMER_INTERVAL;
-                sys_set_timer(Some(deadline), TIMER_MASK);
-            }
+        // If our disposition has changed, if we have been notified of a
+        // faulting task, or if it's time for a delayed restart, we need to
+        // iterate over all of our tasks.
+        if changed || restart_due || (bits & FAULT_MASK) != 0 {
+            self.scan(now);
+        }
 
-            // If our disposition has changed or if we have been notified of
-            // a faulting task, we need to iterate over all of our tasks.
-            if changed || (msginfo.operation & fault_mask) != 0 {
-                for i in 0..hubris_num_tasks::NUM_TASKS {
-                    match kipc::read_task_status(i) {
-                        abi::TaskState::Faulted { fault, .. } => {
-                            if !logged[i] {
-                                log_fault(i, &fault);
-                                logged[i] = true;
-                            }
-
-                            if disposition[i] == Disposition::Restart {
-                                // Stand it back up
-                                kipc::restart_task(i, true);
-                                logged[i] = false;
-                            }
-                        }
+        // Wake up for whichever comes first: our periodic work, or the next
+        // delayed restart.
+        let wake = self
+            .restarts
+            .next_due()
+            .map_or(self.deadline, |t| t.min(self.deadline));
+        sys_set_timer(Some(wake), TIMER_MASK);
+    }
 
-                        abi::TaskState::Healthy(abi::SchedState::Stopped) => {
-                            if disposition[i] == Disposition::Start {
-                                kipc::restart_task(i, true);
-                            }
-                        }
+    /// Brings every task in line with its disposition.
+    fn scan(&mut self, now: u64) {
+        for i in 0..hubris_num_tasks::NUM_TASKS {
+            match kipc::read_task_status(i) {
+                abi::TaskState::Faulted { fault, .. } => {
+                    if !self.logged[i] {
+                        log_fault(i, &fault, &kipc::read_fault_detail(i));
+                        self.faults.record(i, &fault);
+                        let history = &mut self.history[i];
+                        history.faults = history.faults.wrapping_add(1);
+                        history.last_fault = Some((now, fault));
+                        self.logged[i] = true;
+                    }
+
+                    if self.disposition[i] != Disposition::Restart {
+                        continue;
+                    }
 
-                        abi::TaskState::Healthy(..) => {
-                            if disposition[i] == Disposition::Fault {
-                                kipc::fault_task(i);
-                            }
+                    match self.restarts.check(i, now) {
+                        restart::Action::Wait => (),
+                        restart::Action::Restart => {
+                            // Stand it back up
+                            self.restart(i, now);
                         }
+                        restart::Action::Escalate(e) => {
+                            self.escalate(i, e, now)
+                        }
+                    }
+                }
+
+                abi::TaskState::Healthy(abi::SchedState::Stopped) => {
+                    if self.disposition[i] == Disposition::Start {
+                        self.restart(i, now);
+                    }
+                }
+
+                abi::TaskState::Healthy(..) => {
+                    if self.disposition[i] == Disposition::Fault {
+                        kipc::fault_task(i);
                     }
                 }
             }
+        }
+    }
+
+    fn restart(&mut self, i: usize, now: u64) {
+        kipc::restart_task(i, true);
+        self.watchdog.started(i, now);
+        self.logged[i] = false;
+        let history = &mut self.history[i];
+        history.restarts = history.restarts.wrapping_add(1);
+    }
+
+    /// Handles task `i` having exceeded its restart budget.
+    fn escalate(
+        &mut self,
+        i: usize,
+        escalation: restart::Escalation,
+        now: u64,
+    ) {
+        match escalation {
+            restart::Escalation::Hold => {
+                sys_log!("Task #{} keeps faulting; holding it", i);
+                self.disposition[i] = Disposition::Hold;
+            }
+            restart::Escalation::RestartDependents(dependents) => {
+                sys_log!("Task #{} keeps faulting; restarting dependents", i);
+                self.restart(i, now);
+                for &d in dependents {
+                    self.restart(d, now);
+                    self.restarts.cancel(d);
+                }
+            }
+            restart::Escalation::Reset => {
+                sys_log!("Task #{} keeps faulting; resetting", i);
+                kipc::reset();
+            }
+        }
+    }
+}
+
+impl idl::InOrderJefeImpl for ServerImpl {
+    fn heartbeat(
+        &mut self,
+        msg: &RecvMessage,
+    ) -> Result<(), RequestError<JefeError>> {
+        self.watchdog
+            .checkin(msg.sender.index(), sys_get_timer().now);
+        Ok(())
+    }
+
+    fn read_fault(
+        &mut self,
+        _: &RecvMessage,
+        index: u32,
+    ) -> Result<abi::FaultRecord, RequestError<JefeError>> {
+        Ok(self.faults.get(index).ok_or(JefeError::NoSuchEntry)?)
+    }
+
+    fn clear_faults(
+        &mut self,
+        _: &RecvMessage,
+    ) -> Result<(), RequestError<JefeError>> {
+        self.faults.clear();
+        Ok(())
+    }
+
+    fn get_task_count(
+        &mut self,
+        _: &RecvMessage,
+    ) -> Result<u32, RequestError<JefeError>> {
+        Ok(hubris_num_tasks::NUM_TASKS as u32)
+    }
+
+    fn get_task_status(
+        &mut self,
+        _: &RecvMessage,
+        index: u32,
+    ) -> Result<TaskStatus, RequestError<JefeError>> {
+        let i = index as usize;
+        let history = self.history.get(i).ok_or(JefeError::NoSuchEntry)?;
+
+        let state = match kipc::read_task_status(i) {
+            abi::TaskState::Faulted { .. } => TaskRunState::Faulted,
+            abi::TaskState::Healthy(abi::SchedState::Stopped) => {
+                TaskRunState::Stopped
+            }
+            abi::TaskState::Healthy(abi::SchedState::Runnable) => {
+                TaskRunState::Runnable
+            }
+            abi::TaskState::Healthy(..) => TaskRunState::Blocked,
+        };
+        let (last_fault_time, last_fault) = match history.last_fault {
+            Some((time, fault)) => (time, task_jefe_api::encode_fault(&fault)),
+            None => (0, [0; abi::FAULT_RECORD_INFO_SIZE]),
+        };
+
+        Ok(TaskStatus {
+            last_fault_time,
+            restarts: history.restarts,
+            faults: history.faults,
+            generation: u8::from(task_generation(i)).into(),
+            state: state as u8,
+            disposition: self.disposition[i] as u8,
+            last_fault,
+            stack_used: stacks::read(i),
+        })
+    }
+
+    fn set_disposition_raw(
+        &mut self,
+        msg: &RecvMessage,
+        index: u32,
+        disposition: u32,
+    ) -> Result<(), RequestError<JefeError>> {
+        if !ipc_config::DISPOSITION_CALLERS.contains(&msg.sender.index()) {
+            return Err(JefeError::NotPermitted.into());
+        }
+        let i = index as usize;
+        if i == 0 {
+            return Err(JefeError::IllegalTask.into());
+        }
+        if i >= hubris_num_tasks::NUM_TASKS {
+            return Err(JefeError::NoSuchEntry.into());
+        }
+        self.disposition[i] = Disposition::from_u32(disposition)
+            .ok_or(JefeError::BadDisposition)?;
+
+        // As for a change made by a debugger, act on this right away.
+        self.scan(sys_get_timer().now);
+        Ok(())
+    }
+}
+
+/// Hands a message from another task to our Idol server. We can't simply
+/// use `idol_runtime::dispatch`, as we also need to receive notifications.
+fn dispatch(server: &mut ServerImpl, incoming: &[u8], rm: &RecvMessage) {
+    use idol_runtime::Server;
+
+    let op = match idl::JefeOperation::from_u32(rm.operation) {
+        Some(op) => op,
+        None => {
+            // ...huh. A task has sent a message to us that we don't
+            // understand. That seems wrong, but it's still owed a reply.
+            sys_log!("Unexpected message from {}", rm.sender.0);
+            sys_reply(rm.sender, JefeError::BadMessage.into(), &[]);
+            return;
+        }
+    };
+
+    let mut server = (core::marker::PhantomData, server);
+    match server.handle(op, incoming, rm) {
+        Ok(()) => (),
+        Err(RequestError::Runtime(code)) => {
+            sys_reply(rm.sender, u32::from(code), &[]);
+        }
+        Err(RequestError::Fail(_)) => {
+            sys_log!("Bad message from {}", rm.sender.0);
+            sys_reply(rm.sender, JefeError::BadMessage.into(), &[]);
+        }
+    }
+}
+
+#[export_name = "main"]
+fn main() -> ! {
+    sys_log!("viva el jefe");
+
+    let deadline = TIMER_INTERVAL;
+    sys_set_timer(Some(deadline), TIMER_MASK);
+
+    let mut server = ServerImpl {
+        disposition: [Disposition::Restart; hubris_num_tasks::NUM_TASKS],
+        logged: [false; hubris_num_tasks::NUM_TASKS],
+        history: [TaskHistory::default(); hubris_num_tasks::NUM_TASKS],
+        restarts: restart::Restarts::new(),
+        starts: start::Starts::new(),
+        faults: faultlog::FaultLog::start(),
+        watchdog: watchdog::Watchdog::start(sys_get_timer().now),
+        deadline,
+        stack_refresh: 0,
+    };
+
+    external::set_ready();
+
+    let mut buffer = [0; idl::INCOMING_SIZE];
+    loop {
+        let msginfo = sys_recv_open(&mut buffer, FAULT_MASK | TIMER_MASK);
+
+        if msginfo.sender == TaskId::KERNEL {
+            server.handle_notification(msginfo.operation);
         } else {
-            // ...huh. A task has sent a message to us. That seems wrong.
-            sys_log!("Unexpected message from {}", msginfo.sender.0);
+            let len = msginfo.message_len.min(buffer.len());
+            dispatch(&mut server, &buffer[..len], &msginfo);
         }
     }
 }
+
+mod ipc_config {
+    include!(concat!(env!("OUT_DIR"), "/ipc_config.rs"));
+}
+
+mod idl {
+    use abi::FaultRecord;
+    use task_jefe_api::{JefeError, TaskStatus};
+
+    include!(concat!(env!("OUT_DIR"), "/server_stub.rs"));
+}
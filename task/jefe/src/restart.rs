@@ -0,0 +1,172 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Restart policy for Jefe
+//!
+//! Restarting a faulted task immediately, every time, means that a task that
+//! faults on boot spins at full speed, starving every task of lower priority
+//! (including, e.g., the one running HIF that might be used to diagnose it).
+//! So, instead, we back off: the first restart in a row happens immediately,
+//! and each one after that waits twice as long as the last, up to a limit. A
+//! task that runs for a while without faulting starts over with a clean
+//! slate.
+//!
+//! Optionally, once a task has been restarted too many times in a row, we
+//! escalate: we can hold the task (leaving it faulted for a debugger, as for
+//! `Disposition::Hold`), restart a set of tasks that depend on it along with
+//! it, or give up and reset the system.
+//!
+//! All of this is configured in `app.toml`, with a default policy for all
+//! tasks and overrides for particular ones:
+//!
+//! ```toml
+//! [tasks.jefe.config.restart.default]
+//! initial-backoff-ms = 1
+//! max-backoff-ms = 1000
+//! window-ms = 10000
+//!
+//! [tasks.jefe.config.restart.task.spd]
+//! max-restarts = 5
+//! escalation = "restart-dependents"  # or "hold", or "reset"
+//! dependents = ["thermal"]
+//! ```
+
+use ringbuf::*;
+
+/// What to do once a task has exceeded its restart budget.
+// Which of these get constructed depends on the application's configuration.
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Escalation {
+    /// Leave the task faulted.
+    Hold,
+    /// Restart the task and all of these tasks, and start counting afresh.
+    RestartDependents(&'static [usize]),
+    /// Reset the system.
+    Reset,
+}
+
+pub struct RestartPolicy {
+    initial_backoff_ms: u64,
+    max_backoff_ms: u64,
+    max_restarts: Option<u32>,
+    window_ms: u64,
+    escalation: Escalation,
+}
+
+mod config {
+    use super::{Escalation, RestartPolicy};
+
+    include!(concat!(env!("OUT_DIR"), "/restart_config.rs"));
+}
+
+/// The outcome of checking on a faulted task.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Action {
+    /// Restart the task now.
+    Restart,
+    /// Leave the task alone for now; its restart is scheduled for later.
+    Wait,
+    /// The task has exceeded its budget.
+    Escalate(Escalation),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Trace {
+    None,
+    Backoff { task: u16, delay: u64 },
+    Escalate { task: u16, restarts: u32 },
+}
+
+ringbuf!(Trace, 8, Trace::None);
+
+#[derive(Copy, Clone, Default)]
+struct TaskRestarts {
+    /// Number of restarts in a row.
+    count: u32,
+    /// Time of the most recent fault.
+    last_fault: Option<u64>,
+    /// Delay to apply before the next restart.
+    backoff: u64,
+    /// Time at which a delayed restart is due, if one is.
+    pending: Option<u64>,
+}
+
+pub struct Restarts {
+    tasks: [TaskRestarts; hubris_num_tasks::NUM_TASKS],
+}
+
+impl Restarts {
+    pub fn new() -> Self {
+        Self {
+            tasks: [TaskRestarts::default(); hubris_num_tasks::NUM_TASKS],
+        }
+    }
+
+    /// Decides what to do about task `index`, which is faulted and has the
+    /// `Restart` disposition. This is called each time we look at the task,
+    /// but only the first call after a fault counts it as a fault.
+    pub fn check(&mut self, index: usize, now: u64) -> Action {
+        let policy = &config::RESTART_POLICY[index];
+        let t = &mut self.tasks[index];
+
+        if let Some(due) = t.pending {
+            if now < due {
+                return Action::Wait;
+            }
+            t.pending = None;
+            return Action::Restart;
+        }
+
+        if let Some(last) = t.last_fault {
+            if now - last > policy.window_ms {
+                t.count = 0;
+                t.backoff = 0;
+            }
+        }
+        t.last_fault = Some(now);
+        t.count += 1;
+
+        if let Some(max) = policy.max_restarts {
+            if t.count > max {
+                ringbuf_entry!(Trace::Escalate {
+                    task: index as u16,
+                    restarts: t.count - 1,
+                });
+                t.count = 0;
+                t.backoff = 0;
+                return Action::Escalate(policy.escalation);
+            }
+        }
+
+        let delay = t.backoff;
+        t.backoff = if delay == 0 {
+            policy.initial_backoff_ms
+        } else {
+            (delay * 2).min(policy.max_backoff_ms)
+        };
+
+        if delay == 0 {
+            Action::Restart
+        } else {
+            ringbuf_entry!(Trace::Backoff {
+                task: index as u16,
+                delay
+            });
+            t.pending = Some(now + delay);
+            Action::Wait
+        }
+    }
+
+    /// Forgets any delayed restart of task `index`, e.g. because it's been
+    /// restarted by other means.
+    pub fn cancel(&mut self, index: usize) {
+        self.tasks[index].pending = None;
+    }
+
+    /// Returns the time at which the next delayed restart is due, if any.
+    pub fn next_due(&self) -> Option<u64> {
+        self.tasks.iter().filter_map(|t| t.pending).min()
+    }
+}
@@ -0,0 +1,40 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Per-task sample tables for debuggers
+//!
+//! Jefe periodically asks the kernel about every task, and keeps the answers
+//! in arrays indexed by task, each at a symbol of its own, where a debugger can
+//! find them (see the `stacks` and `cpu` modules). A `TaskSamples` is laid out
+//! exactly like the array it wraps, so a dump of the symbol is just the array.
+
+use core::cell::UnsafeCell;
+use hubris_num_tasks::NUM_TASKS;
+
+#[repr(transparent)]
+pub struct TaskSamples<T>(UnsafeCell<[T; NUM_TASKS]>);
+
+// Safety: Jefe is single-threaded, so only one `refresh` can ever be running,
+// and nothing else in Jefe reads the samples.
+unsafe impl<T> Sync for TaskSamples<T> {}
+
+impl<T> TaskSamples<T> {
+    pub const fn new(initial: [T; NUM_TASKS]) -> Self {
+        Self(UnsafeCell::new(initial))
+    }
+}
+
+impl<T: Copy> TaskSamples<T> {
+    /// Replaces each task's sample with `read` of its index.
+    pub fn refresh(&self, read: impl Fn(usize) -> T) {
+        for i in 0..NUM_TASKS {
+            let sample = read(i);
+            // Safety: see the `Sync` impl. A debugger may read the samples at
+            // any time, hence the volatile write.
+            unsafe {
+                core::ptr::write_volatile(&mut (*self.0.get())[i], sample);
+            }
+        }
+    }
+}
@@ -0,0 +1,39 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Stack usage tracking for Jefe
+//!
+//! The kernel paints each task's stack when it starts the task, and can tell
+//! us how far down the paint has been disturbed since. Every so often, we ask
+//! it about every task and keep the answers in `STACK_USAGE`, an array of
+//! peak stack usage in bytes, indexed by task, where a debugger can find it;
+//! `cargo xtask stacks` decodes a dump of it, comparing each task's usage with
+//! its `stacksize` in `app.toml`.
+//!
+//! Because the paint is reapplied when a task restarts, these are peaks since
+//! each task last started, not since boot.
+
+use crate::samples::TaskSamples;
+use userlib::*;
+
+/// Recorded for tasks whose stack usage the kernel can't tell us.
+pub const UNKNOWN: u32 = u32::MAX;
+
+/// How many of Jefe's periodic timer ticks go by between refreshes.
+pub const REFRESH_TICKS: u32 = 10;
+
+#[used]
+#[no_mangle]
+static STACK_USAGE: TaskSamples<u32> =
+    TaskSamples::new([UNKNOWN; hubris_num_tasks::NUM_TASKS]);
+
+/// Asks the kernel for the stack usage of task `index`.
+pub fn read(index: usize) -> u32 {
+    kipc::read_stack_usage(index).unwrap_or(UNKNOWN)
+}
+
+/// Brings `STACK_USAGE` up to date.
+pub fn refresh() {
+    STACK_USAGE.refresh(read);
+}
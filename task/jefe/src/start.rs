@@ -0,0 +1,94 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Start ordering for Jefe
+//!
+//! Tasks that are started at boot all start at once, so a task that depends on
+//! a server may well send to it before it's ready. A task can instead be told,
+//! in `app.toml`, to wait for other tasks:
+//!
+//! ```toml
+//! [tasks.gimlet_seq]
+//! start = true
+//! start-after = ["gpio_driver", "spi2_driver"]
+//! ```
+//!
+//! `cargo xtask dist` leaves such tasks out of the ones that the kernel starts
+//! at boot, and checks that no tasks are waiting for each other. We start each
+//! one once every task it's waiting for has made an open `RECV`, which we take
+//! to mean that it's ready to serve. (A closed `RECV` usually means it's still
+//! setting up, waiting on some particular task.) The kernel keeps track of
+//! that for us, and posts our fault notification the first time each task does
+//! it, so we only need to look when we're notified.
+//!
+//! Tasks are started without being restarted, so anything already sent to
+//! them is delivered once they get going.
+//!
+//! This only applies at boot: a task that's restarted later is restarted
+//! straight away, like any other.
+
+use ringbuf::*;
+use userlib::*;
+
+mod config {
+    include!(concat!(env!("OUT_DIR"), "/start_config.rs"));
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Trace {
+    None,
+    Started(u16),
+}
+
+ringbuf!(Trace, 8, Trace::None);
+
+pub struct Starts {
+    /// Whether each task has been seen to be ready.
+    ready: [bool; hubris_num_tasks::NUM_TASKS],
+    /// Whether each task is still waiting to be started.
+    waiting: [bool; hubris_num_tasks::NUM_TASKS],
+}
+
+impl Starts {
+    pub fn new() -> Self {
+        let mut waiting = [false; hubris_num_tasks::NUM_TASKS];
+        for (w, after) in waiting.iter_mut().zip(&config::START_AFTER) {
+            *w = !after.is_empty();
+        }
+        Self {
+            ready: [false; hubris_num_tasks::NUM_TASKS],
+            waiting,
+        }
+    }
+
+    /// Returns whether any tasks are still waiting to be started.
+    pub fn pending(&self) -> bool {
+        self.waiting.iter().any(|&w| w)
+    }
+
+    /// Starts every waiting task whose dependencies are all ready, calling
+    /// `started` with the index of each one.
+    pub fn check(&mut self, mut started: impl FnMut(usize)) {
+        for i in 0..hubris_num_tasks::NUM_TASKS {
+            if !self.waiting[i] {
+                continue;
+            }
+
+            let mut all_ready = true;
+            for &dep in config::START_AFTER[i] {
+                if !self.ready[dep] {
+                    self.ready[dep] = kipc::read_task_ready(dep);
+                    all_ready &= self.ready[dep];
+                }
+            }
+
+            if all_ready {
+                ringbuf_entry!(Trace::Started(i as u16));
+                kipc::start_task(i);
+                started(i);
+                self.waiting[i] = false;
+            }
+        }
+    }
+}
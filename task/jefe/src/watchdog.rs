@@ -0,0 +1,407 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Hardware watchdog management for Jefe
+//!
+//! Jefe can own the chip's independent watchdog (the IWDG on STM32, the WWDT
+//! on LPC55), which is selected by feature. It's left off unless the
+//! application asks for it, by listing its critical tasks in Jefe's
+//! configuration:
+//!
+//! ```toml
+//! [tasks.jefe]
+//! features = ["h753"]
+//! uses = ["iwdg", "rcc"]
+//!
+//! [tasks.jefe.config.watchdog]
+//! critical = ["i2c_driver", "thermal"]
+//! heartbeat-ms = 1000
+//! timeout-ms = 2000
+//! ```
+//!
+//! (On the LPC55, Jefe uses `wwdt`, `syscon` and `pmc` instead.)
+//!
+//! Critical tasks are expected to check in (with `userlib::hl::heartbeat`) at
+//! least every `heartbeat-ms`, starting from when Jefe starts or restarts
+//! them. Jefe kicks the watchdog from its periodic timer only as long as they
+//! all have; once one of them misses a check-in, Jefe stops kicking for good,
+//! and the watchdog resets the system `timeout-ms` later. Tasks that aren't
+//! running -- because they haven't been started yet, or have faulted and are
+//! waiting to be restarted -- aren't expected to check in, and nor are tasks
+//! that have been held (or are being faulted) by a debugger.
+//!
+//! Before giving up, Jefe records which task missed its check-in in a part of
+//! its RAM that isn't initialized at boot, and reports it on the way back up,
+//! if the chip's reset flags agree that it was the watchdog that reset it.
+//! With a chip feature, Jefe reads and clears those flags at startup whether
+//! or not the watchdog is on, so that they only ever describe the most recent
+//! reset.
+//!
+//! Note that the watchdog keeps counting while the processor is halted, so
+//! stopping at a breakpoint for longer than `timeout-ms` will reset the
+//! system.
+
+use crate::Disposition;
+use core::mem::MaybeUninit;
+
+use ringbuf::*;
+use userlib::*;
+
+mod config {
+    include!(concat!(env!("OUT_DIR"), "/watchdog_config.rs"));
+}
+
+/// What the chip's reset flags say caused the most recent reset.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ResetReason {
+    PowerOn,
+    Pin,
+    Brownout,
+    Software,
+    Watchdog,
+    /// None of the above; this holds the raw flags.
+    Other(u32),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Trace {
+    None,
+    Reset(ResetReason),
+    Started,
+    PreviousReset { task: u16, missed_at: u64 },
+    Missed { task: u16, now: u64 },
+}
+
+ringbuf!(Trace, 4, Trace::None);
+
+/// Record of why Jefe let the watchdog reset the system, which survives the
+/// reset.
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct ResetRecord {
+    /// `RESET_RECORD_MAGIC` if this record is valid.
+    magic: u32,
+    /// Index of the critical task that missed its check-in.
+    task: u32,
+    /// Time at which we noticed.
+    missed_at: u64,
+}
+
+const RESET_RECORD_MAGIC: u32 = 0x1ef3_d09e;
+
+#[link_section = ".uninit"]
+static mut RESET_RECORD: MaybeUninit<ResetRecord> = MaybeUninit::uninit();
+
+pub struct Watchdog {
+    /// Time at which each task last checked in.
+    last_checkin: [u64; hubris_num_tasks::NUM_TASKS],
+    /// Set once we've stopped kicking the watchdog.
+    expired: bool,
+}
+
+impl Watchdog {
+    /// Reports why the chip last reset, and on any watchdog reset that we
+    /// caused, and starts the watchdog, if it's been asked for. `now` is the
+    /// time at which the tasks that start at boot were started.
+    pub fn start(now: u64) -> Self {
+        let reason = hw::take_reset_reason();
+        if let Some(reason) = reason {
+            sys_log!("Reset reason: {:?}", reason);
+            ringbuf_entry!(Trace::Reset(reason));
+        }
+
+        // Safety: we are the only code that touches `RESET_RECORD`, and
+        // every bit pattern is a valid `ResetRecord`, even before it's been
+        // written.
+        let record = unsafe { core::ptr::read_volatile(RESET_RECORD.as_ptr()) };
+        // If we wrote a record but something else reset the chip before the
+        // watchdog got to it, the record doesn't explain anything. Without
+        // hardware to ask, we take the record's word for it.
+        let by_watchdog = reason.map_or(true, |r| r == ResetReason::Watchdog);
+        if record.magic == RESET_RECORD_MAGIC && by_watchdog {
+            sys_log!(
+                "Watchdog reset: task #{} missed its check-in at {}",
+                record.task,
+                record.missed_at
+            );
+            ringbuf_entry!(Trace::PreviousReset {
+                task: record.task as u16,
+                missed_at: record.missed_at,
+            });
+        }
+        if record.magic == RESET_RECORD_MAGIC {
+            unsafe {
+                core::ptr::write_volatile(
+                    &mut (*RESET_RECORD.as_mut_ptr()).magic,
+                    0,
+                );
+            }
+        }
+
+        if config::ENABLED {
+            hw::start(config::TIMEOUT_MS);
+            ringbuf_entry!(Trace::Started);
+        }
+
+        Self {
+            last_checkin: [now; hubris_num_tasks::NUM_TASKS],
+            expired: false,
+        }
+    }
+
+    /// Records a heartbeat from task `index`.
+    pub fn checkin(&mut self, index: usize, now: u64) {
+        if let Some(t) = self.last_checkin.get_mut(index) {
+            *t = now;
+        }
+    }
+
+    /// Notes that Jefe has just started (or restarted) task `index`, which
+    /// gives it until `HEARTBEAT_MS` from now to check in.
+    pub fn started(&mut self, index: usize, now: u64) {
+        self.checkin(index, now);
+    }
+
+    /// Kicks the watchdog, if all critical tasks have checked in recently.
+    /// This should be called periodically, more often than the watchdog
+    /// timeout.
+    pub fn tick(&mut self, now: u64, disposition: &[Disposition]) {
+        if !config::ENABLED || self.expired {
+            return;
+        }
+
+        for &i in config::CRITICAL_TASKS {
+            let running = matches!(
+                kipc::read_task_status(i),
+                abi::TaskState::Healthy(s) if s != abi::SchedState::Stopped
+            );
+            let excused = !running
+                || disposition[i] == Disposition::Hold
+                || disposition[i] == Disposition::Fault;
+            if excused {
+                // Its clock starts over once it's back.
+                self.last_checkin[i] = now;
+                continue;
+            }
+            if now.saturating_sub(self.last_checkin[i]) <= config::HEARTBEAT_MS
+            {
+                continue;
+            }
+
+            sys_log!("Task #{} missed its watchdog check-in", i);
+            ringbuf_entry!(Trace::Missed {
+                task: i as u16,
+                now
+            });
+            unsafe {
+                core::ptr::write_volatile(
+                    RESET_RECORD.as_mut_ptr(),
+                    ResetRecord {
+                        magic: RESET_RECORD_MAGIC,
+                        task: i as u32,
+                        missed_at: now,
+                    },
+                );
+            }
+            self.expired = true;
+            return;
+        }
+
+        hw::kick();
+    }
+}
+
+#[cfg(any(
+    feature = "h743",
+    feature = "h753",
+    feature = "h7b3",
+    feature = "f4"
+))]
+mod hw {
+    #[cfg(feature = "f4")]
+    use stm32f4::stm32f407 as device;
+    #[cfg(feature = "h743")]
+    use stm32h7::stm32h743 as device;
+    #[cfg(feature = "h753")]
+    use stm32h7::stm32h753 as device;
+    #[cfg(feature = "h7b3")]
+    use stm32h7::stm32h7b3 as device;
+
+    use super::ResetReason;
+
+    /// Nominal frequency of the LSI oscillator that clocks the IWDG.
+    const LSI_HZ: u32 = 32_000;
+
+    /// Reset flags in RCC_CSR, on the F4.
+    #[cfg(feature = "f4")]
+    mod flags {
+        pub const RMVF: u32 = 1 << 24;
+        pub const BORRSTF: u32 = 1 << 25;
+        pub const PINRSTF: u32 = 1 << 26;
+        pub const PORRSTF: u32 = 1 << 27;
+        pub const SFTRSTF: u32 = 1 << 28;
+        pub const IWDGRSTF: u32 = 1 << 29;
+        pub const WWDGRSTF: u32 = 1 << 30;
+    }
+
+    /// Reset flags in RCC_RSR, which is laid out the same on all our H7s.
+    #[cfg(not(feature = "f4"))]
+    mod flags {
+        pub const RMVF: u32 = 1 << 16;
+        pub const BORRSTF: u32 = 1 << 21;
+        pub const PINRSTF: u32 = 1 << 22;
+        pub const PORRSTF: u32 = 1 << 23;
+        pub const SFTRSTF: u32 = 1 << 24;
+        pub const IWDGRSTF: u32 = 1 << 26;
+        pub const WWDGRSTF: u32 = 1 << 28;
+    }
+
+    /// Reads the reset flags, and then clears them, so that they're fresh for
+    /// the next reset.
+    pub fn take_reset_reason() -> Option<ResetReason> {
+        use flags::*;
+
+        let rcc = unsafe { &*device::RCC::ptr() };
+        #[cfg(feature = "f4")]
+        let reg = &rcc.csr;
+        #[cfg(not(feature = "f4"))]
+        let reg = &rcc.rsr;
+        let flags = reg.read().bits();
+        reg.modify(|r, w| unsafe { w.bits(r.bits() | RMVF) });
+
+        // A power-on reset also sets the brownout and pin flags, so it has to
+        // be checked for before them.
+        Some(if flags & (IWDGRSTF | WWDGRSTF) != 0 {
+            ResetReason::Watchdog
+        } else if flags & SFTRSTF != 0 {
+            ResetReason::Software
+        } else if flags & PORRSTF != 0 {
+            ResetReason::PowerOn
+        } else if flags & BORRSTF != 0 {
+            ResetReason::Brownout
+        } else if flags & PINRSTF != 0 {
+            ResetReason::Pin
+        } else {
+            ResetReason::Other(flags)
+        })
+    }
+
+    pub fn start(timeout_ms: u32) {
+        let iwdg = unsafe { &*device::IWDG::ptr() };
+
+        // Pick the smallest prescaler (from /4 to /256) that lets the timeout
+        // fit in the 12-bit reload register.
+        let mut prescale = 0;
+        let mut reload = timeout_ms * (LSI_HZ / 1000) / 4;
+        while reload > 0xfff && prescale < 6 {
+            prescale += 1;
+            reload /= 2;
+        }
+        let reload = reload.min(0xfff);
+
+        // Start the watchdog, which also starts the LSI, and then unlock the
+        // prescaler and reload registers to configure it.
+        iwdg.kr.write(|w| unsafe { w.bits(0xcccc) });
+        iwdg.kr.write(|w| unsafe { w.bits(0x5555) });
+        iwdg.pr.write(|w| unsafe { w.bits(prescale) });
+        iwdg.rlr.write(|w| unsafe { w.bits(reload) });
+        while iwdg.sr.read().bits() != 0 {
+            // Wait for the new values to reach the watchdog's clock domain.
+        }
+        kick();
+    }
+
+    pub fn kick() {
+        let iwdg = unsafe { &*device::IWDG::ptr() };
+        iwdg.kr.write(|w| unsafe { w.bits(0xaaaa) });
+    }
+}
+
+#[cfg(feature = "lpc55")]
+mod hw {
+    use lpc55_pac as device;
+
+    use super::ResetReason;
+
+    /// Frequency at which the WWDT counts: the 1 MHz FRO, undivided, with the
+    /// watchdog's fixed divide-by-4 prescaler.
+    const WDT_HZ: u32 = 250_000;
+
+    /// Reads the reset cause bits that the PMC keeps in AOREG1, and then
+    /// clears them, so that they're fresh for the next reset.
+    pub fn take_reset_reason() -> Option<ResetReason> {
+        const POR: u32 = 1 << 4;
+        const PADRESET: u32 = 1 << 5;
+        const BODRESET: u32 = 1 << 6;
+        const SYSTEMRESET: u32 = 1 << 7;
+        const WDTRESET: u32 = 1 << 8;
+        const SWRRESET: u32 = 1 << 9;
+        /// All of the reset cause bits, including the deep power-down and
+        /// code watchdog ones we don't pick out.
+        const ALL: u32 = 0x3ff << 4;
+
+        let pmc = unsafe { &*device::PMC::ptr() };
+        let flags = pmc.aoreg1.read().bits() & ALL;
+        pmc.aoreg1.modify(|r, w| unsafe { w.bits(r.bits() & !ALL) });
+
+        Some(if flags & WDTRESET != 0 {
+            ResetReason::Watchdog
+        } else if flags & (SYSTEMRESET | SWRRESET) != 0 {
+            ResetReason::Software
+        } else if flags & POR != 0 {
+            ResetReason::PowerOn
+        } else if flags & BODRESET != 0 {
+            ResetReason::Brownout
+        } else if flags & PADRESET != 0 {
+            ResetReason::Pin
+        } else {
+            ResetReason::Other(flags)
+        })
+    }
+
+    pub fn start(timeout_ms: u32) {
+        let syscon = unsafe { &*device::SYSCON::ptr() };
+        let wwdt = unsafe { &*device::WWDT::ptr() };
+
+        // We start before any other task has run, so we can safely turn on
+        // the watchdog's bus clock here without involving the SYSCON driver.
+        syscon
+            .ahbclkctrl0
+            .modify(|r, w| unsafe { w.bits(r.bits() | 1 << 22) });
+        // Release the watchdog clock divider from halt, dividing by 1.
+        syscon.wdtclkdiv.write(|w| unsafe { w.bits(0) });
+
+        let count = (timeout_ms * (WDT_HZ / 1000)).max(0xff).min(0xff_ffff);
+        wwdt.tc.write(|w| unsafe { w.bits(count) });
+        // Enable the watchdog, with a timeout causing a reset. This takes
+        // effect on the first feed.
+        wwdt.mod_.write(|w| unsafe { w.bits(0b11) });
+        kick();
+    }
+
+    pub fn kick() {
+        let wwdt = unsafe { &*device::WWDT::ptr() };
+        wwdt.feed.write(|w| unsafe { w.bits(0xaa) });
+        wwdt.feed.write(|w| unsafe { w.bits(0x55) });
+    }
+}
+
+#[cfg(not(any(
+    feature = "h743",
+    feature = "h753",
+    feature = "h7b3",
+    feature = "f4",
+    feature = "lpc55"
+)))]
+mod hw {
+    use super::ResetReason;
+
+    pub fn take_reset_reason() -> Option<ResetReason> {
+        None
+    }
+
+    pub fn start(_timeout_ms: u32) {}
+
+    pub fn kick() {}
+}
@@ -4,7 +4,6 @@
 
 #![no_std]
 #![no_main]
-#![feature(asm)]
 
 use userlib::*;
 
@@ -20,24 +19,13 @@ fn nullread() {
     }
 }
 
-#[inline(never)]
-fn divzero() {
-    unsafe {
-        // Divide by 0
-        let p: u32 = 123;
-        let q: u32 = 0;
-        let _res: u32;
-        asm!("udiv r2, r1, r0", in("r1") p, in("r0") q, out("r2") _res);
-    }
-}
-
 #[export_name = "main"]
 fn main() -> ! {
     let peer = PEER.get_task_id();
     const PING_OP: u16 = 1;
     const FAULT_EVERY: u32 = 100;
 
-    let faultme = [nullread, divzero];
+    let faultme = [nullread, divide_by_zero];
 
     let mut response = [0; 16];
     loop {
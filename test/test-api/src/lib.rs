@@ -33,8 +33,25 @@ pub enum AssistOp {
     RefreshTaskIdOffByOne = 21,
     RefreshTaskIdOffByMany = 22,
     ReadNotifications = 23,
+    ReplyLater = 24,
+    SendToTask = 25,
+    PostToTask = 26,
+    TakeLock = 27,
+    ReleaseLock = 28,
+    ReadShared = 29,
+    WriteShared = 30,
+    ReadIrqStats = 31,
+    Spin = 32,
+    LastReplyTime = 33,
 }
 
+/// Interrupt that every test image routes to the assistant. Nothing else raises
+/// it, so it only fires when the suite has the runner raise it.
+pub const ASSIST_IRQ: u32 = 30;
+
+/// Notification the assistant gets for `ASSIST_IRQ`.
+pub const ASSIST_IRQ_NOTIFICATION: u32 = 1 << 28;
+
 /// Operations that are performed by the test-suite
 #[derive(FromPrimitive)]
 pub enum SuiteOp {
@@ -52,6 +69,12 @@ pub enum RunnerOp {
     /// Reads out, and clears, the accumulated set of notifications we've
     /// received (`() -> u32`).
     ReadAndClearNotes = 0,
+    /// Starts a stopped task, which only the supervisor may do (`u32 -> ()`).
+    StartTask = 1,
+    /// Raises the interrupts routed to a task with the given notifications,
+    /// which only the supervisor may do (`[u32; 2] -> ()`: task index and
+    /// notification mask).
+    SoftwareIrq = 2,
     /// Signals that a test is complete, and that the runner is switching back
     /// to passive mode (`() -> ()`).
     TestComplete = 0xFFFF,
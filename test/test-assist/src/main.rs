@@ -8,6 +8,8 @@
 #![no_main]
 #![feature(asm)]
 
+use core::cell::Cell;
+use core::mem::MaybeUninit;
 use hubris_num_tasks::NUM_TASKS;
 use test_api::*;
 use userlib::*;
@@ -112,6 +114,42 @@ fn illinst(_arg: u32) {
     }
 }
 
+/// Sends to the task with index `arg`, which should get us faulted unless
+/// it's the suite: that's the only task we're allowed to talk to.
+fn sendto(arg: u32) {
+    let peer = TaskId::for_index_and_gen(arg as usize, Generation::default());
+    sys_send(peer, 0, &[], &mut [], &[]);
+}
+
+/// Like `sendto`, but posts a notification.
+fn postto(arg: u32) {
+    let peer = TaskId::for_index_and_gen(arg as usize, Generation::default());
+    sys_post(peer, 1);
+}
+
+/// Releases the lock with index `arg`, which we shouldn't be holding.
+fn unlock(arg: u32) {
+    sys_unlock(arg as usize);
+}
+
+/// Asks the kernel about interrupt `arg`, which isn't routed to any task.
+fn readirqstats(arg: u32) {
+    let _ = kipc::read_irq_stats(arg);
+}
+
+/// Word at the start of the `test` shared region, which the suite can write
+/// and we can only read.
+#[link_section = ".shared.test"]
+static mut SHARED: MaybeUninit<u32> = MaybeUninit::uninit();
+
+/// Writes `arg` to the shared region, which we're not allowed to do.
+#[inline(never)]
+fn writeshared(arg: u32) {
+    unsafe {
+        SHARED.as_mut_ptr().write_volatile(arg);
+    }
+}
+
 #[inline(never)]
 fn divzero(_arg: u32) {
     unsafe {
@@ -145,9 +183,13 @@ fn main() -> ! {
     sys_log!("assistant starting");
     let mut buffer = [0; 4];
     let mut last_reply = 0u32;
+    // When SendBack last got its reply, in ticks.
+    let mut last_reply_time = 0u64;
     let mut stored_value = 0;
     let mut borrow_buffer = [0u8; 16];
     let mut posted_bits = 0;
+    // A caller waiting on ReplyLater, and what to reply with.
+    let deferred = Cell::new(None);
 
     let fatalops = [
         (AssistOp::BadMemory, badread as fn(u32)),
@@ -161,17 +203,37 @@ fn main() -> ! {
         (AssistOp::StackOutOfBounds, stackoob),
         (AssistOp::BusError, busfault),
         (AssistOp::IllegalInstruction, illinst),
+        (AssistOp::SendToTask, sendto),
+        (AssistOp::PostToTask, postto),
+        (AssistOp::ReleaseLock, unlock),
+        (AssistOp::WriteShared, writeshared),
+        (AssistOp::ReadIrqStats, readirqstats),
     ];
 
     const ALL_NOTIFICATIONS: u32 = !0;
+    // Used by our timer, for ReplyLater; not posted by any test.
+    const DEFERRED_REPLY_NOTIFICATION: u32 = 1 << 30;
+
+    // Our interrupt only fires when the suite asks for it, and we re-enable it
+    // each time it does.
+    sys_irq_control(ASSIST_IRQ_NOTIFICATION, true);
     loop {
         hl::recv(
             &mut buffer,
             ALL_NOTIFICATIONS,
             &mut posted_bits,
             |posted_bits, notify_bits| {
-                // Just record any notifications so they can be read back out.
-                *posted_bits |= notify_bits;
+                if notify_bits & DEFERRED_REPLY_NOTIFICATION != 0 {
+                    if let Some((caller, value)) = deferred.take() {
+                        sys_reply(caller, 0, u32::as_bytes(&value));
+                    }
+                }
+                if notify_bits & ASSIST_IRQ_NOTIFICATION != 0 {
+                    sys_irq_control(ASSIST_IRQ_NOTIFICATION, true);
+                }
+                // Just record any other notifications, including the
+                // interrupt, so they can be read back out.
+                *posted_bits |= notify_bits & !DEFERRED_REPLY_NOTIFICATION;
             },
             |posted_bits, op, msg| -> Result<(), u32> {
                 // Every incoming message uses the same payload type: it's
@@ -189,7 +251,7 @@ fn main() -> ! {
                         let task_id = caller.task_id();
                         caller.reply(*msg);
                         // ...and then send them a message back, recording any
-                        // reply as last_reply
+                        // reply as last_reply, and when it came
                         sys_send(
                             task_id,
                             42,
@@ -198,10 +260,14 @@ fn main() -> ! {
                             &[],
                         );
                         // Ignore the result.
+                        last_reply_time = sys_get_timer().now;
                     }
                     AssistOp::LastReply => {
                         caller.reply(last_reply);
                     }
+                    AssistOp::LastReplyTime => {
+                        caller.reply(last_reply_time as u32);
+                    }
                     AssistOp::Store => {
                         caller.reply(stored_value);
                         stored_value = *msg;
@@ -273,6 +339,35 @@ fn main() -> ! {
                     AssistOp::ReadNotifications => {
                         caller.reply(core::mem::replace(posted_bits, 0));
                     }
+                    AssistOp::TakeLock => {
+                        // Reply first, since we may have to wait for the
+                        // lock, and then hold on to it until we're restarted.
+                        caller.reply(0);
+                        let _ = sys_lock(*msg as usize);
+                    }
+                    AssistOp::ReadShared => {
+                        caller
+                            .reply(unsafe { SHARED.as_ptr().read_volatile() });
+                    }
+                    AssistOp::Spin => {
+                        // Resume the caller, and then hog the CPU, from
+                        // wherever we are in the priority order, for as many
+                        // ticks as it asks.
+                        caller.reply(0);
+                        let until = sys_get_timer().now + u64::from(*msg);
+                        while sys_get_timer().now < until {
+                            core::hint::spin_loop();
+                        }
+                    }
+                    AssistOp::ReplyLater => {
+                        // Leave the caller hanging for as many ticks as it
+                        // asks, and then echo it.
+                        deferred.set(Some((caller.task_id(), *msg)));
+                        sys_set_timer(
+                            Some(sys_get_timer().now + u64::from(*msg)),
+                            DEFERRED_REPLY_NOTIFICATION,
+                        );
+                    }
                     _ => {
                         // Anything else should be fatal
                         for (which, func) in &fatalops {
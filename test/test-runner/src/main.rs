@@ -61,9 +61,10 @@
 //!
 //! # Output
 //!
-//! Output is produced on ITM stimulus port 8. Output is in a line-oriented
-//! human-readable format modeled after report formats like TAP, but avoiding
-//! some issues.
+//! Output is produced on ITM stimulus port 8 or, with the `mps2-uart` feature,
+//! on UART0 of an MPS2 board (for running under QEMU). Output is in a
+//! line-oriented human-readable format modeled after report formats like TAP,
+//! but avoiding some issues.
 //!
 //! A test report consists of the following lines:
 //!
@@ -78,7 +79,7 @@
 //!     containing newlines) is starting, and any hangs should be blamed on it.
 //!   - `finish STATUS NAME` - indicates that test suite NAME has completed with
 //!     STATUS (which is `ok` or `FAIL`).
-//! - `done STATUS` - signals the end of the test suite. STATUS is `ok` if all
+//! - `done STATUS` - signals the end of the test suite. STATUS is `pass` if all
 //!   tests passed, `FAIL` if any failed.
 
 #![no_std]
@@ -89,7 +90,11 @@ use test_api::*;
 use userlib::*;
 use zerocopy::AsBytes;
 
+#[cfg(feature = "mps2-uart")]
+mod uart;
+
 /// Helper macro for producing output on stimulus port 8.
+#[cfg(not(feature = "mps2-uart"))]
 macro_rules! test_output {
     ($s:expr) => {
         unsafe {
@@ -105,6 +110,17 @@ macro_rules! test_output {
     };
 }
 
+/// Helper macro for producing output on UART0.
+#[cfg(feature = "mps2-uart")]
+macro_rules! test_output {
+    ($($tt:tt)*) => {
+        {
+            use core::fmt::Write;
+            let _ = writeln!(uart::Uart, $($tt)*);
+        }
+    };
+}
+
 /// This runner is written such that the task under test must be task index 1.
 /// (And the runner must be zero.)
 const TEST_TASK: usize = 1;
@@ -167,9 +183,11 @@ fn test_run() {
         // Continue monitoring messages until (1) the test has been reported as
         // complete, or (2) we get notice from the kernel that the testsuite has
         // crashed.
+        // Big enough for the largest message we take, SoftwareIrq's.
+        let mut buffer = [0; 8];
         while state.test_status.is_none() {
             hl::recv(
-                &mut [],
+                &mut buffer,
                 ALL_NOTIFICATIONS,
                 &mut state,
                 |state, bits| {
@@ -192,6 +210,18 @@ fn test_run() {
                             caller.reply(state.received_notes);
                             state.received_notes = 0;
                         }
+                        RunnerOp::StartTask => {
+                            let (&index, caller) =
+                                msg.fixed::<u32, ()>().ok_or(2u32)?;
+                            kipc::start_task(index as usize);
+                            caller.reply(());
+                        }
+                        RunnerOp::SoftwareIrq => {
+                            let (&[index, mask], caller) =
+                                msg.fixed::<[u32; 2], ()>().ok_or(2u32)?;
+                            kipc::software_irq(index as usize, mask);
+                            caller.reply(());
+                        }
                         RunnerOp::TestComplete => {
                             let (_, caller) =
                                 msg.fixed::<(), ()>().ok_or(2u32)?;
@@ -225,6 +255,9 @@ fn test_run() {
 
 #[export_name = "main"]
 fn main() -> ! {
+    #[cfg(feature = "mps2-uart")]
+    uart::init();
+
     loop {
         test_run();
         TEST_RUNS.fetch_add(1, Ordering::SeqCst);
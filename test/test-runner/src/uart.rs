@@ -0,0 +1,50 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Test output over UART0 of an MPS2 board, for use under QEMU, which doesn't
+//! model the ITM.
+//!
+//! This is a minimal polled driver for the CMSDK APB UART. It assumes the
+//! runner has been granted access to the UART's registers (`uses = ["uart0"]`
+//! in the `app.toml`).
+
+use core::fmt;
+use core::ptr;
+
+const UART0_BASE: usize = 0x4000_4000;
+
+const DATA: *mut u32 = UART0_BASE as *mut u32;
+const STATE: *mut u32 = (UART0_BASE + 0x4) as *mut u32;
+const CTRL: *mut u32 = (UART0_BASE + 0x8) as *mut u32;
+const BAUDDIV: *mut u32 = (UART0_BASE + 0x10) as *mut u32;
+
+const STATE_TX_FULL: u32 = 1 << 0;
+const CTRL_TX_ENABLE: u32 = 1 << 0;
+
+/// Enables the transmitter. Must be called before any output is produced.
+pub fn init() {
+    unsafe {
+        // The divisor must be at least 16 for the UART to accept it; QEMU
+        // otherwise ignores it.
+        ptr::write_volatile(BAUDDIV, 16);
+        ptr::write_volatile(CTRL, CTRL_TX_ENABLE);
+    }
+}
+
+/// Zero-sized handle implementing `fmt::Write` on the UART.
+pub struct Uart;
+
+impl fmt::Write for Uart {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &b in s.as_bytes() {
+            unsafe {
+                while ptr::read_volatile(STATE) & STATE_TX_FULL != 0 {
+                    continue;
+                }
+                ptr::write_volatile(DATA, u32::from(b));
+            }
+        }
+        Ok(())
+    }
+}
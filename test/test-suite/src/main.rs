@@ -19,7 +19,8 @@
 #![no_main]
 #![feature(asm)]
 
-use hubris_num_tasks::NUM_TASKS;
+use core::mem::MaybeUninit;
+use hubris_num_tasks::{Lock, NUM_TASKS};
 use test_api::*;
 use userlib::*;
 use zerocopy::AsBytes;
@@ -38,7 +39,15 @@ macro_rules! test_cases {
 // Actual list of functions with their names.
 test_cases! {
     test_send,
+    test_send_async,
+    test_send_async_cancel,
+    test_send_async_queue_full,
+    test_send_async_peer_busy,
+    test_send_async_peer_restart,
+    test_send_timeout,
+    test_send_timeout_two_peers,
     test_recv_reply,
+    test_priority_inheritance,
     test_floating_point_lowregs,
     test_floating_point_highregs,
     test_floating_point_fault,
@@ -52,6 +61,11 @@ test_cases! {
     test_fault_buserror,
     test_fault_illinst,
     test_fault_divzero,
+    test_fault_send_not_permitted,
+    test_fault_post_not_permitted,
+    test_fault_unlock_not_held,
+    test_fault_badirqstats,
+    test_irq_stats,
     test_fault_maxstatus,
     test_fault_badstatus,
     test_fault_maxrestart,
@@ -63,6 +77,8 @@ test_cases! {
     test_panic,
     test_restart,
     test_restart_taskgen,
+    test_start_task,
+    test_task_ready,
     test_borrow_info,
     test_borrow_read,
     test_borrow_write,
@@ -71,13 +87,19 @@ test_cases! {
     test_timer_advance,
     test_timer_notify,
     test_timer_notify_past,
+    test_timer_notify_micros,
     test_task_status,
+    test_stack_usage,
+    test_cpu_usage,
     test_task_fault_injection,
     test_refresh_task_id_basic,
     test_refresh_task_id_off_by_one,
     test_refresh_task_id_off_by_many,
     test_lpc55_flash_write,
     test_post,
+    test_lock,
+    test_shared_region,
+    test_fault_shared_readonly,
 }
 
 #[cfg(feature = "lpc55")]
@@ -122,6 +144,252 @@ fn test_send() {
     assert_eq!(response, !0xDEADBEEF);
 }
 
+/// Tests that an asynchronous send reaches the assistant, and that its reply
+/// is delivered into our buffer along with a notification.
+fn test_send_async() {
+    let mut response = 0_u32;
+    let slot =
+        assist_send_async(AssistOp::JustReply, 0xDEADBEEF, &mut response)
+            .unwrap();
+
+    wait_for_async_send();
+    assert_eq!(sys_async_status(slot), Some((0, 4)));
+    assert_eq!(response, !0xDEADBEEF);
+}
+
+/// Tests that cancelling an asynchronous send the assistant has already
+/// received throws away its reply, and that we can't send to the assistant
+/// again until that reply has come in.
+fn test_send_async_cancel() {
+    const REPLY_DELAY: u32 = 5;
+    let mut response = 0_u32;
+    let slot =
+        assist_send_async(AssistOp::ReplyLater, REPLY_DELAY, &mut response)
+            .unwrap();
+
+    // The assistant is more important than we are, so it has the message by
+    // now, and cancelling can only abandon the reply.
+    assert_eq!(sys_async_cancel(slot), None);
+
+    let mut other = 0_u32;
+    let rc = assist_send_async(AssistOp::JustReply, 0, &mut other);
+    assert_eq!(rc, Err(ASYNC_PEER_BUSY));
+
+    // Wait out the reply, listening for the send's notification too: only the
+    // timer should go off. After that nothing has been written, and the
+    // assistant is free again.
+    const TIMER_NOTIFICATION: u32 = 1 << 16;
+    sys_set_timer(
+        Some(sys_get_timer().now + u64::from(REPLY_DELAY) * 2),
+        TIMER_NOTIFICATION,
+    );
+    let rm = sys_recv_closed(
+        &mut [],
+        ASYNC_NOTIFICATION | TIMER_NOTIFICATION,
+        TaskId::KERNEL,
+    )
+    .unwrap();
+    assert_eq!(rm.operation, TIMER_NOTIFICATION);
+    assert_eq!(response, 0);
+
+    let slot =
+        assist_send_async(AssistOp::JustReply, 0xDEADBEEF, &mut other).unwrap();
+    wait_for_async_send();
+    assert_eq!(sys_async_status(slot), Some((0, 4)));
+    assert_eq!(other, !0xDEADBEEF);
+}
+
+/// Tests that completed sends hold on to their slots until collected, so that
+/// once every slot is taken, further sends are refused.
+fn test_send_async_queue_full() {
+    let mut responses = [0_u32; ASYNC_SENDS_PER_TASK];
+    let mut slots = [0; ASYNC_SENDS_PER_TASK];
+    for (i, response) in responses.iter_mut().enumerate() {
+        slots[i] =
+            assist_send_async(AssistOp::JustReply, i as u32, response).unwrap();
+        // The assistant replies before we get going again, so the next send
+        // doesn't find it busy.
+        wait_for_async_send();
+    }
+
+    let mut extra = 0_u32;
+    let rc = assist_send_async(AssistOp::JustReply, 0, &mut extra);
+    assert_eq!(rc, Err(ASYNC_QUEUE_FULL));
+
+    for (i, slot) in slots.iter().enumerate() {
+        assert_eq!(sys_async_status(*slot), Some((0, 4)));
+        assert_eq!(responses[i], !(i as u32));
+    }
+}
+
+/// Tests that only one asynchronous send to a given task can be outstanding,
+/// and that it stays pending until the reply comes in.
+fn test_send_async_peer_busy() {
+    const REPLY_DELAY: u32 = 5;
+    let mut response = 0_u32;
+    let slot =
+        assist_send_async(AssistOp::ReplyLater, REPLY_DELAY, &mut response)
+            .unwrap();
+    assert_eq!(sys_async_status(slot), None);
+
+    let mut other = 0_u32;
+    let rc = assist_send_async(AssistOp::JustReply, 0, &mut other);
+    assert_eq!(rc, Err(ASYNC_PEER_BUSY));
+
+    wait_for_async_send();
+    assert_eq!(sys_async_status(slot), Some((0, 4)));
+    assert_eq!(response, REPLY_DELAY);
+}
+
+/// Tests that restarting the assistant while it holds an asynchronous send
+/// completes the send with a dead code, and tells us so.
+fn test_send_async_peer_restart() {
+    // Long enough that the reply would never come during the test.
+    const REPLY_DELAY: u32 = 1000;
+    let mut response = 0_u32;
+    let slot =
+        assist_send_async(AssistOp::ReplyLater, REPLY_DELAY, &mut response)
+            .unwrap();
+    assert_eq!(sys_async_status(slot), None);
+
+    restart_assistant();
+
+    wait_for_async_send();
+    let (rc, len) = sys_async_status(slot).unwrap();
+    assert_eq!(rc & 0xffff_ff00, 0xffff_ff00);
+    assert_eq!(len, 0);
+    assert_eq!(response, 0);
+
+    // The slot is free, and the new assistant answers.
+    let slot =
+        assist_send_async(AssistOp::JustReply, 0xDEADBEEF, &mut response)
+            .unwrap();
+    wait_for_async_send();
+    assert_eq!(sys_async_status(slot), Some((0, 4)));
+    assert_eq!(response, !0xDEADBEEF);
+}
+
+/// Tests that a send with a deadline gives up if the assistant doesn't reply in
+/// time, that our own timer is restored afterwards, and that the assistant's
+/// late reply isn't taken for an answer to a later message.
+fn test_send_timeout() {
+    const ARBITRARY_NOTIFICATION: u32 = 1 << 16;
+
+    let assist = assist_task_id();
+    let start_time = sys_get_timer().now;
+
+    // Set a timer of our own that would fire well after the send gives up.
+    let later = start_time + 1000;
+    sys_set_timer(Some(later), ARBITRARY_NOTIFICATION);
+
+    // The assistant receives this one, but doesn't reply until well after
+    // we've given up.
+    const REPLY_DELAY: u32 = 10;
+    let deadline = start_time + 2;
+    let mut response = 0_u32;
+    let (rc, len) = sys_send_with_deadline(
+        assist,
+        AssistOp::ReplyLater as u16,
+        &REPLY_DELAY.to_le_bytes(),
+        response.as_bytes_mut(),
+        &[],
+        deadline,
+    );
+    assert_eq!(rc, SEND_TIMED_OUT);
+    assert_eq!(len, 0);
+    assert!(sys_get_timer().now >= deadline);
+
+    let timer = sys_get_timer();
+    assert_eq!(timer.deadline, Some(later));
+    assert_eq!(timer.on_dl, ARBITRARY_NOTIFICATION);
+    sys_set_timer(None, 0);
+
+    // Until the assistant replies, we can't send it anything else: we
+    // wouldn't be able to tell which message the reply was for.
+    let challenge = 0xDEADBEEF_u32;
+    let (rc, len) = sys_send(
+        assist,
+        AssistOp::JustReply as u16,
+        &challenge.to_le_bytes(),
+        response.as_bytes_mut(),
+        &[],
+    );
+    assert_eq!(rc, SEND_TIMED_OUT);
+    assert_eq!(len, 0);
+
+    // Once it has, its late reply should have gone nowhere, and the next
+    // message should get a reply of its own.
+    hl::sleep_for(u64::from(REPLY_DELAY) * 2);
+    let (rc, len) = sys_send(
+        assist,
+        AssistOp::JustReply as u16,
+        &challenge.to_le_bytes(),
+        response.as_bytes_mut(),
+        &[],
+    );
+    assert_eq!(rc, 0);
+    assert_eq!(len, 4);
+    assert_eq!(response, !0xDEADBEEF);
+}
+
+/// Tests that timing out on two different peers in turn keeps us from sending
+/// to either until each has replied, so that the first's late reply can't be
+/// taken for an answer to a new message.
+fn test_send_timeout_two_peers() {
+    let assist = assist_task_id();
+    let spinner = SPINNER.get_task_id();
+
+    // Both receive, but don't reply until well after we've given up; the
+    // first takes longer than the second.
+    const FIRST_DELAY: u32 = 20;
+    const SECOND_DELAY: u32 = 10;
+    for (peer, delay) in [(assist, FIRST_DELAY), (spinner, SECOND_DELAY)] {
+        let mut response = 0_u32;
+        let (rc, len) = sys_send_with_deadline(
+            peer,
+            AssistOp::ReplyLater as u16,
+            &delay.to_le_bytes(),
+            response.as_bytes_mut(),
+            &[],
+            sys_get_timer().now + 2,
+        );
+        assert_eq!(rc, SEND_TIMED_OUT);
+        assert_eq!(len, 0);
+    }
+
+    // Neither has replied yet, so we can't send to either of them -- the
+    // second timeout mustn't make us forget about the first.
+    let challenge = 0xDEADBEEF_u32;
+    let mut response = 0_u32;
+    for peer in [assist, spinner] {
+        let (rc, len) = sys_send(
+            peer,
+            AssistOp::JustReply as u16,
+            &challenge.to_le_bytes(),
+            response.as_bytes_mut(),
+            &[],
+        );
+        assert_eq!(rc, SEND_TIMED_OUT);
+        assert_eq!(len, 0);
+    }
+
+    // Once both have replied, the late replies should have gone nowhere, and
+    // each peer should answer a new message for itself.
+    hl::sleep_for(u64::from(FIRST_DELAY) * 2);
+    for peer in [assist, spinner] {
+        let (rc, len) = sys_send(
+            peer,
+            AssistOp::JustReply as u16,
+            &challenge.to_le_bytes(),
+            response.as_bytes_mut(),
+            &[],
+        );
+        assert_eq!(rc, 0);
+        assert_eq!(len, 4);
+        assert_eq!(response, !0xDEADBEEF);
+    }
+}
+
 /// Tests that we can receive a message from the assistant and reply.
 fn test_recv_reply() {
     let assist = assist_task_id();
@@ -170,6 +438,95 @@ fn test_recv_reply() {
     assert_eq!(response, reply_token);
 }
 
+/// Tests that, while we owe the more important assistant a reply, we run at
+/// its priority if the kernel has priority inheritance -- so that tasks in
+/// between can't starve it by starving us -- and at our own otherwise.
+fn test_priority_inheritance() {
+    let me = usize::from(SUITE.get_task_index());
+    let assist = assist_task_id();
+    let (_, base) = kipc::read_task_status_and_priority(me);
+    let (_, assist_p) =
+        kipc::read_task_status_and_priority(ASSIST.get_task_index().into());
+    // Otherwise, there's nothing to inherit.
+    assert!(assist_p.is_more_important_than(base));
+
+    // Ask the assistant to send us a message, and receive it, leaving the
+    // assistant blocked waiting for our reply.
+    let mut response = 0_u32;
+    let (rc, _len) = sys_send(
+        assist,
+        AssistOp::SendBack as u16,
+        &0u32.to_le_bytes(),
+        response.as_bytes_mut(),
+        &[],
+    );
+    assert_eq!(rc, 0);
+    let rm = sys_recv_open(response.as_bytes_mut(), 0);
+    assert_eq!(rm.sender, assist);
+
+    let (state, during) = kipc::read_task_status_and_priority(me);
+    assert_eq!(state, TaskState::Healthy(SchedState::Runnable));
+    if cfg!(feature = "priority-inheritance") {
+        assert_eq!(during, assist_p);
+    } else {
+        assert_eq!(during, base);
+    }
+
+    #[cfg(feature = "priority-inheritance")]
+    let spin_end = start_spinner(base, assist_p);
+
+    // Once we've replied, we're back to our own priority either way.
+    sys_reply(assist, 0, &0u32.to_le_bytes());
+    let (_, after) = kipc::read_task_status_and_priority(me);
+    assert_eq!(after, base);
+
+    // We only get here once the spinner is done, but the assistant should
+    // have had its reply well before then.
+    #[cfg(feature = "priority-inheritance")]
+    {
+        let mut replied_at = 0_u32;
+        let (rc, _len) = sys_send(
+            assist,
+            AssistOp::LastReplyTime as u16,
+            &0u32.to_le_bytes(),
+            replied_at.as_bytes_mut(),
+            &[],
+        );
+        assert_eq!(rc, 0);
+        assert!(replied_at < spin_end as u32);
+    }
+}
+
+/// How long `test_priority_inheritance` has the spinner hog the CPU for.
+#[cfg(feature = "priority-inheritance")]
+const SPIN_TICKS: u32 = 50;
+
+/// Sets the spinner -- which is less important than the assistant, but more
+/// important than us -- hogging the CPU, and then waits a little, as a server
+/// might for its hardware before it can reply. Without inheritance, we'd not
+/// be back until the spinner was done, and nor would the assistant, whose
+/// reply we owe. Returns the earliest the spinner can be done, in ticks.
+#[cfg(feature = "priority-inheritance")]
+fn start_spinner(base: Priority, waiter: Priority) -> u64 {
+    let (_, spinner_p) =
+        kipc::read_task_status_and_priority(SPINNER.get_task_index().into());
+    assert!(spinner_p.is_more_important_than(base));
+    assert!(waiter.is_more_important_than(spinner_p));
+
+    let spin_end = sys_get_timer().now + u64::from(SPIN_TICKS);
+    let (rc, _len) = sys_send(
+        SPINNER.get_task_id(),
+        AssistOp::Spin as u16,
+        &SPIN_TICKS.to_le_bytes(),
+        &mut [],
+        &[],
+    );
+    assert_eq!(rc, 0);
+    hl::sleep_for(5);
+    assert!(sys_get_timer().now < spin_end);
+    spin_end
+}
+
 /// Helper routine to send a message to the assistant telling it to fault,
 /// and then verifying that the fault caused a state change into the `Faulted`
 /// state, returning the actual fault info.
@@ -292,6 +649,76 @@ fn test_fault_illinst() {
 /// Tests that division-by-zero results in a DivideByZero fault
 fn test_fault_divzero() {
     assert_eq!(test_fault(AssistOp::DivZero, 0), FaultInfo::DivideByZero);
+
+    // This is a processor fault, so the kernel knows where it happened.
+    let detail = kipc::read_fault_detail(ASSIST.get_task_index().into());
+    assert!(detail.pc().is_some());
+    assert!(detail.message().is_empty());
+    assert!(detail.location().is_empty());
+}
+
+/// Tests that the kernel holds the assistant to the tasks it's allowed to talk
+/// to (just us, according to `app.toml`).
+fn test_fault_send_not_permitted() {
+    let runner = RUNNER.get_task_index().into();
+    assert_eq!(
+        test_fault(AssistOp::SendToTask, runner),
+        FaultInfo::SyscallUsage(UsageError::IpcNotPermitted)
+    );
+}
+
+fn test_fault_post_not_permitted() {
+    let runner = RUNNER.get_task_index().into();
+    assert_eq!(
+        test_fault(AssistOp::PostToTask, runner),
+        FaultInfo::SyscallUsage(UsageError::IpcNotPermitted)
+    );
+}
+
+fn test_fault_unlock_not_held() {
+    assert_eq!(
+        test_fault(AssistOp::ReleaseLock, Lock::test as u32),
+        FaultInfo::SyscallUsage(UsageError::LockNotHeld)
+    );
+}
+
+fn test_fault_badirqstats() {
+    // None of the test images route this to a task.
+    assert_eq!(
+        test_fault(AssistOp::ReadIrqStats, 0xffff),
+        FaultInfo::SyscallUsage(UsageError::NoIrq)
+    );
+}
+
+/// Tests that the assistant's interrupt reaches it, and -- if the kernel keeps
+/// statistics -- that it's counted, along with the time the assistant took to
+/// wake for it and to re-enable it.
+fn test_irq_stats() {
+    let before = kipc::read_irq_stats(ASSIST_IRQ);
+    assert_eq!(before.is_some(), cfg!(feature = "irq-stats"));
+
+    // Drain any previously posted bits.
+    read_assist_notifications();
+
+    // The runner raises the interrupt, and the assistant, being more important
+    // than us, has handled it and re-enabled it by the time we're back.
+    runner_software_irq(
+        ASSIST.get_task_index().into(),
+        ASSIST_IRQ_NOTIFICATION,
+    );
+    assert_eq!(read_assist_notifications(), ASSIST_IRQ_NOTIFICATION);
+
+    if let (Some(before), Some(after)) =
+        (before, kipc::read_irq_stats(ASSIST_IRQ))
+    {
+        assert_eq!(after.count, before.count + 1);
+        // The interrupt fired while the runner was running, so the assistant
+        // had to be woken for it.
+        assert!(after.total_wake > before.total_wake);
+        assert!(after.max_wake > 0);
+        assert!(after.total_masked > before.total_masked);
+        assert!(after.max_masked > 0);
+    }
 }
 
 fn test_fault_badtaskop(op: AssistOp, id: usize) {
@@ -374,6 +801,19 @@ fn test_panic() {
             original_state: SchedState::Runnable,
         },
     );
+
+    // The kernel should have kept the message for us, along with where it
+    // came from.
+    let detail = kipc::read_fault_detail(ASSIST.get_task_index().into());
+    assert!(detail
+        .message()
+        .starts_with(b"panicked at 'wow this blew up"));
+    let location = b"test-assist/src/main.rs:";
+    assert!(detail
+        .location()
+        .windows(location.len())
+        .any(|w| w == location));
+    assert_eq!(detail.pc(), None);
     restart_assistant();
 }
 
@@ -483,6 +923,59 @@ fn test_restart_taskgen() {
     );
 }
 
+/// Tests that starting a stopped task doesn't restart it, and that starting a
+/// task that isn't stopped does nothing.
+fn test_start_task() {
+    let index = ASSIST.get_task_index().into();
+    kipc::restart_task(index, false);
+    assert_eq!(
+        kipc::read_task_status(index),
+        TaskState::Healthy(SchedState::Stopped)
+    );
+    let assist = assist_task_id();
+
+    // Only the supervisor may start tasks, so we ask the runner.
+    runner_start_task(index);
+    assert_eq!(assist_task_id(), assist);
+
+    let challenge = 0xdead_beef_u32;
+    let mut response = 0_u32;
+    let (rc, len) = sys_send(
+        assist,
+        AssistOp::JustReply as u16,
+        &challenge.to_le_bytes(),
+        response.as_bytes_mut(),
+        &[],
+    );
+    assert_eq!(rc, 0);
+    assert_eq!(len, 4);
+    assert_eq!(response, !challenge);
+
+    runner_start_task(index);
+    assert_eq!(assist_task_id(), assist);
+    assert_eq!(
+        kipc::read_task_status(index),
+        TaskState::Healthy(SchedState::InRecv(None))
+    );
+}
+
+/// Tests that the kernel keeps track of whether a task has been ready to serve
+/// -- made an open RECV -- since it was started, and tells the supervisor when
+/// it first is.
+fn test_task_ready() {
+    let index = ASSIST.get_task_index().into();
+    kipc::restart_task(index, false);
+    assert!(!kipc::read_task_ready(index));
+    read_runner_notifications();
+
+    // The assistant is more important than us, so once it's started, it gets
+    // as far as its open RECV before we're back.
+    runner_start_task(index);
+    assert!(kipc::read_task_ready(index));
+    // The expected bitmask here is set in app.toml.
+    assert_eq!(read_runner_notifications(), 1);
+}
+
 /// Tests that the basic `borrow_info` mechanics work by soliciting a
 /// stereotypical loan from the assistant.
 fn test_borrow_info() {
@@ -704,6 +1197,30 @@ fn test_timer_notify() {
     assert!(sys_get_timer().now >= deadline);
 }
 
+/// Tests that we can set a timer in microseconds, between ticks, and that it
+/// doesn't fire early.
+fn test_timer_notify_micros() {
+    const ARBITRARY_NOTIFICATION: u32 = 1 << 16;
+
+    let start = sys_get_timer();
+    // Microseconds must agree with ticks.
+    assert_eq!(start.now_micros / 1000, start.now);
+
+    // A deadline that's probably not on a tick.
+    let deadline = start.now_micros + 1_500;
+    sys_set_timer_micros(Some(deadline), ARBITRARY_NOTIFICATION);
+    assert_eq!(sys_get_timer().deadline_micros, Some(deadline));
+
+    let rm = sys_recv_closed(&mut [], ARBITRARY_NOTIFICATION, TaskId::KERNEL)
+        .unwrap();
+    assert_eq!(rm.sender, TaskId::KERNEL);
+    assert_eq!(rm.operation, ARBITRARY_NOTIFICATION);
+
+    let after = sys_get_timer();
+    assert!(after.now_micros >= deadline);
+    assert_eq!(after.deadline, None);
+}
+
 /// Tests that we can set a timer in the past and get immediate notification.
 fn test_timer_notify_past() {
     const ARBITRARY_NOTIFICATION: u32 = 1 << 16;
@@ -786,6 +1303,59 @@ fn test_floating_point_fault() {
     test_fault(AssistOp::PiAndDie, 0);
 }
 
+/// Tests that the kernel can tell how much stack we've used.
+fn test_stack_usage() {
+    let me = usize::from(SUITE.get_task_index());
+    let before = kipc::read_stack_usage(me).unwrap();
+    // We're running, so we must have used some.
+    assert!(before > 0);
+
+    // Having put a buffer on the stack and scribbled over all of it, we must
+    // have used at least that much -- and no less than before, as this is a
+    // peak.
+    #[inline(never)]
+    fn scribble(me: usize) -> u32 {
+        let mut buffer = [0u8; 256];
+        for b in buffer.iter_mut() {
+            unsafe { core::ptr::write_volatile(b, 0xa5) };
+        }
+        kipc::read_stack_usage(me).unwrap()
+    }
+    let during = scribble(me);
+    assert!(during >= 256);
+    assert!(during >= before);
+}
+
+/// Tests that the kernel accounts for the time we spend running.
+fn test_cpu_usage() {
+    let me = usize::from(SUITE.get_task_index());
+    let before = kipc::read_cpu_usage(me);
+    // We've been switched to at least once, to get here at all.
+    assert!(before.switches > 0);
+
+    // Spinning for a while must be charged to us, and yielding to the assistant
+    // and back must count as being switched to again.
+    let mut x = 0u32;
+    for i in 0..10_000 {
+        x = unsafe { core::ptr::read_volatile(&i) };
+    }
+    assert_eq!(x, 9_999);
+    let assist = assist_task_id();
+    let mut response = 0u32;
+    let (rc, _) = sys_send(
+        assist,
+        AssistOp::JustReply as u16,
+        &0u32.to_le_bytes(),
+        response.as_bytes_mut(),
+        &[],
+    );
+    assert_eq!(rc, 0);
+
+    let after = kipc::read_cpu_usage(me);
+    assert!(after.time > before.time);
+    assert!(after.switches > before.switches);
+}
+
 fn test_task_status() {
     let mut id: usize = 0;
     let assist = assist_task_id();
@@ -916,6 +1486,79 @@ fn test_post() {
     assert_eq!(response, ARBITRARY_MASK);
 }
 
+/// Tests that a lock held by us makes the assistant wait, that it's handed over
+/// when we release it, and that it's released -- with a warning to the next
+/// owner -- when the assistant is restarted while holding it.
+fn test_lock() {
+    let lock = Lock::test as usize;
+    assert_eq!(sys_lock(lock), LockAcquired::Clean);
+
+    // The assistant is more important than us, so it gets as far as waiting
+    // for the lock before we get control back.
+    let assist = assist_task_id();
+    let mut response = 0_u32;
+    let (rc, _) = sys_send(
+        assist,
+        AssistOp::TakeLock as u16,
+        &(lock as u32).to_le_bytes(),
+        response.as_bytes_mut(),
+        &[],
+    );
+    assert_eq!(rc, 0);
+    let status = kipc::read_task_status(ASSIST.get_task_index().into());
+    assert_eq!(status, TaskState::Healthy(SchedState::InLock(lock as u32)));
+
+    // Releasing the lock hands it straight to the assistant.
+    sys_unlock(lock);
+    assert_eq!(sys_try_lock(lock), None);
+
+    restart_assistant();
+    assert_eq!(sys_lock(lock), LockAcquired::OwnerDied);
+    sys_unlock(lock);
+    assert_eq!(sys_try_lock(lock), Some(LockAcquired::Clean));
+    sys_unlock(lock);
+}
+
+/// Word at the start of the `test` shared region, which we can write and the
+/// assistant can only read.
+#[link_section = ".shared.test"]
+static mut SHARED: MaybeUninit<u32> = MaybeUninit::uninit();
+
+/// Tests that the assistant sees what we write to a shared region.
+fn test_shared_region() {
+    let assist = assist_task_id();
+    for &value in &[0x1de_c0de_u32, 0xfeed_f00d] {
+        unsafe {
+            SHARED.as_mut_ptr().write_volatile(value);
+        }
+
+        let mut response = 0_u32;
+        let (rc, len) = sys_send(
+            assist,
+            AssistOp::ReadShared as u16,
+            &0_u32.to_le_bytes(),
+            response.as_bytes_mut(),
+            &[],
+        );
+        assert_eq!(rc, 0);
+        assert_eq!(len, 4);
+        assert_eq!(response, value);
+    }
+}
+
+/// Tests that a task can't write to a shared region it may only read. Both of
+/// our words are alone in the region, so they're at the same address.
+fn test_fault_shared_readonly() {
+    let fault = test_fault(AssistOp::WriteShared, 0);
+    assert_eq!(
+        fault,
+        FaultInfo::MemoryAccess {
+            address: Some(unsafe { SHARED.as_ptr() } as u32),
+            source: FaultSource::User,
+        }
+    );
+}
+
 ///////////////////////////////////////////////////////////////////////////////
 // Frameworky bits follow
 
@@ -924,6 +1567,9 @@ task_slot!(ASSIST, assist);
 // Our own identity
 task_slot!(SUITE, suite);
 task_slot!(RUNNER, runner);
+// A second assistant, for tests that need two peers, and to be in the way of
+// the first in test_priority_inheritance.
+task_slot!(SPINNER, spinner);
 
 /// Gets the current expected `TaskId` for the assistant.
 fn assist_task_id() -> TaskId {
@@ -947,6 +1593,74 @@ fn read_runner_notifications() -> u32 {
     response
 }
 
+/// Asks the runner, as supervisor, to raise the interrupts routed to task
+/// `index` with notifications in `mask`.
+fn runner_software_irq(index: usize, mask: u32) {
+    let runner = RUNNER.get_task_id();
+    let op = RunnerOp::SoftwareIrq as u16;
+    let msg = [index as u32, mask];
+    let (rc, len) = sys_send(runner, op, msg.as_bytes(), &mut [], &[]);
+    assert_eq!(rc, 0);
+    assert_eq!(len, 0);
+}
+
+/// Reads (and clears) the notifications the assistant has been posted.
+fn read_assist_notifications() -> u32 {
+    let mut response = 0_u32;
+    let (rc, len) = sys_send(
+        assist_task_id(),
+        AssistOp::ReadNotifications as u16,
+        &0_u32.to_le_bytes(),
+        response.as_bytes_mut(),
+        &[],
+    );
+    assert_eq!(rc, 0);
+    assert_eq!(len, 4);
+    response
+}
+
+/// Notification the asynchronous send tests ask to be posted on completion.
+const ASYNC_NOTIFICATION: u32 = 1 << 17;
+
+/// Sends `arg` to the assistant as operation `op` with `sys_send_async`, with
+/// the reply going into `response`.
+///
+/// The tests calling this all wait for, collect or cancel the send before
+/// `response` goes out of scope, which is what makes it safe here.
+fn assist_send_async(
+    op: AssistOp,
+    arg: u32,
+    response: &mut u32,
+) -> Result<usize, u32> {
+    unsafe {
+        sys_send_async(
+            assist_task_id(),
+            op as u16,
+            &arg.to_le_bytes(),
+            response.as_bytes_mut(),
+            ASYNC_NOTIFICATION,
+        )
+    }
+}
+
+/// Waits for an asynchronous send made with `assist_send_async` to complete.
+fn wait_for_async_send() {
+    let rm =
+        sys_recv_closed(&mut [], ASYNC_NOTIFICATION, TaskId::KERNEL).unwrap();
+    assert_eq!(rm.sender, TaskId::KERNEL);
+    assert_eq!(rm.operation, ASYNC_NOTIFICATION);
+}
+
+/// Asks the runner, as supervisor, to start task `index`.
+fn runner_start_task(index: usize) {
+    let runner = RUNNER.get_task_id();
+    let op = RunnerOp::StartTask as u16;
+    let (rc, len) =
+        sys_send(runner, op, &(index as u32).to_le_bytes(), &mut [], &[]);
+    assert_eq!(rc, 0);
+    assert_eq!(len, 0);
+}
+
 /// Actual entry point.
 #[export_name = "main"]
 fn main() -> ! {